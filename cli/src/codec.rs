@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use stream_encoder::{gstreamer::Caps, Container, VideoSettings};
+
+/// The codecs the CLI can encode with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    /// H.264 with `x264enc`
+    H264,
+    /// H.265 with `x265enc`
+    H265,
+    /// VP8 with `vp8enc`
+    Vp8,
+    /// VP9 with `vp9enc`
+    Vp9,
+}
+
+impl Codec {
+    /// Switches the encoder and encoded caps of the settings to this codec
+    pub fn apply(self, settings: &mut VideoSettings) {
+        let (encoder, caps) = match self {
+            Codec::H264 => ("x264enc", "video/x-h264"),
+            Codec::H265 => ("x265enc", "video/x-h265"),
+            Codec::Vp8 => ("vp8enc", "video/x-vp8"),
+            Codec::Vp9 => ("vp9enc", "video/x-vp9"),
+        };
+
+        settings.encoder = encoder.to_owned();
+        settings.caps = Caps::builder(caps).build();
+    }
+}
+
+/// Picks the muxer from the extension of the output file
+pub fn apply_container(settings: &mut VideoSettings, output: &Path) -> anyhow::Result<()> {
+    let extension = output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "mp4" => settings.muxer = "mp4mux".to_owned(),
+        "mov" => settings.muxer = "qtmux".to_owned(),
+        "webm" => settings.muxer = "webmmux".to_owned(),
+        "mkv" => settings.container = Container::Matroska,
+        "ts" => settings.container = Container::MpegTs,
+        "y4m" => settings.container = Container::Y4m,
+        _ => {
+            return Err(anyhow::Error::msg(format!(
+                "Can't tell the container of {}, use .mp4, .mov, .webm, .mkv, .ts or .y4m",
+                output.display()
+            )))
+        }
+    }
+
+    Ok(())
+}
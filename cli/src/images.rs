@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use image::GenericImageView;
+use indicatif::{ProgressBar, ProgressStyle};
+use stream_encoder::{natural_cmp, with_encoder, ScalingMode, VideoSettings};
+
+use crate::codec::{apply_container, Codec};
+
+/// The extensions of the files picked up from a directory
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "tga", "webp"];
+
+#[derive(Args)]
+pub struct ImagesArgs {
+    /// A directory of images or a glob like `frames/*.png`
+    input: String,
+    /// The framerate of the video
+    #[arg(long, default_value_t = 30)]
+    fps: u64,
+    /// The codec to encode with
+    #[arg(long, value_enum, default_value_t = Codec::H264)]
+    codec: Codec,
+    /// Where to write the video, the container is picked from the extension
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Encodes the images in natural order, so `frame_2.png` comes before `frame_10.png`
+///
+/// The video is the size of the first image, other sizes are letterboxed.
+pub fn run(args: ImagesArgs) -> anyhow::Result<()> {
+    let paths = collect_images(&args.input)?;
+    let first = match paths.first() {
+        Some(first) => image::open(first)?,
+        None => {
+            return Err(anyhow::Error::msg(format!(
+                "No images found in {}",
+                args.input
+            )))
+        }
+    };
+
+    let mut settings = VideoSettings::new(args.fps, first.width(), first.height());
+    settings.scaling = ScalingMode::Letterbox;
+    args.codec.apply(&mut settings);
+    apply_container(&mut settings, &args.output)?;
+
+    let progress = ProgressBar::new(paths.len() as u64).with_style(ProgressStyle::with_template(
+        "{bar:40} {pos}/{len} frames, {eta} left",
+    )?);
+
+    let output = args.output.to_string_lossy();
+    let stats = with_encoder(&output, settings, |sink| {
+        for path in &paths {
+            let frame = image::open(path)?;
+            sink.push_dynamic_frame(&frame)?;
+            progress.inc(1);
+        }
+        Ok(())
+    })?;
+    progress.finish_and_clear();
+
+    println!(
+        "Encoded {} frames into {} ({} bytes, {:.1}x realtime)",
+        stats.frames, output, stats.file_size, stats.realtime_factor
+    );
+
+    Ok(())
+}
+
+/// The images in a directory, or the files matching a glob, sorted naturally
+fn collect_images(input: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = if std::path::Path::new(input).is_dir() {
+        std::fs::read_dir(input)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| {
+                        IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                    })
+            })
+            .collect::<Vec<_>>()
+    } else {
+        glob::glob(input)?
+            .flatten()
+            .filter(|path| path.is_file())
+            .collect()
+    };
+
+    paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    Ok(paths)
+}
@@ -0,0 +1,38 @@
+use clap::{Parser, Subcommand};
+
+mod codec;
+mod images;
+mod probe;
+mod raw;
+mod transcode;
+
+/// Encode videos with GStreamer without writing any Rust
+#[derive(Parser)]
+#[command(name = "stream-encoder", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encode a directory or glob of images into a video, in natural order
+    Images(images::ImagesArgs),
+    /// Decode a video and encode it again with another codec or quality, dropping its audio
+    Transcode(transcode::TranscodeArgs),
+    /// Print the streams and properties of a media file
+    Probe(probe::ProbeArgs),
+    /// Encode raw frames read from a file or stdin, `-`
+    Raw(raw::RawArgs),
+}
+
+fn main() -> anyhow::Result<()> {
+    stream_encoder::init_encoder();
+
+    match Cli::parse().command {
+        Command::Images(args) => images::run(args),
+        Command::Transcode(args) => transcode::run(args),
+        Command::Probe(args) => probe::run(args),
+        Command::Raw(args) => raw::run(args),
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use serde::Serialize;
+use stream_encoder::{probe, MediaInfo};
+
+#[derive(Args)]
+pub struct ProbeArgs {
+    /// The media file to look at
+    input: PathBuf,
+    /// Print the information as JSON instead of text
+    #[arg(long)]
+    json: bool,
+}
+
+/// What [`probe`] found, in a shape that can be written as JSON
+#[derive(Serialize)]
+struct ProbeReport {
+    duration_secs: Option<f64>,
+    seekable: bool,
+    container: Option<String>,
+    video_streams: Vec<VideoReport>,
+    audio_streams: Vec<AudioReport>,
+}
+
+#[derive(Serialize)]
+struct VideoReport {
+    codec: Option<String>,
+    width: u32,
+    height: u32,
+    framerate: String,
+    bitrate: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct AudioReport {
+    codec: Option<String>,
+    channels: u32,
+    sample_rate: u32,
+    bitrate: Option<u32>,
+}
+
+impl From<MediaInfo> for ProbeReport {
+    fn from(info: MediaInfo) -> Self {
+        ProbeReport {
+            duration_secs: info.duration.map(|duration| duration.as_secs_f64()),
+            seekable: info.seekable,
+            container: info.container,
+            video_streams: info
+                .video_streams
+                .into_iter()
+                .map(|stream| VideoReport {
+                    codec: stream.codec,
+                    width: stream.width,
+                    height: stream.height,
+                    framerate: format!("{}/{}", stream.framerate.0, stream.framerate.1),
+                    bitrate: stream.bitrate,
+                })
+                .collect(),
+            audio_streams: info
+                .audio_streams
+                .into_iter()
+                .map(|stream| AudioReport {
+                    codec: stream.codec,
+                    channels: stream.channels,
+                    sample_rate: stream.sample_rate,
+                    bitrate: stream.bitrate,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Prints the streams and properties of a media file
+pub fn run(args: ProbeArgs) -> anyhow::Result<()> {
+    let report = ProbeReport::from(probe(&args.input)?);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("{}", args.input.display());
+    if let Some(container) = &report.container {
+        println!("  container: {container}");
+    }
+    if let Some(duration) = report.duration_secs {
+        println!("  duration: {duration:.3}s");
+    }
+    println!("  seekable: {}", report.seekable);
+
+    for (i, stream) in report.video_streams.iter().enumerate() {
+        println!(
+            "  video {i}: {} {}x{} at {} fps",
+            stream.codec.as_deref().unwrap_or("unknown codec"),
+            stream.width,
+            stream.height,
+            stream.framerate
+        );
+    }
+    for (i, stream) in report.audio_streams.iter().enumerate() {
+        println!(
+            "  audio {i}: {} {} channels at {} Hz",
+            stream.codec.as_deref().unwrap_or("unknown codec"),
+            stream.channels,
+            stream.sample_rate
+        );
+    }
+
+    Ok(())
+}
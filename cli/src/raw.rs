@@ -0,0 +1,144 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use clap::{Args, ValueEnum};
+use stream_encoder::{gstreamer::video::VideoFormat, with_encoder, VideoSettings};
+
+use crate::codec::{apply_container, Codec};
+
+/// The pixel formats raw frames can be read in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RawFormat {
+    Rgba,
+    Bgra,
+    Rgbx,
+    Bgrx,
+    Rgb,
+    Bgr,
+    Gray8,
+    I420,
+    Nv12,
+    Yuy2,
+}
+
+impl RawFormat {
+    fn video_format(self) -> VideoFormat {
+        match self {
+            RawFormat::Rgba => VideoFormat::Rgba,
+            RawFormat::Bgra => VideoFormat::Bgra,
+            RawFormat::Rgbx => VideoFormat::Rgbx,
+            RawFormat::Bgrx => VideoFormat::Bgrx,
+            RawFormat::Rgb => VideoFormat::Rgb,
+            RawFormat::Bgr => VideoFormat::Bgr,
+            RawFormat::Gray8 => VideoFormat::Gray8,
+            RawFormat::I420 => VideoFormat::I420,
+            RawFormat::Nv12 => VideoFormat::Nv12,
+            RawFormat::Yuy2 => VideoFormat::Yuy2,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct RawArgs {
+    /// The file to read frames from, `-` for stdin
+    input: PathBuf,
+    /// The pixel format of the frames
+    #[arg(long, value_enum)]
+    format: RawFormat,
+    /// The size of the frames, e.g. `1920x1080`
+    #[arg(long, value_parser = parse_size)]
+    size: (u32, u32),
+    /// The framerate of the video
+    #[arg(long, default_value_t = 30)]
+    fps: u64,
+    /// The codec to encode with
+    #[arg(long, value_enum, default_value_t = Codec::H264)]
+    codec: Codec,
+    /// Where to write the video, the container is picked from the extension
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Encodes tightly packed frames read one after the other till the input ends,
+/// the same way frames are piped into `ffmpeg -f rawvideo`
+///
+/// Each frame is its planes one after the other with no padding between rows,
+/// they're laid out the way GStreamer expects before being encoded.
+pub fn run(args: RawArgs) -> anyhow::Result<()> {
+    let mut input: Box<dyn Read> = if args.input.as_os_str() == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(&args.input)?)
+    };
+
+    let (width, height) = args.size;
+    let mut settings = VideoSettings::new(args.fps, width, height);
+    settings.format = args.format.video_format();
+    args.codec.apply(&mut settings);
+    apply_container(&mut settings, &args.output)?;
+
+    let output = args.output.to_string_lossy();
+    let stats = with_encoder(&output, settings, |sink| {
+        let plane_sizes = sink.plane_sizes();
+        let mut frame = vec![0; plane_sizes.iter().sum()];
+        loop {
+            match read_frame(&mut input, &mut frame)? {
+                0 => break,
+                read if read < frame.len() => {
+                    println!("Ignoring the last {read} bytes, they aren't a whole frame");
+                    break;
+                }
+                _ => sink.push_planes(&split_planes(&frame, &plane_sizes))?,
+            }
+        }
+        Ok(())
+    })?;
+
+    println!(
+        "Encoded {} frames into {} ({} bytes, {:.1}x realtime)",
+        stats.frames, output, stats.file_size, stats.realtime_factor
+    );
+
+    Ok(())
+}
+
+/// Fills `frame` from the input, returning fewer bytes than its size only at the end of the input
+fn read_frame(input: &mut impl Read, frame: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < frame.len() {
+        match input.read(&mut frame[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Splits a tightly packed frame into its planes
+fn split_planes<'a>(frame: &'a [u8], plane_sizes: &[usize]) -> Vec<&'a [u8]> {
+    let mut rest = frame;
+    plane_sizes
+        .iter()
+        .map(|size| {
+            let (plane, tail) = rest.split_at(*size);
+            rest = tail;
+            plane
+        })
+        .collect()
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let (width, height) = size
+        .split_once('x')
+        .ok_or_else(|| format!("{size} isn't a size like 1920x1080"))?;
+    let parse = |n: &str| {
+        n.parse::<u32>()
+            .map_err(|_| format!("{size} isn't a size like 1920x1080"))
+    };
+    Ok((parse(width)?, parse(height)?))
+}
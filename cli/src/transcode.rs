@@ -0,0 +1,101 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use stream_encoder::{
+    probe, EncodeJob, EncodeQueue, FrameSource, JobStatus, RateControl, VideoSettings,
+};
+
+use crate::codec::{apply_container, Codec};
+
+/// The framerate used for files with a variable framerate
+const FALLBACK_FPS: u64 = 30;
+
+#[derive(Args)]
+pub struct TranscodeArgs {
+    /// The video to read
+    input: PathBuf,
+    /// Where to write the video, the container is picked from the extension
+    output: PathBuf,
+    /// The codec to encode with
+    #[arg(long, value_enum, default_value_t = Codec::H264)]
+    codec: Codec,
+    /// Encode at a constant quality from 0 to 51, lower is better
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=51))]
+    crf: Option<u8>,
+}
+
+/// Decodes the input and encodes it again with the codec, keeping its size and framerate
+///
+/// Only the first video stream is transcoded, audio is dropped.
+pub fn run(args: TranscodeArgs) -> anyhow::Result<()> {
+    let info = probe(&args.input)?;
+    let stream = info.video_streams.first().ok_or_else(|| {
+        anyhow::Error::msg(format!("{} has no video stream", args.input.display()))
+    })?;
+
+    let fps = match stream.framerate {
+        (numer, denom) if numer > 0 && denom > 0 => (numer as f64 / denom as f64).round() as u64,
+        _ => FALLBACK_FPS,
+    };
+
+    if !info.audio_streams.is_empty() {
+        println!(
+            "{} has audio, which isn't transcoded and won't be in the output",
+            args.input.display()
+        );
+    }
+
+    let mut settings = VideoSettings::new(fps, stream.width, stream.height);
+    args.codec.apply(&mut settings);
+    apply_container(&mut settings, &args.output)?;
+    if let Some(crf) = args.crf {
+        settings.rate_control = RateControl::Crf(crf);
+    }
+
+    let output = args.output.to_string_lossy();
+    let queue = EncodeQueue::new(1);
+    let id = queue.submit(EncodeJob::new(
+        &output,
+        settings,
+        FrameSource::File(args.input.clone()),
+    ));
+
+    let progress = ProgressBar::new(0).with_style(ProgressStyle::with_template(
+        "{bar:40} {pos}/{len} frames, {eta} left",
+    )?);
+
+    let status = loop {
+        match queue.status(id) {
+            Some(JobStatus::Running {
+                frames,
+                total_frames,
+            }) => {
+                progress.set_length(total_frames.unwrap_or(0).max(frames));
+                progress.set_position(frames);
+            }
+            Some(status) if status.is_done() => break status,
+            _ if !queue.is_running() => {
+                progress.finish_and_clear();
+                return Err(anyhow::Error::msg(
+                    "The encode worker stopped before finishing the transcode",
+                ));
+            }
+            _ => {}
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    progress.finish_and_clear();
+
+    match status {
+        JobStatus::Finished(stats) => {
+            println!(
+                "Transcoded {} frames into {} ({} bytes, {:.1}x realtime)",
+                stats.frames, output, stats.file_size, stats.realtime_factor
+            );
+            Ok(())
+        }
+        JobStatus::Failed(e) => Err(anyhow::Error::msg(e)),
+        _ => Err(anyhow::Error::msg("The transcode was cancelled")),
+    }
+}
@@ -0,0 +1,337 @@
+use std::{path::PathBuf, time::Duration};
+
+use gst::{prelude::*, ClockTime, Pipeline};
+use gstreamer as gst;
+
+use crate::{pipeline::muxer_factory, Container, OutputTarget, VideoSettings};
+
+/// Where audio recorded next to the video comes from
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioSource {
+    /// What the computer is playing, from the monitor of the default output with `pulsesrc`
+    /// on Linux and a loopback `wasapisrc` on Windows
+    ///
+    /// Not supported on macOS, which can't record its own output without a virtual device.
+    SystemLoopback,
+    /// A microphone or other input, read by `pulsesrc` on Linux, `wasapisrc` on Windows
+    /// and `osxaudiosrc` on macOS
+    Microphone {
+        /// The device to open, a PulseAudio source name on Linux, a device ID on Windows
+        /// and a device index on macOS, `None` opens the default input
+        device: Option<String>,
+    },
+    /// An audio file like a WAV or FLAC, decoded by `decodebin`, e.g. music under a render
+    File {
+        path: PathBuf,
+        /// Trims the file or pads it with silence to this length, usually the length of the video
+        ///
+        /// When `None` the file plays to its end, or till the video ends if that's sooner.
+        length: Option<Duration>,
+    },
+}
+
+/// An audio source and how loud it is in the mix, see [`VideoSettings::audio`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioInput {
+    pub source: AudioSource,
+    /// What the source's samples are multiplied by, `1.0` leaves them as they are
+    pub volume: f64,
+}
+
+impl AudioInput {
+    pub fn new(source: AudioSource) -> Self {
+        AudioInput {
+            source,
+            volume: 1.0,
+        }
+    }
+
+    pub fn volume(mut self, volume: f64) -> Self {
+        self.volume = volume;
+        self
+    }
+}
+
+impl From<AudioSource> for AudioInput {
+    fn from(source: AudioSource) -> Self {
+        AudioInput::new(source)
+    }
+}
+
+/// A filter run on the mixed audio before it's encoded, see [`VideoSettings::audio_filters`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioFilter {
+    /// EBU R128 loudness normalization with `audioloudnorm` from gst-plugins-rs,
+    /// so recordings don't need a pass afterwards to fix their levels
+    LoudnessNormalize {
+        /// The integrated loudness to reach in LUFS, EBU R128 uses -23
+        target_lufs: f64,
+        /// The highest true peak allowed in dBTP
+        max_true_peak: f64,
+    },
+    /// Applies the ReplayGain tags of the sources with `rgvolume`, for audio files that have them
+    ReplayGain {
+        /// Extra gain in dB applied on top of the tags
+        pre_amp: f64,
+    },
+    /// Multiplies every sample by a factor with `audioamplify`, clipping anything that overflows
+    Amplify(f64),
+}
+
+impl AudioFilter {
+    /// EBU R128 loudness normalization to -23 LUFS with peaks kept under -1 dBTP
+    pub fn ebu_r128() -> Self {
+        AudioFilter::LoudnessNormalize {
+            target_lufs: -23.0,
+            max_true_peak: -1.0,
+        }
+    }
+
+    fn make_element(self) -> anyhow::Result<gst::Element> {
+        match self {
+            AudioFilter::LoudnessNormalize {
+                target_lufs,
+                max_true_peak,
+            } => {
+                let filter = gst::ElementFactory::make("audioloudnorm", None)?;
+                filter.set_property("loudness-target", target_lufs);
+                filter.set_property("max-true-peak", max_true_peak);
+                Ok(filter)
+            }
+            AudioFilter::ReplayGain { pre_amp } => {
+                let filter = gst::ElementFactory::make("rgvolume", None)?;
+                filter.set_property("pre-amp", pre_amp);
+                Ok(filter)
+            }
+            AudioFilter::Amplify(amplification) => {
+                let filter = gst::ElementFactory::make("audioamplify", None)?;
+                filter.set_property("amplification", amplification as f32);
+                Ok(filter)
+            }
+        }
+    }
+}
+
+/// Mixes the audio inputs of the settings into one track and links it to the muxer
+///
+/// Fails when an element is missing, like `avenc_aac` without gst-libav.<br>
+/// Live audio sources never end on their own, so they're ended when the video reaches the encoder's
+/// end of the stream, or `video_pad`'s when there's no encoder.
+pub(crate) fn link_audio(
+    pipeline: &Pipeline,
+    video_settings: &VideoSettings,
+    video_pad: &gst::Pad,
+) -> anyhow::Result<()> {
+    let muxer = match (&video_settings.output, &video_settings.container) {
+        (
+            OutputTarget::File | OutputTarget::Custom(_),
+            Container::Video | Container::Matroska | Container::MpegTs,
+        ) => pipeline.by_name("muxer").unwrap(),
+        _ => {
+            println!("Audio can only be muxed into a single video file, ignoring it");
+            return Ok(());
+        }
+    };
+
+    let mixer = gst::ElementFactory::make("audiomixer", Some("audio mixer"))?;
+    let mut tail = vec![mixer.clone()];
+    // Filters can need other formats and rates, like the 192kHz audioloudnorm works at
+    for filter in &video_settings.audio_filters {
+        tail.push(gst::ElementFactory::make("audioconvert", None)?);
+        tail.push(gst::ElementFactory::make("audioresample", None)?);
+        tail.push(filter.make_element()?);
+    }
+    tail.extend([
+        gst::ElementFactory::make("audioconvert", None)?,
+        gst::ElementFactory::make("audioresample", None)?,
+        make_audio_encoder(video_settings)?,
+        gst::ElementFactory::make("queue", None)?,
+    ]);
+
+    let tail = tail.iter().collect::<Vec<_>>();
+    pipeline.add_many(&tail)?;
+    gst::Element::link_many(&tail)?;
+    tail.last().unwrap().link(&muxer)?;
+
+    let mut sources = Vec::new();
+    for (i, input) in video_settings.audio.iter().enumerate() {
+        let src = match make_audio_source(i, &input.source)? {
+            Some(src) => src,
+            None => continue,
+        };
+        let volume = gst::ElementFactory::make("volume", None)?;
+        volume.set_property("volume", input.volume);
+
+        let elements = [
+            &src,
+            &gst::ElementFactory::make("queue", None)?,
+            &gst::ElementFactory::make("audioconvert", None)?,
+            &gst::ElementFactory::make("audioresample", None)?,
+            &volume,
+        ];
+        pipeline.add_many(&elements)?;
+        gst::Element::link_many(&elements)?;
+        volume.link(&mixer)?;
+
+        // A file cut to a length ends on its own, and may not have been fully decoded
+        // by the time the video ends
+        if !matches!(
+            input.source,
+            AudioSource::File {
+                length: Some(_),
+                ..
+            }
+        ) {
+            sources.push(src);
+        }
+    }
+
+    // The stream can also be ended past `video_pad`, like when an `EncodeLimits` is hit
+    let video_pad = pipeline
+        .by_name("encoder")
+        .and_then(|encoder| encoder.static_pad("src"))
+        .unwrap_or_else(|| video_pad.clone());
+    video_pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+        if let Some(gst::PadProbeData::Event(event)) = &info.data {
+            if event.type_() == gst::EventType::Eos {
+                for src in &sources {
+                    src.send_event(gst::event::Eos::new());
+                }
+            }
+        }
+        gst::PadProbeReturn::Ok
+    });
+
+    Ok(())
+}
+
+/// Creates the element audio is captured by, `None` when the platform can't capture it
+fn make_audio_source(index: usize, source: &AudioSource) -> anyhow::Result<Option<gst::Element>> {
+    let name = format!("audio source {index}");
+    let factory = if cfg!(target_os = "macos") {
+        "osxaudiosrc"
+    } else if cfg!(target_os = "windows") {
+        "wasapisrc"
+    } else {
+        "pulsesrc"
+    };
+
+    let src = match source {
+        AudioSource::SystemLoopback => {
+            if cfg!(target_os = "macos") {
+                println!("Recording system audio isn't supported on macOS, ignoring it");
+                return Ok(None);
+            }
+
+            let src = gst::ElementFactory::make(factory, Some(&name))?;
+            if cfg!(target_os = "windows") {
+                src.set_property("loopback", true);
+            } else {
+                src.set_property("device", "@DEFAULT_MONITOR@");
+            }
+            src
+        }
+        AudioSource::Microphone { device } => {
+            let src = gst::ElementFactory::make(factory, Some(&name))?;
+            if let Some(device) = device {
+                src.set_property_from_str("device", device);
+            }
+            src
+        }
+        AudioSource::File { path, length } => make_file_source(&name, path, *length)?,
+    };
+
+    Ok(Some(src))
+}
+
+/// Creates a bin decoding an audio file, trimmed or padded with silence to `length`
+fn make_file_source(
+    name: &str,
+    path: &std::path::Path,
+    length: Option<Duration>,
+) -> anyhow::Result<gst::Element> {
+    let bin = gst::Bin::new(Some(name));
+
+    let filesrc = gst::ElementFactory::make("filesrc", None)?;
+    filesrc.set_property("location", path.to_string_lossy().as_ref());
+    let decodebin = gst::ElementFactory::make("decodebin", None)?;
+    let convert = gst::ElementFactory::make("audioconvert", None)?;
+
+    bin.add_many(&[&filesrc, &decodebin, &convert])?;
+    filesrc.link(&decodebin)?;
+
+    let convert_sink = convert.static_pad("sink").unwrap();
+    decodebin.connect_pad_added(move |_, pad| {
+        let is_audio = pad
+            .current_caps()
+            .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("audio/")))
+            .unwrap_or(false);
+
+        // Only the first audio stream is used, anything else is left unlinked
+        if is_audio && !convert_sink.is_linked() {
+            if let Err(e) = pad.link(&convert_sink) {
+                println!("Could not link the audio of the file: {e}");
+            }
+        }
+    });
+
+    let output = match length {
+        Some(length) => {
+            let length = ClockTime::from_nseconds(length.as_nanos() as u64);
+            end_at(&convert.static_pad("src").unwrap(), length);
+
+            // Silence as long as the video, so a shorter file is padded by the mixer
+            let silence = gst::ElementFactory::make("audiotestsrc", None)?;
+            silence.set_property_from_str("wave", "silence");
+            end_at(&silence.static_pad("src").unwrap(), length);
+
+            let mixer = gst::ElementFactory::make("audiomixer", None)?;
+            bin.add_many(&[&silence, &mixer])?;
+            convert.link(&mixer)?;
+            silence.link(&mixer)?;
+            mixer
+        }
+        None => convert,
+    };
+
+    let ghost = gst::GhostPad::with_target(Some("src"), &output.static_pad("src").unwrap())?;
+    bin.add_pad(&ghost)?;
+
+    Ok(bin.upcast())
+}
+
+/// Ends the stream leaving `pad` once it reaches `length`
+///
+/// The probe is removed once the end is sent, after that the peer refuses buffers
+/// so the source stops instead of producing audio that's thrown away.
+fn end_at(pad: &gst::Pad, length: ClockTime) {
+    pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+        let pts = match &info.data {
+            Some(gst::PadProbeData::Buffer(buffer)) => buffer.pts(),
+            _ => None,
+        };
+
+        if pts.is_some_and(|pts| pts >= length) {
+            if let Some(peer) = pad.peer() {
+                peer.send_event(gst::event::Eos::new());
+            }
+            return gst::PadProbeReturn::Remove;
+        }
+        gst::PadProbeReturn::Ok
+    });
+}
+
+/// Creates the audio encoder of the settings, or one the muxer accepts
+fn make_audio_encoder(video_settings: &VideoSettings) -> anyhow::Result<gst::Element> {
+    let factory = video_settings.audio_encoder.as_deref().unwrap_or_else(|| {
+        match muxer_factory(video_settings) {
+            "webmmux" | "matroskamux" => "opusenc",
+            _ => "avenc_aac",
+        }
+    });
+
+    Ok(gst::ElementFactory::make(factory, Some("audio encoder"))?)
+}
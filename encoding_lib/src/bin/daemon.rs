@@ -0,0 +1,256 @@
+//! Runs an [`EncodeQueue`] as a long lived service so short lived tools can share one warm GStreamer process
+//!
+//! Usage: `stream-encoder-daemon [socket path] [concurrent jobs]`
+//!
+//! Clients connect to the Unix socket and send one JSON request per line,
+//! every request gets one JSON response line back:
+//! ```text
+//! {"cmd":"submit","output":"out.mp4","input":{"file":"in.mkv"},"settings":{"framerate":30,"width":1280,"height":720}}
+//! {"ok":true,"id":0}
+//! {"cmd":"status","id":0}
+//! {"ok":true,"jobs":[{"id":0,"state":"running","frames":120,"total_frames":900}]}
+//! {"cmd":"cancel","id":0}
+//! {"ok":true,"cancelled":true}
+//! ```
+//! Leaving out the `id` of a status request lists every job.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use stream_encoder::{EncodeJob, EncodeQueue, FrameSource, JobId, JobStatus, VideoSettings};
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+enum Request {
+    Submit(SubmitRequest),
+    Status { id: Option<u64> },
+    Cancel { id: u64 },
+}
+
+#[derive(Deserialize)]
+struct SubmitRequest {
+    output: String,
+    input: JobInput,
+    settings: JobSettings,
+    #[serde(default)]
+    priority: i32,
+    threads: Option<u32>,
+    nice: Option<i32>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum JobInput {
+    /// A video file to transcode
+    File(PathBuf),
+    /// Image files to encode in order
+    Frames(Vec<PathBuf>),
+}
+
+#[derive(Deserialize)]
+struct JobSettings {
+    framerate: u64,
+    width: u32,
+    height: u32,
+    encoder: Option<String>,
+    muxer: Option<String>,
+}
+
+#[derive(Serialize)]
+struct JobReport {
+    id: u64,
+    state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frames: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_frames: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl JobReport {
+    fn new(id: JobId, status: JobStatus) -> Self {
+        let mut report = JobReport {
+            id: id.0,
+            state: "queued",
+            frames: None,
+            total_frames: None,
+            file_size: None,
+            error: None,
+        };
+
+        match status {
+            JobStatus::Queued => {}
+            JobStatus::Running {
+                frames,
+                total_frames,
+            } => {
+                report.state = "running";
+                report.frames = Some(frames);
+                report.total_frames = total_frames;
+            }
+            JobStatus::Finished(stats) => {
+                report.state = "finished";
+                report.frames = Some(stats.frames);
+                report.file_size = Some(stats.file_size);
+            }
+            JobStatus::Failed(e) => {
+                report.state = "failed";
+                report.error = Some(e);
+            }
+            JobStatus::Cancelled => report.state = "cancelled",
+        }
+
+        report
+    }
+}
+
+impl SubmitRequest {
+    fn into_job(self) -> anyhow::Result<EncodeJob> {
+        let mut settings = VideoSettings::new(
+            self.settings.framerate,
+            self.settings.width,
+            self.settings.height,
+        );
+        if let Some(encoder) = self.settings.encoder {
+            settings.encoder = encoder;
+        }
+        if let Some(muxer) = self.settings.muxer {
+            settings.muxer = muxer;
+        }
+
+        let input = match self.input {
+            JobInput::File(path) => FrameSource::File(path),
+            JobInput::Frames(paths) => FrameSource::Frames(
+                paths
+                    .iter()
+                    .map(image::open)
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+        };
+
+        let mut job = EncodeJob::new(&self.output, settings, input).priority(self.priority);
+        if let Some(threads) = self.threads {
+            job = job.threads(threads);
+        }
+        if let Some(nice) = self.nice {
+            job = job.nice(nice);
+        }
+        Ok(job)
+    }
+}
+
+/// Handles one request line, returning the response
+fn handle_request(queue: &EncodeQueue, line: &str) -> serde_json::Value {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(e) => return serde_json::json!({ "ok": false, "error": e.to_string() }),
+    };
+
+    match request {
+        Request::Submit(submit) => match submit.into_job() {
+            Ok(job) => serde_json::json!({ "ok": true, "id": queue.submit(job).0 }),
+            Err(e) => serde_json::json!({ "ok": false, "error": e.to_string() }),
+        },
+        Request::Status { id: Some(id) } => match queue.status(JobId(id)) {
+            Some(status) => {
+                serde_json::json!({ "ok": true, "jobs": [JobReport::new(JobId(id), status)] })
+            }
+            None => serde_json::json!({ "ok": false, "error": format!("There is no job {id}") }),
+        },
+        Request::Status { id: None } => {
+            let jobs = queue
+                .jobs()
+                .into_iter()
+                .map(|(id, status)| JobReport::new(id, status))
+                .collect::<Vec<_>>();
+            serde_json::json!({ "ok": true, "jobs": jobs })
+        }
+        Request::Cancel { id } => {
+            serde_json::json!({ "ok": true, "cancelled": queue.cancel(JobId(id)) })
+        }
+    }
+}
+
+#[cfg(unix)]
+fn main() -> anyhow::Result<()> {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::{fs::FileTypeExt, net::UnixListener},
+        sync::Arc,
+    };
+
+    let mut args = std::env::args().skip(1);
+    let socket_path = args.next().map(PathBuf::from).unwrap_or_else(|| {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("stream-encoder.sock")
+    });
+    let concurrency = match args.next() {
+        Some(concurrency) => concurrency.parse()?,
+        None => std::thread::available_parallelism().map_or(1, |cores| cores.get() / 2),
+    };
+
+    stream_encoder::init_encoder();
+
+    // A socket left over from a daemon that didn't shut down cleanly,
+    // anything else at the path is left alone
+    match std::fs::symlink_metadata(&socket_path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&socket_path)?,
+        Ok(_) => {
+            return Err(anyhow::Error::msg(format!(
+                "{} already exists and isn't a socket",
+                socket_path.display()
+            )))
+        }
+        Err(_) => {}
+    }
+    let listener = UnixListener::bind(&socket_path)?;
+    println!(
+        "Listening on {} running {concurrency} jobs at once",
+        socket_path.display()
+    );
+
+    let queue = Arc::new(EncodeQueue::new(concurrency));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Could not accept a connection: {e}");
+                continue;
+            }
+        };
+
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            let mut writer = match stream.try_clone() {
+                Ok(writer) => writer,
+                Err(_) => return,
+            };
+
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(line) if line.trim().is_empty() => continue,
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+
+                let response = handle_request(&queue, &line);
+                if writeln!(writer, "{response}").is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    println!("stream-encoder-daemon only runs on Unix for now");
+}
@@ -0,0 +1,173 @@
+use gst::{prelude::*, Caps};
+use gstreamer as gst;
+
+use crate::{
+    pipeline::{link_encoding, new_pipeline},
+    EncodeHandle, ScalingMode, VideoSettings,
+};
+
+/// Where [`start_capture`] records frames from
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureSource {
+    /// A camera, read by `v4l2src` on Linux, `avfvideosrc` on macOS and `ksvideosrc` on Windows
+    ///
+    /// Frames are converted and scaled from whatever mode the camera picks,
+    /// cameras that only output MJPEG aren't supported.
+    Camera {
+        /// The device to open, a path like `/dev/video0` on Linux, an index on macOS
+        /// and a device path on Windows, `None` opens the default camera
+        device: Option<String>,
+        /// The width and height of the video
+        resolution: (u32, u32),
+        /// The framerate of the video
+        fps: u64,
+    },
+    /// A single window, which is followed as it's resized
+    ///
+    /// The video stays the size of the settings, frames are fitted into it by
+    /// [`VideoSettings::scaling`], which is switched to `Letterbox` when it's `Strict`.
+    Window {
+        /// The window to capture
+        window: WindowId,
+        /// The framerate of the video
+        fps: u64,
+    },
+}
+
+/// Identifies a window to capture with [`CaptureSource::Window`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowId {
+    /// An X11 window ID, captured by `ximagesrc`
+    X11(u64),
+    /// A Windows window handle, captured by `d3d11screencapturesrc`
+    Hwnd(u64),
+    /// A PipeWire node, e.g. from the xdg-desktop-portal screencast portal, captured by `pipewiresrc`
+    PipeWire(u32),
+}
+
+impl CaptureSource {
+    /// Creates the element frames are captured by
+    fn make_source(&self) -> anyhow::Result<gst::Element> {
+        match self {
+            CaptureSource::Camera { device, .. } => {
+                let factory = if cfg!(target_os = "macos") {
+                    "avfvideosrc"
+                } else if cfg!(target_os = "windows") {
+                    "ksvideosrc"
+                } else {
+                    "v4l2src"
+                };
+                let src = gst::ElementFactory::make(factory, Some("capture"))?;
+
+                if let Some(device) = device {
+                    if cfg!(target_os = "macos") {
+                        src.set_property_from_str("device-index", device);
+                    } else if cfg!(target_os = "windows") {
+                        src.set_property("device-path", device);
+                    } else {
+                        src.set_property("device", device);
+                    }
+                }
+
+                Ok(src)
+            }
+            CaptureSource::Window { window, .. } => match window {
+                WindowId::X11(xid) => {
+                    let src = gst::ElementFactory::make("ximagesrc", Some("capture"))?;
+                    src.set_property("xid", xid);
+                    // Otherwise only the changed parts of the window are redrawn
+                    src.set_property("use-damage", false);
+                    Ok(src)
+                }
+                WindowId::Hwnd(hwnd) => {
+                    let src = gst::ElementFactory::make("d3d11screencapturesrc", Some("capture"))?;
+                    src.set_property("window-handle", hwnd);
+                    Ok(src)
+                }
+                WindowId::PipeWire(node) => {
+                    let src = gst::ElementFactory::make("pipewiresrc", Some("capture"))?;
+                    src.set_property("path", node.to_string());
+                    Ok(src)
+                }
+            },
+        }
+    }
+
+    /// The caps frames are converted to before the filters of the settings,
+    /// which leave out the size of windows so they can change it
+    fn caps(&self, video_settings: &VideoSettings) -> Caps {
+        let framerate = gst::Fraction::new(video_settings.framerate as i32, 1);
+        match self {
+            CaptureSource::Camera { .. } => Caps::builder("video/x-raw")
+                .field("width", video_settings.width as i32)
+                .field("height", video_settings.height as i32)
+                .field("framerate", framerate)
+                .build(),
+            CaptureSource::Window { .. } => Caps::builder("video/x-raw")
+                .field("framerate", framerate)
+                .build(),
+        }
+    }
+
+    /// Overrides the parts of the settings the source decides
+    fn apply(&self, video_settings: &mut VideoSettings) {
+        match self {
+            CaptureSource::Camera {
+                resolution, fps, ..
+            } => {
+                video_settings.width = resolution.0;
+                video_settings.height = resolution.1;
+                video_settings.framerate = *fps;
+            }
+            CaptureSource::Window { fps, .. } => {
+                video_settings.framerate = *fps;
+                if video_settings.scaling == ScalingMode::Strict {
+                    video_settings.scaling = ScalingMode::Letterbox;
+                }
+            }
+        }
+    }
+}
+
+/// Starts recording from a capture source in the background, through the same filters, encoder
+/// and muxer as frames pushed by hand
+///
+/// The framerate of `video_settings` is replaced by the one of the source,
+/// and the size too for cameras.<br>
+/// The recording runs until [`EncodeHandle::stop`] is called or the handle is dropped.
+pub fn start_capture(
+    output_path: &str,
+    source: CaptureSource,
+    mut video_settings: VideoSettings,
+) -> anyhow::Result<EncodeHandle> {
+    crate::init_encoder();
+    source.apply(&mut video_settings);
+
+    let pipeline = new_pipeline(&video_settings);
+
+    let src = source.make_source()?;
+    let filter = gst::ElementFactory::make("capsfilter", Some("capture filter"))?;
+    filter.set_property("caps", source.caps(&video_settings));
+    let output_pad = filter.static_pad("src").unwrap();
+
+    let head = vec![
+        src,
+        gst::ElementFactory::make("videoconvert", Some("capture convert"))?,
+        gst::ElementFactory::make("videoscale", Some("capture scale"))?,
+        gst::ElementFactory::make("videorate", Some("capture rate"))?,
+        filter,
+    ];
+
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output_path);
+
+    link_encoding(&pipeline, head, sink, &video_settings)?;
+
+    pipeline.set_state(gst::State::Playing)?;
+    Ok(EncodeHandle::spawn_with_sources(
+        pipeline,
+        Vec::new(),
+        output_pad,
+        &video_settings,
+    ))
+}
@@ -0,0 +1,284 @@
+use std::{
+    ops::Deref,
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+};
+
+use gst::prelude::*;
+use gst_app::AppSrc;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+
+use crate::{
+    data_provider::set_data_provider,
+    data_provider_impls::sized_reciever_data_provider,
+    pipeline::{configure_appsrc, init_encoder, link_encoding, make_video_info, new_pipeline},
+    Decoder, EncodeHandle, EncodeStats, PixelFormat, VideoSettings,
+};
+
+/// Where a source of a [`Compositor`] is drawn in the video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// The position of the left edge, can be outside the video
+    pub x: i32,
+    /// The position of the top edge, can be outside the video
+    pub y: i32,
+    /// The width frames are scaled to
+    pub width: u32,
+    /// The height frames are scaled to
+    pub height: u32,
+    /// Sources with a higher z-order are drawn on top
+    pub zorder: u32,
+}
+
+impl Placement {
+    pub fn new(x: i32, y: i32, width: u32, height: u32, zorder: u32) -> Self {
+        Placement {
+            x,
+            y,
+            width,
+            height,
+            zorder,
+        }
+    }
+}
+
+/// Blends frames from several sources into one video with the `compositor` element,
+/// e.g. game footage with a webcam in the corner
+///
+/// Each source gets its own channel and can send frames of its own size,
+/// the parts of the video not covered by a source are black.<br>
+/// The compositor waits for a frame from every source that hasn't ended yet,
+/// so a source that stops sending frames without dropping its sender stalls the video.
+#[derive(Debug, Clone)]
+pub struct Compositor {
+    video_settings: VideoSettings,
+    sources: Vec<(u32, u32, Placement)>,
+}
+
+impl Compositor {
+    /// Starts building a compositor making a video described by `video_settings`
+    pub fn new(video_settings: VideoSettings) -> Self {
+        Compositor {
+            video_settings,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Adds a source sending `width` by `height` frames, drawn at `placement`
+    pub fn source(mut self, width: u32, height: u32, placement: Placement) -> Self {
+        self.sources.push((width, height, placement));
+        self
+    }
+
+    /// Starts encoding, returning a sender for every source in the order they were added
+    ///
+    /// Fails if the pipeline can't be built.
+    ///
+    /// # Deadlock
+    /// Joining the handle before dropping every sender will deadlock.
+    #[allow(clippy::type_complexity)]
+    pub fn start<
+        Format: PixelFormat + Send + Sync + 'static,
+        Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+        const BUFFER_SIZE: usize,
+    >(
+        mut self,
+        output_path: &str,
+    ) -> anyhow::Result<(EncodeHandle, Vec<Sender<ImageBuffer<Format, Container>>>)> {
+        init_encoder();
+        self.video_settings.format = Format::VIDEO_FORMAT;
+
+        let pipeline = new_pipeline(&self.video_settings);
+
+        let compositor = gst::ElementFactory::make("compositor", Some("compositor"))?;
+        compositor.set_property_from_str("background", "black");
+
+        // Fixes the size, framerate and format of the blended frames
+        let filter = gst::ElementFactory::make("capsfilter", Some("compositor caps"))?;
+        filter.set_property(
+            "caps",
+            make_video_info(&self.video_settings).to_caps().unwrap(),
+        );
+
+        let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+        sink.set_property("location", output_path);
+
+        link_encoding(
+            &pipeline,
+            vec![compositor.clone(), filter],
+            sink,
+            &self.video_settings,
+        )?;
+
+        let mut appsrcs = Vec::new();
+        let mut senders = Vec::new();
+
+        for (i, (width, height, placement)) in self.sources.iter().enumerate() {
+            let mut source_settings = self.video_settings.clone();
+            source_settings.width = *width;
+            source_settings.height = *height;
+
+            let appsrc = gst::ElementFactory::make("appsrc", Some(&format!("source {i}")))?
+                .dynamic_cast::<AppSrc>()
+                .unwrap();
+            pipeline.add(&appsrc)?;
+
+            let video_info = make_video_info(&source_settings);
+            configure_appsrc(&appsrc, &video_info, &source_settings);
+
+            let pad = compositor.request_pad_simple("sink_%u").unwrap();
+            pad.set_property("xpos", placement.x);
+            pad.set_property("ypos", placement.y);
+            pad.set_property("width", placement.width as i32);
+            pad.set_property("height", placement.height as i32);
+            pad.set_property("zorder", placement.zorder);
+            appsrc.static_pad("src").unwrap().link(&pad)?;
+
+            let (sender, recv) = channel();
+            set_data_provider(
+                &appsrc,
+                video_info,
+                source_settings,
+                sized_reciever_data_provider::<Format, Container>,
+                None::<fn(&AppSrc, &VideoSettings, _) -> anyhow::Result<()>>,
+                (
+                    Arc::new(Mutex::new(0)),
+                    Arc::new(Mutex::new(recv)),
+                    Arc::new(Mutex::new(None)),
+                    BUFFER_SIZE,
+                ),
+            );
+
+            appsrcs.push(appsrc);
+            senders.push(sender);
+        }
+
+        pipeline.set_state(gst::State::Playing)?;
+
+        let output_pad = compositor.static_pad("src").unwrap();
+        let handle =
+            EncodeHandle::spawn_with_sources(pipeline, appsrcs, output_pad, &self.video_settings);
+
+        Ok((handle, senders))
+    }
+}
+
+/// The frames of one tile of [`encode_grid`]
+#[derive(Debug, Clone)]
+pub enum FrameSource {
+    /// Frames already in memory, which all have to be the same size
+    Frames(Vec<DynamicImage>),
+    /// A video file, decoded as it's encoded
+    File(PathBuf),
+}
+
+impl FrameSource {
+    /// The size of the frames, which for files means opening them
+    fn frame_size(&self) -> anyhow::Result<(u32, u32)> {
+        match self {
+            FrameSource::Frames(frames) => frames
+                .first()
+                .map(|frame| (frame.width(), frame.height()))
+                .ok_or_else(|| anyhow::Error::msg("A grid source has no frames")),
+            FrameSource::File(path) => Decoder::open(path)?
+                .video_info()
+                .map(|info| (info.width(), info.height()))
+                .ok_or_else(|| {
+                    anyhow::Error::msg(format!("Can't tell the size of {}", path.display()))
+                }),
+        }
+    }
+
+    /// Sends every frame of the source, stopping early if the encoder has gone away
+    fn send_all(self, sender: Sender<ImageBuffer<Rgba<u8>, Vec<u8>>>) -> anyhow::Result<()> {
+        match self {
+            FrameSource::Frames(frames) => {
+                for frame in frames {
+                    if sender.send(frame.into_rgba8()).is_err() {
+                        break;
+                    }
+                }
+            }
+            FrameSource::File(path) => {
+                for frame in Decoder::open(path)? {
+                    if sender.send(frame).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Tiles the sources into a `cols` by `rows` grid, filled a row at a time,
+/// e.g. to compare renders or review several cameras at once
+///
+/// Every tile is the size of the video divided by the grid, frames are stretched to fit it.<br>
+/// The video is as long as the longest source, tiles of sources that have ended are black.
+///
+/// Blocks the current thread till the encoding is done
+pub fn encode_grid(
+    output_path: &str,
+    sources: Vec<FrameSource>,
+    cols: u32,
+    rows: u32,
+    video_settings: VideoSettings,
+) -> anyhow::Result<EncodeStats> {
+    if cols == 0 || rows == 0 || sources.len() > (cols * rows) as usize {
+        return Err(anyhow::Error::msg(format!(
+            "{} sources don't fit in a {cols}x{rows} grid",
+            sources.len()
+        )));
+    }
+
+    let tile_width = video_settings.width / cols;
+    let tile_height = video_settings.height / rows;
+
+    let mut compositor = Compositor::new(video_settings);
+    for (i, source) in sources.iter().enumerate() {
+        let (width, height) = source.frame_size()?;
+        let (col, row) = (i as u32 % cols, i as u32 / cols);
+        compositor = compositor.source(
+            width,
+            height,
+            Placement::new(
+                (col * tile_width) as i32,
+                (row * tile_height) as i32,
+                tile_width,
+                tile_height,
+                0,
+            ),
+        );
+    }
+
+    let (handle, senders) = compositor.start::<Rgba<u8>, Vec<u8>, 4>(output_path)?;
+
+    let threads = sources
+        .into_iter()
+        .zip(senders)
+        .map(|(source, sender)| std::thread::spawn(move || source.send_all(sender)))
+        .collect::<Vec<_>>();
+
+    let mut result = Ok(());
+    for thread in threads {
+        let sent = thread
+            .join()
+            .map_err(|_| anyhow::Error::msg("A grid source thread panicked"))
+            .and_then(|sent| sent);
+        if result.is_ok() {
+            result = sent;
+        }
+    }
+
+    // Errors from the sources are more useful than the pipeline failing because of them
+    let stats = handle.join();
+    result?;
+    Ok(stats?)
+}
@@ -0,0 +1,119 @@
+use std::sync::{
+    mpsc::{channel, Sender},
+    Arc, Mutex,
+};
+
+use gst::{prelude::*, Caps};
+use gst_app::AppSrc;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+
+use crate::{
+    data_provider::start_pipeline,
+    data_provider_impls::raw_reciever_data_provider,
+    pipeline::{configure_live, link_encoding, make_video_info, new_pipeline},
+    EncodeHandle, VideoSettings,
+};
+
+/// The formats of compressed still images that can be decoded inside the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StillFormat {
+    /// Decoded by `pngdec`
+    Png,
+    /// Decoded by `jpegdec`
+    Jpeg,
+}
+
+impl StillFormat {
+    fn caps_name(self) -> &'static str {
+        match self {
+            StillFormat::Png => "image/png",
+            StillFormat::Jpeg => "image/jpeg",
+        }
+    }
+
+    fn decoder(self) -> &'static str {
+        match self {
+            StillFormat::Png => "pngdec",
+            StillFormat::Jpeg => "jpegdec",
+        }
+    }
+}
+
+/// Sends the encoded bytes of whole images to an encoder started by [`start_encoding_compressed`]
+#[derive(Debug, Clone)]
+pub struct CompressedFrameSender {
+    sender: Sender<gst::Buffer>,
+}
+
+impl CompressedFrameSender {
+    /// Sends the bytes of a whole PNG or JPEG file to the encoder without copying them
+    pub fn send(&self, image: Vec<u8>) -> anyhow::Result<()> {
+        self.sender
+            .send(gst::Buffer::from_mut_slice(image))
+            .map_err(|_| anyhow::Error::msg("The encoder has already stopped"))
+    }
+}
+
+/// Starts encoding in the background from PNG or JPEG images, which are decoded by the pipeline
+/// instead of on the caller's thread, e.g. for frames that arrive over the network as JPEGs
+///
+/// Images that aren't the size of the video are handled by [`VideoSettings::scaling`],
+/// `format` is ignored as the decoder picks it.
+///
+/// # Deadlock
+/// Joining the handle before dropping the sender will deadlock.
+pub fn start_encoding_compressed<const BUFFER_SIZE: usize>(
+    output_path: &str,
+    still_format: StillFormat,
+    video_settings: VideoSettings,
+) -> anyhow::Result<(EncodeHandle, CompressedFrameSender)> {
+    crate::init_encoder();
+
+    let pipeline = new_pipeline(&video_settings);
+
+    let src = gst::ElementFactory::make("appsrc", Some("source"))?;
+    let decoder = gst::ElementFactory::make(still_format.decoder(), Some("image decoder"))?;
+    let convert = gst::ElementFactory::make("videoconvert", Some("decode convert"))?;
+
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output_path);
+
+    link_encoding(
+        &pipeline,
+        vec![src.clone(), decoder, convert],
+        sink,
+        &video_settings,
+    )?;
+
+    let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+    appsrc.set_caps(Some(
+        &Caps::builder(still_format.caps_name())
+            .field(
+                "framerate",
+                gst::Fraction::new(video_settings.framerate as i32, 1),
+            )
+            .build(),
+    ));
+    appsrc.set_format(gst::Format::Time);
+    configure_live(&appsrc, &video_settings);
+
+    let (sender, recv) = channel();
+    let recv = Arc::new(Mutex::new(recv));
+    let handle_settings = video_settings.clone();
+
+    start_pipeline(
+        &pipeline,
+        &appsrc,
+        make_video_info(&video_settings),
+        video_settings,
+        raw_reciever_data_provider::<BUFFER_SIZE>,
+        None::<fn(&AppSrc, &VideoSettings, _) -> ()>,
+        (Arc::new(Mutex::new(0)), recv.clone()),
+    );
+
+    let handle = EncodeHandle::spawn(pipeline, appsrc, &handle_settings);
+    handle.close_on_failure(recv);
+
+    Ok((handle, CompressedFrameSender { sender }))
+}
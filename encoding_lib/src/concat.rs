@@ -0,0 +1,74 @@
+use gst::{prelude::*, Pipeline};
+use gstreamer as gst;
+
+use crate::{
+    data_provider::wait_for_eos,
+    pipeline::{make_muxer, unique_pipeline},
+    VideoSettings,
+};
+
+/// Joins the video streams of `parts` into one file at `output_path` without re-encoding them
+///
+/// The parts have to be encoded with the same settings,
+/// the output is muxed as the settings describe.
+pub(crate) fn concat_files(
+    parts: &[String],
+    output_path: &str,
+    video_settings: &VideoSettings,
+) -> anyhow::Result<()> {
+    let pipeline = unique_pipeline("concat pipeline");
+
+    let concat = gst::ElementFactory::make("concat", Some("concat"))?;
+    let muxer = make_muxer(video_settings)?;
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output_path);
+
+    pipeline.add_many(&[&concat, &muxer, &sink])?;
+    concat.link(&muxer)?;
+    muxer.link(&sink)?;
+
+    for (i, part) in parts.iter().enumerate() {
+        let src = gst::ElementFactory::make("filesrc", Some(&format!("source {i}")))?;
+        src.set_property("location", part);
+        let demux = gst::ElementFactory::make("parsebin", Some(&format!("demux {i}")))?;
+
+        pipeline.add_many(&[&src, &demux])?;
+        src.link(&demux)?;
+
+        // The pads are requested up front so the parts are played in order
+        let concat_pad = concat.request_pad_simple("sink_%u").unwrap();
+
+        let pipeline_weak = pipeline.downgrade();
+        demux.connect_pad_added(move |_, pad| {
+            let pipeline = match pipeline_weak.upgrade() {
+                Some(pipeline) => pipeline,
+                None => return,
+            };
+
+            link_part(&pipeline, pad, &concat_pad);
+        });
+    }
+
+    pipeline.set_state(gst::State::Playing)?;
+    wait_for_eos(&pipeline, video_settings.on_message.as_ref())?;
+
+    Ok(())
+}
+
+/// Links the video stream of a part to its concat pad, anything else is thrown away
+fn link_part(pipeline: &Pipeline, pad: &gst::Pad, concat_pad: &gst::Pad) {
+    let is_video = pad
+        .current_caps()
+        .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("video/")))
+        .unwrap_or(false);
+
+    if is_video && !concat_pad.is_linked() {
+        pad.link(concat_pad).unwrap();
+        return;
+    }
+
+    let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+    pipeline.add(&fakesink).unwrap();
+    fakesink.sync_state_with_parent().unwrap();
+    pad.link(&fakesink.static_pad("sink").unwrap()).unwrap();
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use gst::{prelude::*, MessageView, Pipeline};
+use gstreamer as gst;
+
+use crate::{
+    data_provider::CallbackError, error::PipelineError, pipeline::finish_partial_output,
+    watchdog::STALLED_MESSAGE, MessageCallback, PartialFile,
+};
+
+/// Something that happened in an encode driven by an [`EncodingController`]
+#[derive(Debug)]
+pub enum EncodeEvent {
+    /// The video has been finalized
+    Finished,
+    /// The encode failed and the pipeline has been stopped
+    Error(PipelineError),
+    /// A warning posted by one of the elements
+    Warning(String),
+    /// No buffers have reached the output for this long, see [`Watchdog`](crate::Watchdog)
+    Stalled(Duration),
+    /// Any other message posted on the bus
+    Message(gst::Message),
+}
+
+/// Controls an encode started by [`encode_video_nonblocking`](crate::data_provider::encode_video_nonblocking)
+///
+/// Nothing watches the bus in the background, so [`poll_events`](EncodingController::poll_events)
+/// has to be called regularly, e.g. once per frame of a GUI.
+///
+/// Dropping the controller before the encode is finished stops the pipeline,
+/// which may leave an unplayable file.
+pub struct EncodingController {
+    pipeline: Pipeline,
+    on_message: Option<MessageCallback>,
+    callback_error: CallbackError,
+    result: Option<Result<(), PipelineError>>,
+    atomic_output: Option<PartialFile>,
+}
+
+impl EncodingController {
+    pub(crate) fn new(
+        pipeline: Pipeline,
+        on_message: Option<MessageCallback>,
+        callback_error: CallbackError,
+        atomic_output: Option<PartialFile>,
+    ) -> Self {
+        EncodingController {
+            pipeline,
+            on_message,
+            callback_error,
+            result: None,
+            atomic_output,
+        }
+    }
+
+    /// Handles every message currently on the bus without blocking
+    pub fn poll_events(&mut self) -> Vec<EncodeEvent> {
+        let bus = self.pipeline.bus().unwrap();
+        let mut events = Vec::new();
+
+        while self.result.is_none() {
+            match bus.pop() {
+                Some(msg) => events.push(self.handle_message(msg)),
+                None => break,
+            }
+        }
+
+        events
+    }
+
+    /// Whether the encode has finished, either by finalizing the video or by failing
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Blocks until the encode is finished
+    ///
+    /// # Deadlock
+    /// Waiting before the data provider has ended the stream will deadlock.
+    pub fn wait(mut self) -> anyhow::Result<()> {
+        let bus = self.pipeline.bus().unwrap();
+
+        while self.result.is_none() {
+            if let Some(msg) = bus.timed_pop(gst::ClockTime::NONE) {
+                self.handle_message(msg);
+            }
+        }
+
+        // Errors from the callbacks are also on the bus but the original error is more useful
+        let error = self.callback_error.lock().unwrap().take();
+        match error {
+            Some(e) => Err(e),
+            None => Ok(self.result.take().unwrap()?),
+        }
+    }
+
+    fn handle_message(&mut self, msg: gst::Message) -> EncodeEvent {
+        if let Some(callback) = &self.on_message {
+            (callback.0)(&msg);
+        }
+
+        let event = match msg.view() {
+            MessageView::Eos(_) => {
+                self.result = Some(Ok(()));
+                EncodeEvent::Finished
+            }
+            MessageView::Error(e) => {
+                let error = PipelineError::from_message(&e);
+                self.result = Some(Err(error.clone()));
+                EncodeEvent::Error(error)
+            }
+            MessageView::Warning(w) => EncodeEvent::Warning(w.error().to_string()),
+            MessageView::Application(a)
+                if a.structure().map(|s| s.name()) == Some(STALLED_MESSAGE) =>
+            {
+                let seconds = a.structure().unwrap().get::<f64>("seconds").unwrap_or(0.0);
+                EncodeEvent::Stalled(Duration::from_secs_f64(seconds))
+            }
+            _ => EncodeEvent::Message(msg.clone()),
+        };
+
+        if let Some(result) = &self.result {
+            self.pipeline.set_state(gst::State::Null).unwrap();
+
+            let succeeded = result.is_ok() && self.callback_error.lock().unwrap().is_none();
+            if let Err(e) = finish_partial_output(&self.pipeline, self.atomic_output, succeeded) {
+                println!("Couldn't finish the partial output: {e}");
+            }
+        }
+
+        event
+    }
+}
+
+impl Drop for EncodingController {
+    fn drop(&mut self) {
+        if self.result.is_none() {
+            let _ = self.pipeline.set_state(gst::State::Null);
+            let _ = finish_partial_output(&self.pipeline, self.atomic_output, false);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{ffi::c_void, ptr};
+
+use gst::glib::{
+    ffi::gpointer,
+    translate::{from_glib_full, ToGlibPtr},
+};
+use gst_video::VideoInfo;
+use gstreamer as gst;
+use gstreamer_video as gst_video;
+
+// The CUDA helpers are part of gst-plugins-bad's libgstcuda, which has no Rust bindings,
+// so the two functions needed are declared here.
+// gst_cuda_allocator_alloc_wrapped was added in GStreamer 1.24, older versions fail to link.
+#[link(name = "gstcuda-1.0")]
+extern "C" {
+    fn gst_cuda_context_new_wrapped(context: *mut c_void, device: i32) -> *mut gst::ffi::GstObject;
+    fn gst_cuda_allocator_alloc_wrapped(
+        allocator: *mut gst::ffi::GstAllocator,
+        context: *mut gst::ffi::GstObject,
+        stream: *mut c_void,
+        info: *const gst_video::ffi::GstVideoInfo,
+        dev_ptr: u64,
+        user_data: gpointer,
+        notify: Option<unsafe extern "C" fn(gpointer)>,
+    ) -> *mut gst::ffi::GstMemory;
+}
+
+/// A CUDA context frames are allocated in, shared with GStreamer's nvcodec elements
+#[derive(Debug, Clone)]
+pub struct CudaContext(gst::Object);
+
+impl CudaContext {
+    /// Wraps a `CUcontext` of `device` that the application already created
+    ///
+    /// # Safety
+    /// `context` has to be a valid `CUcontext` for `device` that outlives every frame pushed with it.
+    pub unsafe fn wrap(context: *mut c_void, device: i32) -> anyhow::Result<Self> {
+        let (major, minor, ..) = gst::version();
+        if (major, minor) < (1, 24) {
+            return Err(anyhow::Error::msg(format!(
+                "CUDA frames need GStreamer 1.24, found {major}.{minor}"
+            )));
+        }
+
+        let context = gst_cuda_context_new_wrapped(context, device);
+        if context.is_null() {
+            return Err(anyhow::Error::msg("Could not wrap the CUDA context"));
+        }
+
+        Ok(CudaContext(from_glib_full(context)))
+    }
+}
+
+/// A frame in CUDA device memory, which `nvh264enc` can encode without copying it
+///
+/// Frames are pushed with `memory:CUDAMemory` caps,
+/// elements between the source and the encoder have to accept them or the pipeline won't link.<br>
+/// The frame has to be in the format and size of the video,
+/// only its strides and plane offsets can differ.
+pub struct CudaFrame {
+    pub context: CudaContext,
+    /// The `CUdeviceptr` of the start of the frame
+    pub device_ptr: u64,
+    /// Where each plane starts, in bytes
+    pub offsets: Vec<usize>,
+    /// The pitch of each plane in bytes
+    pub strides: Vec<i32>,
+    /// Called once the pipeline is done with the memory, e.g. to give it back to a pool
+    pub release: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl CudaFrame {
+    /// Wraps the device memory in a buffer of `GstCudaMemory`
+    pub(crate) fn into_buffer(self, video_info: &VideoInfo) -> anyhow::Result<gst::Buffer> {
+        let info = VideoInfo::builder(video_info.format(), video_info.width(), video_info.height())
+            .fps(video_info.fps())
+            .offset(&self.offsets)
+            .stride(&self.strides)
+            .build()?;
+
+        let release = Box::into_raw(Box::new(self.release));
+        let memory = unsafe {
+            let memory = gst_cuda_allocator_alloc_wrapped(
+                ptr::null_mut(),
+                self.context.0.to_glib_none().0,
+                ptr::null_mut(),
+                info.to_glib_none().0,
+                self.device_ptr,
+                release as gpointer,
+                Some(release_frame),
+            );
+            if memory.is_null() {
+                release_frame(release as gpointer);
+                return Err(anyhow::Error::msg("Could not wrap the CUDA memory"));
+            }
+            from_glib_full::<_, gst::Memory>(memory)
+        };
+
+        let mut buffer = gst::Buffer::new();
+        buffer.get_mut().unwrap().append_memory(memory);
+
+        Ok(buffer)
+    }
+}
+
+unsafe extern "C" fn release_frame(user_data: gpointer) {
+    let release = Box::from_raw(user_data as *mut Option<Box<dyn FnOnce() + Send>>);
+    if let Some(release) = *release {
+        release();
+    }
+}
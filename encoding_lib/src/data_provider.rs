@@ -1,12 +1,20 @@
 use gst_app::AppSrc;
 
-use gst::{prelude::*, MessageView};
+use std::sync::{Arc, Mutex};
+
+use gst::{prelude::*, MessageView, Pipeline};
 use gst_video::VideoInfo;
 use gstreamer as gst;
 use gstreamer_app as gst_app;
 use gstreamer_video as gst_video;
 
-use crate::{pipeline::init_pipeline, VideoSettings};
+use crate::{
+    controller::EncodingController,
+    error::PipelineError,
+    pipeline::{finish_partial_output, init_pipeline, init_pipeline_with_launch},
+    stats::StatsCollector,
+    EncodeStats, MessageCallback, VideoSettings,
+};
 
 pub enum DataGenReturn {
     Result(anyhow::Result<()>),
@@ -124,6 +132,13 @@ impl<S: Send + Sync + Clone + 'static> EnoughData<S, Option<()>> for Option<()>
     }
 }
 
+/// Encodes a video using a custom data provider
+///
+/// If `need_data` or `enough_data` return an error the pipeline is torn down
+/// and the error is returned.<br>
+/// Errors from the pipeline itself are returned as a [`PipelineError`].
+///
+/// Blocks the current thread till the encoding is done
 pub fn encode_video<
     S: Send + Sync + Clone + 'static,
     O: Into<DataGenReturn> + 'static,
@@ -135,22 +150,206 @@ pub fn encode_video<
     need_data: P,
     enough_data: Option<E>,
     state: S,
-) {
-    let (pipeline, appsrc, video_info) = init_pipeline(output_path, video_settings.clone());
+) -> anyhow::Result<EncodeStats> {
+    let (pipeline, appsrc, video_info) = init_pipeline(output_path, video_settings.clone())?;
+
+    run_pipeline(
+        &pipeline,
+        &appsrc,
+        video_info,
+        video_settings,
+        need_data,
+        enough_data,
+        state,
+    )
+}
+
+/// The same as [`encode_video`] but everything after the appsrc is built from a `gst-launch` style description,
+/// e.g. `videoconvert ! x264enc ! mp4mux ! filesink location=out.mp4`
+///
+/// Only the size, framerate and format are used from `video_settings`.
+///
+/// Blocks the current thread till the encoding is done
+pub fn encode_video_with_launch<
+    S: Send + Sync + Clone + 'static,
+    O: Into<DataGenReturn> + 'static,
+    P: DataProvider<S, O> + Send + Sync + 'static,
+    E: EnoughData<S, O> + Send + Sync + 'static,
+>(
+    description: &str,
+    video_settings: VideoSettings,
+    need_data: P,
+    enough_data: Option<E>,
+    state: S,
+) -> anyhow::Result<EncodeStats> {
+    let (pipeline, appsrc, video_info) =
+        init_pipeline_with_launch(description, video_settings.clone())?;
+
+    run_pipeline(
+        &pipeline,
+        &appsrc,
+        video_info,
+        video_settings,
+        need_data,
+        enough_data,
+        state,
+    )
+}
+
+/// Starts the pipeline and blocks till it's done
+fn run_pipeline<
+    S: Send + Sync + Clone + 'static,
+    O: Into<DataGenReturn> + 'static,
+    P: DataProvider<S, O> + Send + Sync + 'static,
+    E: EnoughData<S, O> + Send + Sync + 'static,
+>(
+    pipeline: &Pipeline,
+    appsrc: &AppSrc,
+    video_info: VideoInfo,
+    video_settings: VideoSettings,
+    need_data: P,
+    enough_data: Option<E>,
+    state: S,
+) -> anyhow::Result<EncodeStats> {
+    let on_message = video_settings.on_message.clone();
+    let atomic_output = video_settings.atomic_output;
+    let stats = StatsCollector::install(
+        pipeline,
+        &appsrc.static_pad("src").unwrap(),
+        &video_settings.limits,
+    );
+
+    let callback_error = start_pipeline(
+        pipeline,
+        appsrc,
+        video_info,
+        video_settings,
+        need_data,
+        enough_data,
+        state,
+    );
+
+    let result = wait_for_eos(pipeline, on_message.as_ref());
+
+    // Errors from the callbacks are also on the bus but the original error is more useful
+    let error = callback_error.lock().unwrap().take();
+    finish_partial_output(pipeline, atomic_output, result.is_ok() && error.is_none())?;
+    match error {
+        Some(e) => Err(e),
+        None => {
+            result?;
+            Ok(stats.stats())
+        }
+    }
+}
+
+/// Starts encoding a video using a custom data provider without blocking
+///
+/// The returned [`EncodingController`] has to be polled to drive the bus,
+/// which lets GUI apps run the encode from their own event loop.
+pub fn encode_video_nonblocking<
+    S: Send + Sync + Clone + 'static,
+    O: Into<DataGenReturn> + 'static,
+    P: DataProvider<S, O> + Send + Sync + 'static,
+    E: EnoughData<S, O> + Send + Sync + 'static,
+>(
+    output_path: String,
+    video_settings: VideoSettings,
+    need_data: P,
+    enough_data: Option<E>,
+    state: S,
+) -> anyhow::Result<EncodingController> {
+    let (pipeline, appsrc, video_info) = init_pipeline(output_path, video_settings.clone())?;
+    let on_message = video_settings.on_message.clone();
+    let atomic_output = video_settings.atomic_output;
+
+    let callback_error = start_pipeline(
+        &pipeline,
+        &appsrc,
+        video_info,
+        video_settings,
+        need_data,
+        enough_data,
+        state,
+    );
+
+    Ok(EncodingController::new(
+        pipeline,
+        on_message,
+        callback_error,
+        atomic_output,
+    ))
+}
+
+/// The first error returned by a data provider callback
+pub(crate) type CallbackError = Arc<Mutex<Option<anyhow::Error>>>;
+
+/// Hooks the data provider up to the appsrc and starts the pipeline
+///
+/// Errors from the callbacks are posted on the bus, which stops the pipeline,
+/// and kept in the returned [`CallbackError`].
+pub(crate) fn start_pipeline<
+    S: Send + Sync + Clone + 'static,
+    O: Into<DataGenReturn> + 'static,
+    P: DataProvider<S, O> + Send + Sync + 'static,
+    E: EnoughData<S, O> + Send + Sync + 'static,
+>(
+    pipeline: &Pipeline,
+    appsrc: &AppSrc,
+    video_info: VideoInfo,
+    video_settings: VideoSettings,
+    need_data: P,
+    enough_data: Option<E>,
+    state: S,
+) -> CallbackError {
+    let callback_error = set_data_provider(
+        appsrc,
+        video_info,
+        video_settings,
+        need_data,
+        enough_data,
+        state,
+    );
+
+    pipeline.set_state(gst::State::Playing).unwrap();
+
+    callback_error
+}
 
+/// Hooks the data provider up to the appsrc without starting the pipeline,
+/// for pipelines with more than one appsrc
+pub(crate) fn set_data_provider<
+    S: Send + Sync + Clone + 'static,
+    O: Into<DataGenReturn> + 'static,
+    P: DataProvider<S, O> + Send + Sync + 'static,
+    E: EnoughData<S, O> + Send + Sync + 'static,
+>(
+    appsrc: &AppSrc,
+    video_info: VideoInfo,
+    video_settings: VideoSettings,
+    need_data: P,
+    enough_data: Option<E>,
+    state: S,
+) -> CallbackError {
     let state_clone = state.clone();
 
     let settings_clone = video_settings.clone();
 
+    let callback_error = CallbackError::default();
+    let need_data_error = callback_error.clone();
+    let enough_data_error = callback_error.clone();
+
     let mut builder = gst_app::AppSrcCallbacks::builder().need_data(move |appsrc, len| {
         let state = state.clone();
-        need_data.need_data(appsrc, &video_info, &video_settings, len, state);
+        let result = need_data.need_data(appsrc, &video_info, &video_settings, len, state);
+        report_callback_error(appsrc, &need_data_error, result.into());
     });
 
     builder = if let Some(func) = enough_data {
         builder.enough_data(move |appsrc| {
             let state = state_clone.clone();
-            func.enough_data(appsrc, &settings_clone, state);
+            let result = func.enough_data(appsrc, &settings_clone, state);
+            report_callback_error(appsrc, &enough_data_error, result.into());
         })
     } else {
         builder
@@ -158,17 +357,45 @@ pub fn encode_video<
 
     appsrc.set_callbacks(builder.build());
 
-    pipeline.set_state(gst::State::Playing).unwrap();
+    callback_error
+}
+
+/// Posts an error returned by a callback on the bus so the pipeline gets torn down
+fn report_callback_error(appsrc: &AppSrc, callback_error: &CallbackError, result: DataGenReturn) {
+    let result: anyhow::Result<()> = result.into();
+
+    if let Err(e) = result {
+        let message = gst::message::Error::builder(gst::LibraryError::Failed, &format!("{e:#}"))
+            .src(appsrc)
+            .build();
+        let _ = appsrc.post_message(message);
+
+        callback_error.lock().unwrap().get_or_insert(e);
+    }
+}
 
+/// Watches the bus of a playing pipeline till the stream ends, then shuts the pipeline down
+///
+/// Returns the first error posted on the bus, which also stops the pipeline.
+pub(crate) fn wait_for_eos(
+    pipeline: &Pipeline,
+    on_message: Option<&MessageCallback>,
+) -> Result<(), PipelineError> {
     let bus = pipeline.bus().unwrap();
+    let mut result = Ok(());
 
     for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        if let Some(callback) = on_message {
+            (callback.0)(&msg);
+        }
+
         match msg.view() {
             MessageView::Eos(_) => break,
             MessageView::Error(e) => {
-                pipeline.set_state(gst::State::Null).unwrap();
-                println!("Error! {e:?}");
+                result = Err(PipelineError::from_message(&e));
+                break;
             }
+            _ if on_message.is_some() => {}
             MessageView::Progress(p) => println!("{p:?}"),
             MessageView::Warning(w) => println!("Warning: {w:?}"),
             MessageView::Info(i) => println!("Info: {i:?}"),
@@ -179,4 +406,6 @@ pub fn encode_video<
     println!("ending pipeline");
 
     pipeline.set_state(gst::State::Null).unwrap();
+
+    result
 }
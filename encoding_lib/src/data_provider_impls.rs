@@ -1,22 +1,165 @@
-use std::{
-    ops::Deref,
-    sync::{mpsc::Receiver, Arc, Mutex, RwLock},
-};
+#[cfg(feature = "image")]
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{mpsc::Receiver, Arc, Mutex};
+#[cfg(feature = "image")]
+use std::{ops::Deref, sync::RwLock, time::Duration};
 
 use gst_app::AppSrc;
 
+#[cfg(feature = "image")]
+use gst_video::VideoFormat;
 use gst_video::VideoInfo;
 use gstreamer as gst;
 use gstreamer_app as gst_app;
 use gstreamer_video as gst_video;
-use image::{DynamicImage, ImageBuffer, Pixel};
+#[cfg(feature = "image")]
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
 
-use crate::VideoSettings;
+#[cfg(feature = "image")]
+use crate::TimedFrame;
 
+#[cfg(feature = "image")]
+use crate::pipeline::{acquire_frame_buffer, frame_pts, report_frame_received, scale_pts};
+#[cfg(feature = "image")]
+use crate::ScalingMode;
+use crate::{pipeline::set_frame_timing, VideoSettings};
+
+/// Pushes frames of raw bytes sent through a [`RawFrameSender`](crate::RawFrameSender)
+///
+/// The bytes are handed to gstreamer as is, without being copied.
+pub fn raw_reciever_data_provider<const BUFFER_SIZE: usize>(
+    appsrc: &AppSrc,
+    _video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    _length: u32,
+    state: (Arc<Mutex<u64>>, Arc<Mutex<Receiver<gst::Buffer>>>),
+) {
+    let mut frame_num = state.0.lock().unwrap();
+    let receiver = state.1.lock().unwrap();
+
+    for _ in 0..BUFFER_SIZE {
+        if let Ok(mut buffer) = receiver.recv() {
+            set_frame_timing(buffer.get_mut().unwrap(), *frame_num, video_settings);
+            *frame_num += 1;
+
+            // The stream may have been ended by an EncodeHandle
+            if appsrc.push_buffer(buffer).is_err() {
+                return;
+            }
+        } else {
+            println!("End of video stream detected!");
+            let _ = appsrc.end_of_stream();
+            return;
+        }
+    }
+}
+
+/// Copies tightly packed planes into `dest`, which is laid out as described by `video_info`
+///
+/// Each plane has to contain exactly its rows with no padding in between,
+/// e.g. an I420 frame is a full size Y plane followed by quarter size U and V planes
+/// while NV12 has a Y plane and a single interleaved UV plane.<br>
+/// Packed formats like `Bgrx` are just a single plane.
+pub fn write_planes(
+    planes: &[&[u8]],
+    dest: &mut [u8],
+    video_info: &VideoInfo,
+) -> anyhow::Result<()> {
+    if planes.len() != video_info.n_planes() as usize {
+        return Err(anyhow::Error::msg(format!(
+            "{:?} frames have {} planes but {} were given",
+            video_info.format(),
+            video_info.n_planes(),
+            planes.len()
+        )));
+    }
+
+    for (plane, data) in planes.iter().enumerate() {
+        let (row_size, rows) = plane_size(video_info, plane);
+
+        if data.len() != row_size * rows {
+            return Err(anyhow::Error::msg(format!(
+                "Plane {plane} is {} bytes but should be {} bytes",
+                data.len(),
+                row_size * rows
+            )));
+        }
+
+        let stride = video_info.stride()[plane] as usize;
+        let offset = video_info.offset()[plane];
+
+        for (row, line) in data.chunks_exact(row_size).enumerate() {
+            let start = offset + row * stride;
+            dest[start..start + row_size].copy_from_slice(line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the size in bytes of each plane of a frame when it's tightly packed,
+/// the way [`write_planes`] expects them
+pub(crate) fn packed_plane_sizes(video_info: &VideoInfo) -> Vec<usize> {
+    (0..video_info.n_planes() as usize)
+        .map(|plane| {
+            let (row_size, rows) = plane_size(video_info, plane);
+            row_size * rows
+        })
+        .collect()
+}
+
+/// Gets the size in bytes of each row of a plane without padding, and how many rows it has
+fn plane_size(video_info: &VideoInfo, plane: usize) -> (usize, usize) {
+    let format_info = video_info.format_info();
+    let component = format_info
+        .plane()
+        .iter()
+        .take(format_info.n_components() as usize)
+        .position(|p| *p as usize == plane)
+        .unwrap_or(0);
+
+    let width = format_info.scale_width(component as u8, video_info.width()) as usize;
+    let height = format_info.scale_height(component as u8, video_info.height()) as usize;
+
+    (
+        width * format_info.pixel_stride()[component] as usize,
+        height,
+    )
+}
+
+#[cfg(feature = "image")]
+#[allow(clippy::type_complexity)]
 pub fn reciever_data_provider<
     Format: Pixel<Subpixel = u8> + 'static,
     Container: Deref<Target = [Format::Subpixel]>,
     const BUFFER_SIZE: usize,
+>(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    length: u32,
+    state: (
+        Arc<Mutex<u64>>,
+        Arc<Mutex<Receiver<ImageBuffer<Format, Container>>>>,
+        Arc<Mutex<Option<gst::Buffer>>>,
+    ),
+) -> anyhow::Result<()> {
+    sized_reciever_data_provider(
+        appsrc,
+        video_info,
+        video_settings,
+        length,
+        (state.0, state.1, state.2, BUFFER_SIZE),
+    )
+}
+
+/// The same as [`reciever_data_provider`] but with the number of frames to wait for
+/// as the last element of the state instead of a const generic
+#[cfg(feature = "image")]
+#[allow(clippy::type_complexity)]
+pub fn sized_reciever_data_provider<
+    Format: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [Format::Subpixel]>,
 >(
     appsrc: &AppSrc,
     video_info: &VideoInfo,
@@ -25,109 +168,379 @@ pub fn reciever_data_provider<
     state: (
         Arc<Mutex<u64>>,
         Arc<Mutex<Receiver<ImageBuffer<Format, Container>>>>,
+        Arc<Mutex<Option<gst::Buffer>>>,
+        usize,
     ),
-) {
-    let mut frame_num = state.0.lock().unwrap();
+) -> anyhow::Result<()> {
     let receiver = state.1.lock().unwrap();
-    println!("frames requested, currently provided {frame_num} frames of video");
+    println!(
+        "frames requested, currently provided {} frames of video",
+        state.0.lock().unwrap()
+    );
 
-    for _ in 0..BUFFER_SIZE {
-        let mut buffer = gst::Buffer::with_size(video_info.size()).unwrap();
-        if let Ok(image) = receiver.recv() {
-            let buffer = buffer.get_mut().unwrap();
+    let interval = Duration::from_nanos(frame_pts(1, video_settings.framerate).nseconds());
+    let fill_missing_frames = video_settings.fill_missing_frames;
+
+    push_received_frames(
+        appsrc,
+        video_info,
+        video_settings,
+        &state.0,
+        Some(&state.2),
+        state.3,
+        || {
+            if !fill_missing_frames {
+                return receiver.recv().ok().map(|image| (image, None)).into();
+            }
+
+            match receiver.recv_timeout(interval) {
+                Ok(image) => NextFrame::Frame(image, None),
+                Err(RecvTimeoutError::Timeout) => NextFrame::Stalled,
+                Err(RecvTimeoutError::Disconnected) => NextFrame::End,
+            }
+        },
+    )
+}
 
-            buffer
-                .set_pts(*frame_num * (1_000 / video_settings.framerate) * gst::ClockTime::MSECOND);
-
-            let mut pixels = image.pixels().map(|p| p.to_bgra());
-
-            let mut vframe =
-                gst_video::VideoFrameRef::from_buffer_ref_writable(buffer, &video_info).unwrap();
-
-            let width = vframe.width() as usize;
-            let height = vframe.height() as usize;
-            let stride = vframe.plane_stride()[0] as usize;
-
-            for line in vframe
-                .plane_data_mut(0)
-                .unwrap()
-                .chunks_exact_mut(stride)
-                .take(height)
-            {
-                for pixel in line[..(4 * width)].chunks_exact_mut(4) {
-                    if let Some(frame_pixels) = pixels.next() {
-                        pixel[0] = frame_pixels[0];
-                        pixel[1] = frame_pixels[1];
-                        pixel[2] = frame_pixels[2];
-                        pixel[3] = frame_pixels[3];
+/// The same as [`reciever_data_provider`] but using the timestamps the frames were sent with
+#[cfg(feature = "image")]
+#[allow(clippy::type_complexity)]
+pub fn timed_reciever_data_provider<
+    Format: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [Format::Subpixel]>,
+    const BUFFER_SIZE: usize,
+>(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    _length: u32,
+    state: (
+        Arc<Mutex<u64>>,
+        Arc<Mutex<Receiver<TimedFrame<Format, Container>>>>,
+    ),
+) -> anyhow::Result<()> {
+    let receiver = state.1.lock().unwrap();
+    push_received_frames(
+        appsrc,
+        video_info,
+        video_settings,
+        &state.0,
+        None,
+        BUFFER_SIZE,
+        || {
+            receiver
+                .recv()
+                .ok()
+                .map(|frame| (frame.image, Some(frame.pts)))
+                .into()
+        },
+    )
+}
+
+/// The same as [`reciever_data_provider`] but reading from a tokio channel
+#[cfg(all(feature = "image", feature = "tokio"))]
+#[allow(clippy::type_complexity)]
+pub fn async_reciever_data_provider<
+    Format: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [Format::Subpixel]>,
+    const BUFFER_SIZE: usize,
+>(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    _length: u32,
+    state: (
+        Arc<Mutex<u64>>,
+        Arc<Mutex<tokio::sync::mpsc::Receiver<ImageBuffer<Format, Container>>>>,
+    ),
+) -> anyhow::Result<()> {
+    let mut receiver = state.1.lock().unwrap();
+    println!(
+        "frames requested, currently provided {} frames of video",
+        state.0.lock().unwrap()
+    );
+    push_received_frames(
+        appsrc,
+        video_info,
+        video_settings,
+        &state.0,
+        None,
+        BUFFER_SIZE,
+        || receiver.blocking_recv().map(|image| (image, None)).into(),
+    )
+}
+
+/// Pushes the next frame of an iterator each time data is requested
+#[cfg(feature = "image")]
+pub fn iter_data_provider<
+    Format: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [Format::Subpixel]>,
+    Frames: Iterator<Item = ImageBuffer<Format, Container>>,
+>(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    _length: u32,
+    state: (Arc<Mutex<u64>>, Arc<Mutex<Frames>>),
+) -> anyhow::Result<()> {
+    let mut frames = state.1.lock().unwrap();
+    push_received_frames(
+        appsrc,
+        video_info,
+        video_settings,
+        &state.0,
+        None,
+        1,
+        || frames.next().map(|image| (image, None)).into(),
+    )
+}
+
+/// What a frame source handed to [`push_received_frames`] produced
+#[cfg(feature = "image")]
+enum NextFrame<Format: Pixel, Container> {
+    /// A frame, with its timestamp if it has its own
+    Frame(ImageBuffer<Format, Container>, Option<Duration>),
+    /// No frame arrived within a frame interval
+    Stalled,
+    /// There are no more frames
+    End,
+}
+
+#[cfg(feature = "image")]
+impl<Format: Pixel, Container> From<Option<(ImageBuffer<Format, Container>, Option<Duration>)>>
+    for NextFrame<Format, Container>
+{
+    fn from(frame: Option<(ImageBuffer<Format, Container>, Option<Duration>)>) -> Self {
+        match frame {
+            Some((image, pts)) => NextFrame::Frame(image, pts),
+            None => NextFrame::End,
+        }
+    }
+}
+
+/// Pushes up to `buffer_size` frames from `next_frame`,
+/// ending the stream once it runs out of frames
+///
+/// When `last_frame` is given, the last frame pushed is kept in it
+/// and pushed again whenever the source stalls.
+#[cfg(feature = "image")]
+fn push_received_frames<
+    Format: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [Format::Subpixel]>,
+>(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    frame_num: &Mutex<u64>,
+    last_frame: Option<&Mutex<Option<gst::Buffer>>>,
+    buffer_size: usize,
+    mut next_frame: impl FnMut() -> NextFrame<Format, Container>,
+) -> anyhow::Result<()> {
+    let mut frame_num = frame_num.lock().unwrap();
+
+    for _ in 0..buffer_size {
+        let (image, pts) = match next_frame() {
+            NextFrame::Frame(image, pts) => (image, pts),
+            NextFrame::Stalled => {
+                let repeated = last_frame
+                    .and_then(|last_frame| last_frame.lock().unwrap().as_ref().map(|b| b.copy()));
+
+                if let Some(mut buffer) = repeated {
+                    set_frame_timing(buffer.get_mut().unwrap(), *frame_num, video_settings);
+                    *frame_num += 1;
+
+                    if appsrc.push_buffer(buffer).is_err() {
+                        return Ok(());
                     }
                 }
+                continue;
+            }
+            NextFrame::End => {
+                println!("End of video stream detected!");
+                let _ = appsrc.end_of_stream();
+                return Ok(());
+            }
+        };
+
+        let frame_info = frame_info(
+            appsrc,
+            video_info,
+            video_settings,
+            image.width(),
+            image.height(),
+        )?;
+        let mut buffer = acquire_frame_buffer(appsrc, &frame_info)?;
+
+        {
+            let buffer = buffer.get_mut().unwrap();
+
+            match pts {
+                Some(pts) => {
+                    report_frame_received(*frame_num, video_settings);
+                    buffer.set_pts(scale_pts(
+                        gst::ClockTime::from_nseconds(pts.as_nanos() as u64),
+                        video_settings,
+                    ))
+                }
+                None => set_frame_timing(buffer, *frame_num, video_settings),
             }
+
+            write_frame(&image, buffer, &frame_info);
             *frame_num += 1;
-        } else {
-            println!("End of video stream detected!");
-            let _ = appsrc.end_of_stream();
-            return;
         }
 
-        let _ = appsrc.push_buffer(buffer).unwrap();
+        if let Some(last_frame) = last_frame {
+            *last_frame.lock().unwrap() = Some(buffer.clone());
+        }
+
+        // The stream may have been ended by an EncodeHandle
+        if appsrc.push_buffer(buffer).is_err() {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the `VideoInfo` a frame of the given size has to be written with,
+/// switching the caps of the appsrc over when the size changes
+///
+/// Frames that aren't the size of the video are only allowed when the pipeline scales them.
+#[cfg(feature = "image")]
+pub(crate) fn frame_info(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<VideoInfo> {
+    let frame_info = if width == video_info.width() && height == video_info.height() {
+        video_info.clone()
+    } else if video_settings.scaling == ScalingMode::Strict {
+        return Err(anyhow::Error::msg(format!(
+            "Frame is {width}x{height} but the video is {}x{}, set `scaling` to allow resizing frames",
+            video_info.width(),
+            video_info.height()
+        )));
+    } else {
+        gst_video::VideoInfo::builder(video_info.format(), width, height)
+            .fps(video_info.fps())
+            .colorimetry(&video_info.colorimetry())
+            .build()?
+    };
+
+    let caps = frame_info.to_caps()?;
+    if appsrc.caps().as_ref() != Some(&caps) {
+        appsrc.set_caps(Some(&caps));
+    }
+
+    Ok(frame_info)
+}
+
+/// Copies an image into a buffer laid out as described by `video_info`
+#[cfg(feature = "image")]
+pub(crate) fn write_frame<
+    Format: Pixel<Subpixel = u8> + 'static,
+    Container: Deref<Target = [Format::Subpixel]>,
+>(
+    image: &ImageBuffer<Format, Container>,
+    buffer: &mut gst::BufferRef,
+    video_info: &VideoInfo,
+) {
+    // Pixels are converted to whatever layout the video format has
+    let (pixel_size, convert): (usize, fn(&Format) -> [u8; 4]) = match video_info.format() {
+        VideoFormat::Rgba | VideoFormat::Rgbx => (4, |p| p.to_rgba().0),
+        VideoFormat::Rgb => (3, |p| {
+            let [r, g, b] = p.to_rgb().0;
+            [r, g, b, 0]
+        }),
+        VideoFormat::Bgr => (3, |p| {
+            let [b, g, r] = p.to_bgr().0;
+            [b, g, r, 0]
+        }),
+        VideoFormat::Gray8 => (1, |p| [p.to_luma().0[0], 0, 0, 0]),
+        _ => (4, |p| p.to_bgra().0),
+    };
+    let mut pixels = image.pixels().map(convert);
+
+    let mut vframe =
+        gst_video::VideoFrameRef::from_buffer_ref_writable(buffer, video_info).unwrap();
+
+    let width = vframe.width() as usize;
+    let height = vframe.height() as usize;
+    let stride = vframe.plane_stride()[0] as usize;
+
+    for line in vframe
+        .plane_data_mut(0)
+        .unwrap()
+        .chunks_exact_mut(stride)
+        .take(height)
+    {
+        for pixel in line[..(pixel_size * width)].chunks_exact_mut(pixel_size) {
+            if let Some(frame_pixel) = pixels.next() {
+                pixel.copy_from_slice(&frame_pixel[..pixel_size]);
+            }
+        }
     }
 }
 
+#[cfg(feature = "image")]
 pub fn vec_data_provider(
     appsrc: &AppSrc,
     video_info: &VideoInfo,
     video_settings: &VideoSettings,
     _length: u32,
     state: (Arc<Mutex<u64>>, Arc<RwLock<Vec<DynamicImage>>>),
-) {
+) -> anyhow::Result<()> {
     let mut frame_num = state.0.lock().unwrap();
     let images = state.1.read().unwrap();
 
     if *frame_num as usize == images.len() {
         let _ = appsrc.end_of_stream().unwrap();
-        return;
+        return Ok(());
     }
 
-    let mut buffer = gst::Buffer::with_size(video_info.size()).unwrap();
+    let image = images.get(*frame_num as usize).unwrap();
+    let frame_info = frame_info(
+        appsrc,
+        video_info,
+        video_settings,
+        image.width(),
+        image.height(),
+    )?;
+    let mut buffer = acquire_frame_buffer(appsrc, &frame_info)?;
 
     {
-        let image = images.get(*frame_num as usize).unwrap();
         let buffer = buffer.get_mut().unwrap();
 
-        buffer.set_pts(
-            *frame_num * (1000 / video_settings.framerate) as u64 * gst::ClockTime::MSECOND,
-        );
-
-        // Expensive clone, try to remove
-        let image_rgb = image.clone().into_rgba8();
-        let mut pixels = image_rgb.pixels().map(|p| p.0);
-
-        let mut vframe =
-            gst_video::VideoFrameRef::from_buffer_ref_writable(buffer, &video_info).unwrap();
-
-        let width = vframe.width() as usize;
-        let height = vframe.height() as usize;
-        let stride = vframe.plane_stride()[0] as usize;
+        set_frame_timing(buffer, *frame_num, video_settings);
 
-        for line in vframe
-            .plane_data_mut(0)
-            .unwrap()
-            .chunks_exact_mut(stride)
-            .take(height)
-        {
-            for pixel in line[..(4 * width)].chunks_exact_mut(4) {
-                if let Some(frame_pixels) = pixels.next() {
-                    pixel[0] = frame_pixels[0];
-                    pixel[1] = frame_pixels[1];
-                    pixel[2] = frame_pixels[2];
-                    pixel[3] = 0;
-                }
-            }
-        }
+        write_dynamic_frame(image, buffer, &frame_info);
 
         *frame_num += 1;
     }
 
     let _ = appsrc.push_buffer(buffer).unwrap();
+
+    Ok(())
+}
+
+/// Copies a `DynamicImage` into a buffer laid out as described by `video_info`
+///
+/// 8 bit images are read in place, only 16 bit images need to be converted first.
+#[cfg(feature = "image")]
+pub(crate) fn write_dynamic_frame(
+    image: &DynamicImage,
+    buffer: &mut gst::BufferRef,
+    video_info: &VideoInfo,
+) {
+    match image {
+        DynamicImage::ImageLuma8(image) => write_frame(image, buffer, video_info),
+        DynamicImage::ImageLumaA8(image) => write_frame(image, buffer, video_info),
+        DynamicImage::ImageRgb8(image) => write_frame(image, buffer, video_info),
+        DynamicImage::ImageRgba8(image) => write_frame(image, buffer, video_info),
+        DynamicImage::ImageBgr8(image) => write_frame(image, buffer, video_info),
+        DynamicImage::ImageBgra8(image) => write_frame(image, buffer, video_info),
+        image => write_frame(&image.to_bgra8(), buffer, video_info),
+    }
 }
@@ -0,0 +1,87 @@
+use std::{fs::File, io::Write, path::PathBuf, ptr, sync::Mutex};
+
+use gst::DebugLevel;
+use gstreamer as gst;
+
+use crate::pipeline::init_encoder;
+
+/// The log function writing into the `log_file` of the last config, if it had one
+static LOG_FUNCTION: Mutex<Option<gst::DebugLogFunction>> = Mutex::new(None);
+
+/// Which GStreamer debug output to turn on, see [`init_encoder_with`]
+///
+/// This is the same as the `GST_DEBUG` and `GST_DEBUG_FILE` environment variables,
+/// e.g. `x264enc:5` is `categories: vec![("x264enc".into(), DebugLevel::Debug)]`.
+#[derive(Debug, Clone)]
+pub struct DebugConfig {
+    /// The level of every category not in `categories`
+    pub level: DebugLevel,
+    /// Levels for specific categories, names can use `*` as a wildcard like `GST_DEBUG`
+    pub categories: Vec<(String, DebugLevel)>,
+    /// Where to write the output instead of stderr
+    pub log_file: Option<PathBuf>,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        DebugConfig {
+            level: DebugLevel::Warning,
+            categories: Vec::new(),
+            log_file: None,
+        }
+    }
+}
+
+/// Initializes GStreamer like [`init_encoder`] and turns on the debug output described by `config`
+///
+/// Can be called again to change the output, later configs replace earlier ones.
+pub fn init_encoder_with(config: DebugConfig) -> anyhow::Result<()> {
+    init_encoder();
+
+    gst::debug_set_active(true);
+    gst::debug_set_default_threshold(config.level);
+    for (name, level) in &config.categories {
+        gst::debug_set_threshold_for_name(name, *level);
+    }
+
+    let mut log_function = LOG_FUNCTION.lock().unwrap();
+    match log_function.take() {
+        // The default stderr output was removed when the file was set up
+        Some(previous) if config.log_file.is_none() => {
+            gst::debug_remove_log_function(previous);
+            unsafe {
+                gst::ffi::gst_debug_add_log_function(
+                    Some(gst::ffi::gst_debug_log_default),
+                    ptr::null_mut(),
+                    None,
+                );
+            }
+        }
+        Some(previous) => gst::debug_remove_log_function(previous),
+        None if config.log_file.is_some() => gst::debug_remove_default_log_function(),
+        None => {}
+    }
+
+    if let Some(path) = &config.log_file {
+        let file = Mutex::new(File::create(path)?);
+
+        *log_function = Some(gst::debug_add_log_function(
+            move |category, level, file_name, function, line, object, message| {
+                if level > category.threshold() {
+                    return;
+                }
+
+                let object = object.map(|object| object.to_string()).unwrap_or_default();
+                let _ = writeln!(
+                    file.lock().unwrap(),
+                    "{:?} {} {file_name}:{line}:{function}:<{object}> {}",
+                    level,
+                    category.name(),
+                    message.get().unwrap_or_default(),
+                );
+            },
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use std::{path::Path, time::Duration};
+
+use gst::{glib, prelude::*, ClockTime, MessageView, Pipeline};
+use gst_app::AppSink;
+use gst_video::VideoInfo;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+use image::{DynamicImage, ImageBuffer, Rgba};
+
+use crate::{
+    error::PipelineError,
+    pipeline::{init_encoder, unique_pipeline},
+};
+
+/// A frame decoded from a video file
+pub type DecodedImage = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+/// Reads the frames of a video file, built on `uridecodebin`
+///
+/// Frames are decoded as they're pulled, iterating ends at the end of the video
+/// or when decoding fails, which [`error`](Decoder::error) then returns.
+pub struct Decoder {
+    pipeline: Pipeline,
+    appsink: AppSink,
+    position: Option<Duration>,
+    error: Option<PipelineError>,
+}
+
+/// Opens the video at `path` to iterate over its frames as RGBA images
+pub fn decode_frames(path: impl AsRef<Path>) -> anyhow::Result<Decoder> {
+    Decoder::open(path)
+}
+
+/// Decodes the frame shown at `timestamp`, e.g. for a poster image
+pub fn extract_frame(path: impl AsRef<Path>, timestamp: Duration) -> anyhow::Result<DynamicImage> {
+    let mut decoder = Decoder::open(path)?;
+    decoder.seek(timestamp)?;
+
+    next_image(&mut decoder)?
+        .ok_or_else(|| anyhow::Error::msg(format!("The video has no frame at {timestamp:?}")))
+}
+
+/// Decodes a frame every `interval`, starting at the first frame, e.g. for a scrubber preview
+///
+/// When `max_size` is set frames are shrunk to fit inside it, keeping their aspect ratio.
+pub fn generate_thumbnails(
+    path: impl AsRef<Path>,
+    interval: Duration,
+    max_size: Option<(u32, u32)>,
+) -> anyhow::Result<Vec<DynamicImage>> {
+    if interval.is_zero() {
+        return Err(anyhow::Error::msg("The thumbnail interval can't be zero"));
+    }
+
+    let mut decoder = Decoder::open(path)?;
+    let duration = decoder.duration();
+    let mut thumbnails = Vec::new();
+    let mut timestamp = Duration::ZERO;
+
+    while duration.is_none_or(|duration| timestamp < duration) {
+        decoder.seek(timestamp)?;
+
+        match next_image(&mut decoder)? {
+            Some(image) => thumbnails.push(match max_size {
+                Some((width, height)) => image.thumbnail(width, height),
+                None => image,
+            }),
+            None => break,
+        }
+
+        timestamp += interval;
+    }
+
+    Ok(thumbnails)
+}
+
+/// Gets the next frame of `decoder`, turning a decoding error into an `Err`
+fn next_image(decoder: &mut Decoder) -> anyhow::Result<Option<DynamicImage>> {
+    match decoder.next() {
+        Some(image) => Ok(Some(DynamicImage::ImageRgba8(image))),
+        None => match decoder.error() {
+            Some(e) => Err(e.clone().into()),
+            None => Ok(None),
+        },
+    }
+}
+
+impl Decoder {
+    /// Opens the video at `path`, failing if it can't be read or has no video stream
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        init_encoder();
+
+        let path = path.as_ref().canonicalize()?;
+        let uri = glib::filename_to_uri(&path, None)?;
+
+        let pipeline = unique_pipeline("decoding pipeline");
+
+        let decodebin = gst::ElementFactory::make("uridecodebin", Some("decoder"))?;
+        decodebin.set_property("uri", uri.as_str());
+
+        let convert = gst::ElementFactory::make("videoconvert", Some("convert"))?;
+        let appsink = gst::ElementFactory::make("appsink", Some("sink"))?
+            .dynamic_cast::<AppSink>()
+            .unwrap();
+        appsink.set_caps(Some(
+            &gst::Caps::builder("video/x-raw")
+                .field("format", gst_video::VideoFormat::Rgba.to_str())
+                .build(),
+        ));
+        // Frames are pulled as fast as the caller wants them, not at the speed of the video
+        appsink.set_sync(false);
+        appsink.set_max_buffers(4);
+
+        pipeline.add_many(&[&decodebin, &convert, appsink.upcast_ref()])?;
+        convert.link(&appsink)?;
+
+        let pipeline_weak = pipeline.downgrade();
+        decodebin.connect_pad_added(move |_, pad| {
+            let pipeline = match pipeline_weak.upgrade() {
+                Some(pipeline) => pipeline,
+                None => return,
+            };
+
+            let is_video = pad
+                .current_caps()
+                .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("video/")))
+                .unwrap_or(false);
+
+            let sink_pad = if is_video {
+                pipeline
+                    .by_name("convert")
+                    .unwrap()
+                    .static_pad("sink")
+                    .unwrap()
+            } else {
+                // Other streams like audio have to go somewhere or the decoder stops
+                let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+                fakesink.set_property("sync", false);
+                pipeline.add(&fakesink).unwrap();
+                fakesink.sync_state_with_parent().unwrap();
+                fakesink.static_pad("sink").unwrap()
+            };
+
+            if !sink_pad.is_linked() {
+                pad.link(&sink_pad).unwrap();
+            }
+        });
+
+        let decoder = Decoder {
+            pipeline,
+            appsink,
+            position: None,
+            error: None,
+        };
+
+        decoder.pipeline.set_state(gst::State::Playing)?;
+        // Waits for the first frame so seeking works straight away
+        if decoder.pipeline.state(ClockTime::NONE).0.is_err() {
+            return Err(decoder
+                .take_bus_error()
+                .map(anyhow::Error::new)
+                .unwrap_or_else(|| anyhow::Error::msg("Could not start decoding")));
+        }
+
+        Ok(decoder)
+    }
+
+    /// The length of the video, if it's known
+    pub fn duration(&self) -> Option<Duration> {
+        self.pipeline
+            .query_duration::<ClockTime>()
+            .map(|duration| Duration::from_nanos(duration.nseconds()))
+    }
+
+    /// The timestamp of the last frame returned
+    pub fn position(&self) -> Option<Duration> {
+        self.position
+    }
+
+    /// The `VideoInfo` of the decoded frames
+    pub fn video_info(&self) -> Option<VideoInfo> {
+        let caps = self.appsink.static_pad("sink")?.current_caps()?;
+        VideoInfo::from_caps(&caps).ok()
+    }
+
+    /// The error that stopped decoding early, if any
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.error.as_ref()
+    }
+
+    /// Moves to `timestamp` so the next frame is the one shown at that time
+    ///
+    /// The seek is frame accurate, which means decoding from the keyframe before `timestamp`,
+    /// so it can take a while for videos with few keyframes.
+    pub fn seek(&mut self, timestamp: Duration) -> anyhow::Result<()> {
+        self.pipeline.seek_simple(
+            gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+            ClockTime::from_nseconds(timestamp.as_nanos() as u64),
+        )?;
+        self.pipeline.state(ClockTime::NONE).0?;
+        self.position = None;
+
+        Ok(())
+    }
+
+    /// Skips `frames - 1` frames and returns the one after them,
+    /// so `step(1)` is the same as `next()`
+    pub fn step(&mut self, frames: u32) -> Option<DecodedImage> {
+        for _ in 1..frames {
+            self.pull_sample()?;
+        }
+
+        self.next()
+    }
+
+    /// Pulls the next sample, or `None` at the end of the video or on an error
+    fn pull_sample(&mut self) -> Option<gst::Sample> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self.appsink.pull_sample() {
+            Ok(sample) => {
+                if let Some(pts) = sample.buffer().and_then(|buffer| buffer.pts()) {
+                    self.position = Some(Duration::from_nanos(pts.nseconds()));
+                }
+                Some(sample)
+            }
+            Err(_) => {
+                self.error = self.take_bus_error();
+                None
+            }
+        }
+    }
+
+    /// Pops messages off the bus until an error is found
+    fn take_bus_error(&self) -> Option<PipelineError> {
+        let bus = self.pipeline.bus().unwrap();
+
+        while let Some(msg) = bus.pop() {
+            if let MessageView::Error(e) = msg.view() {
+                return Some(PipelineError::from_message(&e));
+            }
+        }
+
+        None
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = DecodedImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.pull_sample()?;
+        let info = VideoInfo::from_caps(sample.caps()?).ok()?;
+        let buffer = sample.buffer()?.map_readable().ok()?;
+
+        let width = info.width() as usize;
+        let stride = info.stride()[0] as usize;
+
+        // Rows can be padded, so they're copied one at a time
+        let mut data = Vec::with_capacity(width * 4 * info.height() as usize);
+        for row in buffer.chunks(stride).take(info.height() as usize) {
+            data.extend_from_slice(&row[..width * 4]);
+        }
+
+        ImageBuffer::from_raw(info.width(), info.height(), data)
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
@@ -0,0 +1,65 @@
+use gst::{prelude::*, Caps};
+use gstreamer as gst;
+
+use crate::pipeline::init_encoder;
+
+/// An element factory installed on this system
+#[derive(Debug, Clone)]
+pub struct ElementInfo {
+    /// The name used to create the element, e.g. `x264enc`, which can be put into `VideoSettings`
+    pub name: String,
+    /// A human readable name, e.g. `x264 H.264 Encoder`
+    pub long_name: String,
+    pub description: String,
+    /// What the element can output
+    pub caps: Vec<Caps>,
+    /// Whether the element uses dedicated hardware like a GPU
+    pub hardware: bool,
+    /// How strongly GStreamer prefers this element over others doing the same thing
+    pub rank: gst::Rank,
+}
+
+impl ElementInfo {
+    fn from_factory(factory: &gst::ElementFactory) -> Self {
+        ElementInfo {
+            name: factory.name().to_string(),
+            long_name: factory.longname().to_owned(),
+            description: factory.description().to_owned(),
+            caps: factory
+                .static_pad_templates()
+                .iter()
+                .filter(|template| template.direction() == gst::PadDirection::Src)
+                .map(|template| template.caps())
+                .collect(),
+            hardware: factory.klass().split('/').any(|part| part == "Hardware"),
+            rank: factory.rank(),
+        }
+    }
+
+    /// Whether the element can output anything matching `caps`, e.g. `video/x-h264`
+    pub fn can_output(&self, caps: &Caps) -> bool {
+        self.caps.iter().any(|own| own.can_intersect(caps))
+    }
+}
+
+/// Lists every installed video encoder, best ranked first
+pub fn list_video_encoders() -> Vec<ElementInfo> {
+    list_factories(gst::ElementFactoryType::VIDEO_ENCODER)
+}
+
+/// Lists every installed muxer, best ranked first
+pub fn list_muxers() -> Vec<ElementInfo> {
+    list_factories(gst::ElementFactoryType::MUXER)
+}
+
+fn list_factories(factory_type: gst::ElementFactoryType) -> Vec<ElementInfo> {
+    init_encoder();
+
+    let mut factories = gst::ElementFactory::factories_with_type(factory_type, gst::Rank::None)
+        .iter()
+        .map(ElementInfo::from_factory)
+        .collect::<Vec<_>>();
+
+    factories.sort_by(|a, b| b.rank.cmp(&a.rank).then_with(|| a.name.cmp(&b.name)));
+    factories
+}
@@ -0,0 +1,96 @@
+use std::os::{
+    fd::{IntoRawFd, OwnedFd},
+    raw::c_int,
+};
+
+use gst::glib::translate::{from_glib_full, ToGlibPtr};
+use gst_video::VideoInfo;
+use gstreamer as gst;
+use gstreamer_video as gst_video;
+
+// The gstreamer-allocators bindings start at 0.19 and can't be used with gstreamer 0.18,
+// so the two functions needed are declared here, linking libgstallocators when `dmabuf` is enabled
+#[link(name = "gstallocators-1.0")]
+extern "C" {
+    fn gst_dmabuf_allocator_new() -> *mut gst::ffi::GstAllocator;
+    fn gst_dmabuf_allocator_alloc(
+        allocator: *mut gst::ffi::GstAllocator,
+        fd: c_int,
+        size: usize,
+    ) -> *mut gst::ffi::GstMemory;
+}
+
+/// A frame living in GPU memory, exported as a DMA-BUF
+///
+/// Pushing one hands the memory to the pipeline without copying it,
+/// encoders like `vaapih264enc` can read it straight from the GPU.<br>
+/// The frame has to be in the format and size of the video,
+/// only its strides and plane offsets can differ.
+#[derive(Debug)]
+pub struct DmaBufFrame {
+    /// The exported file descriptor, closed once the pipeline is done with the frame
+    pub fd: OwnedFd,
+    /// The size of the whole allocation in bytes
+    pub size: usize,
+    /// Where each plane starts, in bytes
+    pub offsets: Vec<usize>,
+    /// The length of a row of each plane in bytes, including padding
+    pub strides: Vec<i32>,
+}
+
+impl DmaBufFrame {
+    /// Describes a single plane frame, like the `Bgrx` frames the wgpu demo renders
+    pub fn new(fd: OwnedFd, size: usize, stride: i32) -> Self {
+        DmaBufFrame {
+            fd,
+            size,
+            offsets: vec![0],
+            strides: vec![stride],
+        }
+    }
+
+    /// Wraps the frame in a buffer of `GstDmaBufMemory` with a `VideoMeta` describing its layout
+    pub(crate) fn into_buffer(self, video_info: &VideoInfo) -> anyhow::Result<gst::Buffer> {
+        if self.offsets.len() != video_info.n_planes() as usize
+            || self.strides.len() != video_info.n_planes() as usize
+        {
+            return Err(anyhow::Error::msg(format!(
+                "{:?} frames have {} planes but the DMA-BUF describes {}",
+                video_info.format(),
+                video_info.n_planes(),
+                self.offsets.len()
+            )));
+        }
+
+        // The allocator only keeps a reference to the global dmabuf allocator
+        let allocator: gst::Allocator = unsafe { from_glib_full(gst_dmabuf_allocator_new()) };
+        let memory: gst::Memory = unsafe {
+            let memory = gst_dmabuf_allocator_alloc(
+                allocator.to_glib_none().0,
+                self.fd.into_raw_fd(),
+                self.size,
+            );
+            if memory.is_null() {
+                return Err(anyhow::Error::msg("Could not import the DMA-BUF"));
+            }
+            from_glib_full(memory)
+        };
+
+        let mut buffer = gst::Buffer::new();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.append_memory(memory);
+            gst_video::VideoMeta::add_full(
+                buffer,
+                gst_video::VideoFrameFlags::empty(),
+                video_info.format(),
+                video_info.width(),
+                video_info.height(),
+                &self.offsets,
+                &self.strides,
+            )?;
+        }
+
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::{Arc, Mutex};
+
+use gst::{prelude::*, Caps};
+use gstreamer as gst;
+
+use crate::{
+    data_provider::wait_for_eos,
+    pipeline::{init_encoder, init_pipeline_with_sink, set_frame_timing},
+    VideoSettings,
+};
+
+/// How many blank frames a dry run pushes through the pipeline
+const DRY_RUN_FRAMES: u64 = 5;
+
+/// What [`VideoSettings::dry_run`] found out about the pipeline the settings build
+#[derive(Debug, Clone)]
+pub struct DryRunReport {
+    /// The caps every element sent downstream, as `(element name, caps)` in the order they were negotiated
+    pub negotiated: Vec<(String, Caps)>,
+}
+
+impl DryRunReport {
+    /// The last caps sent by the element named `name`, e.g. `source`, `encoder` or `muxer`
+    pub fn caps_of(&self, name: &str) -> Option<&Caps> {
+        self.negotiated
+            .iter()
+            .rev()
+            .find(|(element, _)| element == name)
+            .map(|(_, caps)| caps)
+    }
+}
+
+pub(crate) fn dry_run(video_settings: &VideoSettings) -> anyhow::Result<DryRunReport> {
+    init_encoder();
+
+    let mut settings = video_settings.clone();
+    settings.preview = None;
+    settings.subtitles = None;
+    settings.live_source = false;
+
+    let sink = gst::ElementFactory::make("fakesink", Some("sink"))?;
+    sink.set_property("sync", false);
+
+    let (pipeline, appsrc, video_info) = init_pipeline_with_sink(sink, settings.clone())?;
+    replace_sinks(pipeline.upcast_ref())?;
+
+    let negotiated = Arc::new(Mutex::new(Vec::new()));
+    for element in pipeline.iterate_recurse().into_iter().flatten() {
+        for pad in element.src_pads() {
+            let negotiated = negotiated.clone();
+            let name = element.name().to_string();
+            pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+                if let Some(gst::PadProbeData::Event(event)) = &info.data {
+                    if let gst::EventView::Caps(caps) = event.view() {
+                        negotiated
+                            .lock()
+                            .unwrap()
+                            .push((name.clone(), caps.caps_owned()));
+                    }
+                }
+                gst::PadProbeReturn::Ok
+            });
+        }
+    }
+
+    pipeline.set_state(gst::State::Playing)?;
+
+    for frame_num in 0..DRY_RUN_FRAMES {
+        let mut buffer = gst::Buffer::with_size(video_info.size())?;
+        set_frame_timing(buffer.get_mut().unwrap(), frame_num, &settings);
+        if appsrc.push_buffer(buffer).is_err() {
+            break;
+        }
+    }
+    let _ = appsrc.end_of_stream();
+
+    wait_for_eos(&pipeline, settings.on_message.as_ref())?;
+
+    let negotiated = negotiated.lock().unwrap().clone();
+    Ok(DryRunReport { negotiated })
+}
+
+/// Swaps every sink the output target built for a `fakesink`, so nothing is written or sent
+///
+/// Plain bins, like the ones holding each rendition, are searched for sinks of their own
+/// while sinks that are bins, like `dashsink`, are replaced whole.
+fn replace_sinks(bin: &gst::Bin) -> anyhow::Result<()> {
+    for element in bin.children() {
+        if element.type_() == gst::Bin::static_type() {
+            replace_sinks(element.downcast_ref().unwrap())?;
+            continue;
+        }
+
+        let is_sink = element.element_flags().contains(gst::ElementFlags::SINK)
+            && element.src_pads().is_empty();
+        let is_fakesink = element
+            .factory()
+            .is_some_and(|factory| factory.name() == "fakesink");
+        if !is_sink || is_fakesink {
+            continue;
+        }
+
+        let upstream = element.sink_pads().iter().find_map(|pad| pad.peer());
+        let name = element.name();
+        bin.remove(&element)?;
+
+        let fakesink = gst::ElementFactory::make("fakesink", Some(&name))?;
+        fakesink.set_property("sync", false);
+        bin.add(&fakesink)?;
+        if let Some(upstream) = upstream {
+            upstream.link(&fakesink.static_pad("sink").unwrap())?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use gst::prelude::*;
+use gstreamer as gst;
+
+use crate::{
+    error::{MissingPlugin, MissingPlugins},
+    pipeline::{init_encoder, muxer_factory},
+    BackpressurePolicy, Container, OutputTarget, PreviewTarget, ScalingMode, SubtitleSource,
+    TextKind, VideoSettings,
+};
+
+/// Checks that every element `video_settings` needs is installed
+///
+/// Building a pipeline with a missing element panics,
+/// so this is worth calling first when the settings come from a user.
+pub fn check_requirements(video_settings: &VideoSettings) -> Result<(), MissingPlugins> {
+    init_encoder();
+
+    let missing = required_factories(video_settings)
+        .into_iter()
+        .filter(|factory| gst::ElementFactory::find(factory).is_none())
+        .map(|factory| MissingPlugin {
+            package: package_for(&factory),
+            alternatives: alternatives_for(&factory, video_settings),
+            factory,
+        })
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(MissingPlugins { missing })
+    }
+}
+
+/// The name of every element factory a pipeline made from `video_settings` uses
+fn required_factories(video_settings: &VideoSettings) -> Vec<String> {
+    let mut factories = vec!["appsrc", "videoconvert", "capsfilter"]
+        .into_iter()
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+
+    if video_settings.backpressure != BackpressurePolicy::Block {
+        factories.push("queue".to_owned());
+    }
+
+    if video_settings.rate_conversion {
+        factories.push("videorate".to_owned());
+    }
+
+    match video_settings.scaling {
+        ScalingMode::Strict => {}
+        ScalingMode::Crop => {
+            factories.push("aspectratiocrop".to_owned());
+            factories.push("videoscale".to_owned());
+        }
+        ScalingMode::Stretch | ScalingMode::Letterbox => factories.push("videoscale".to_owned()),
+    }
+
+    if !video_settings.overlays.is_empty() {
+        factories.push("gdkpixbufoverlay".to_owned());
+    }
+
+    if let Some(text_overlay) = &video_settings.text_overlay {
+        factories.push(
+            match text_overlay.kind {
+                TextKind::Text => "textoverlay",
+                TextKind::StreamTime => "timeoverlay",
+                TextKind::Clock => "clockoverlay",
+            }
+            .to_owned(),
+        );
+    }
+
+    match video_settings.preview {
+        Some(PreviewTarget::Window) => factories.push("autovideosink".to_owned()),
+        Some(PreviewTarget::Gl) => factories.push("glimagesink".to_owned()),
+        None => {}
+    }
+
+    if video_settings.preview.is_some() {
+        factories.push("tee".to_owned());
+        factories.push("queue".to_owned());
+    }
+
+    match &video_settings.subtitles {
+        Some(SubtitleSource::File(_)) => {
+            factories.push("filesrc".to_owned());
+            factories.push("subparse".to_owned());
+        }
+        Some(SubtitleSource::Cues(_)) => factories.push("appsrc".to_owned()),
+        None => {}
+    }
+
+    match &video_settings.output {
+        OutputTarget::ImageSequence { .. } => {
+            factories.push("pngenc".to_owned());
+            factories.push("multifilesink".to_owned());
+        }
+        OutputTarget::V4l2Loopback { .. } => factories.push("v4l2sink".to_owned()),
+        OutputTarget::Dash { .. } => {
+            factories.push(video_settings.encoder.clone());
+            factories.push("dashsink".to_owned());
+        }
+        target => {
+            match video_settings.container {
+                Container::Video | Container::MpegTs | Container::Matroska => {
+                    factories.push(video_settings.encoder.clone());
+                    factories.push(muxer_factory(video_settings).to_owned());
+                    factories.extend(
+                        video_settings
+                            .custom_elements
+                            .iter()
+                            .map(|(_, spec)| spec.factory.clone()),
+                    );
+                }
+                Container::Gif { .. } => factories.push("gifenc".to_owned()),
+                Container::WebP { .. } => factories.push("webpenc".to_owned()),
+                Container::Y4m => factories.push("y4menc".to_owned()),
+            }
+
+            match target {
+                OutputTarget::File => factories.push("filesink".to_owned()),
+                OutputTarget::MultiOutput(_) => {
+                    factories.push("videoscale".to_owned());
+                    factories.push("filesink".to_owned());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    factories.sort();
+    factories.dedup();
+    factories
+}
+
+/// The package that typically provides a factory
+fn package_for(factory: &str) -> Option<&'static str> {
+    let package = match factory {
+        "appsrc" | "videoconvert" | "videorate" | "videoscale" | "textoverlay" | "timeoverlay"
+        | "clockoverlay" | "glimagesink" | "subparse" => "gst-plugins-base",
+        "capsfilter" | "filesink" | "filesrc" | "tee" | "queue" => "gstreamer core",
+        "mp4mux" | "qtmux" | "matroskamux" | "webmmux" | "vp8enc" | "vp9enc" | "pngenc"
+        | "multifilesink" | "aspectratiocrop" | "gdkpixbufoverlay" | "flvmux" | "autovideosink"
+        | "v4l2sink" => "gst-plugins-good",
+        "x264enc" => "gst-plugins-ugly",
+        "x265enc" | "svtav1enc" | "av1enc" | "webpenc" | "mpegtsmux" | "dashsink" | "nvh264enc"
+        | "nvh265enc" | "vah264enc" => "gst-plugins-bad",
+        "rav1enc" | "gifenc" => "gst-plugins-rs",
+        factory if factory.starts_with("avenc_") => "gst-libav",
+        _ => return None,
+    };
+
+    Some(package)
+}
+
+/// Installed factories that could replace a missing encoder or muxer
+fn alternatives_for(factory: &str, video_settings: &VideoSettings) -> Vec<String> {
+    let (factory_type, caps) = if factory == video_settings.encoder {
+        (
+            gst::ElementFactoryType::VIDEO_ENCODER,
+            Some(&video_settings.caps),
+        )
+    } else if factory == muxer_factory(video_settings) {
+        (gst::ElementFactoryType::MUXER, None)
+    } else {
+        return Vec::new();
+    };
+
+    gst::ElementFactory::factories_with_type(factory_type, gst::Rank::Marginal)
+        .filter(|alternative| caps.is_none_or(|caps| alternative.can_src_any_caps(caps)))
+        .map(|alternative| alternative.name().to_string())
+        .collect()
+}
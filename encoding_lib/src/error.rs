@@ -0,0 +1,139 @@
+use std::{any::Any, fmt};
+
+use gst::{glib, prelude::*};
+use gstreamer as gst;
+
+/// An error message posted on the bus by one of the pipeline's elements
+#[derive(Debug, Clone)]
+pub struct PipelineError {
+    /// The path of the element that posted the error, e.g. `/GstPipeline:encoding pipeline/GstFileSink:sink`
+    pub source_element: Option<String>,
+    /// The error itself
+    ///
+    /// Its domain and code can be matched with `code.kind::<gst::ResourceError>()` and friends.
+    pub code: glib::Error,
+    /// Extra information meant for debugging
+    pub debug: Option<String>,
+}
+
+impl PipelineError {
+    pub(crate) fn from_message(message: &gst::message::Error) -> Self {
+        PipelineError {
+            source_element: message.src().map(|src| src.path_string().to_string()),
+            code: message.error(),
+            debug: message.debug(),
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source_element {
+            Some(element) => write!(f, "Error from {element}: {}", self.code)?,
+            None => write!(f, "Error: {}", self.code)?,
+        }
+
+        if let Some(debug) = &self.debug {
+            write!(f, " ({debug})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Why an encode running in the background failed, see [`EncodeHandle::error`](crate::EncodeHandle::error)
+#[derive(Debug, Clone)]
+pub enum EncodingError {
+    /// One of the pipeline's elements posted an error
+    Pipeline(PipelineError),
+    /// Something panicked while encoding, like a data provider or the thread watching the bus,
+    /// with the message it panicked with
+    Internal(String),
+}
+
+impl EncodingError {
+    /// Gets the message out of the payload of a caught panic
+    pub(crate) fn from_panic(panic: Box<dyn Any + Send>) -> Self {
+        let message = match panic.downcast::<String>() {
+            Ok(message) => *message,
+            Err(panic) => match panic.downcast::<&str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Panicked".to_owned(),
+            },
+        };
+
+        EncodingError::Internal(message)
+    }
+}
+
+impl From<PipelineError> for EncodingError {
+    /// Panics in appsrc callbacks are caught by the bindings and posted as errors,
+    /// those are turned back into [`EncodingError::Internal`]
+    fn from(error: PipelineError) -> Self {
+        match error.code.message().strip_prefix("Panicked") {
+            Some(message)
+                if error.code.kind::<gst::LibraryError>() == Some(gst::LibraryError::Failed) =>
+            {
+                EncodingError::Internal(message.trim_start_matches(": ").to_owned())
+            }
+            _ => EncodingError::Pipeline(error),
+        }
+    }
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::Pipeline(error) => error.fmt(f),
+            EncodingError::Internal(message) => write!(f, "The encoder panicked: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+/// Element factories needed by a set of `VideoSettings` that aren't installed,
+/// returned by [`check_requirements`](crate::encoder::check_requirements)
+#[derive(Debug, Clone)]
+pub struct MissingPlugins {
+    pub missing: Vec<MissingPlugin>,
+}
+
+/// An element factory that isn't installed
+#[derive(Debug, Clone)]
+pub struct MissingPlugin {
+    /// The name of the factory, e.g. `x264enc`
+    pub factory: String,
+    /// The package that usually provides the factory, if known
+    pub package: Option<&'static str>,
+    /// Installed factories that could be used instead
+    pub alternatives: Vec<String>,
+}
+
+impl fmt::Display for MissingPlugins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Missing GStreamer elements:")?;
+
+        for plugin in &self.missing {
+            write!(f, "\n  {}", plugin.factory)?;
+
+            if let Some(package) = plugin.package {
+                write!(f, " (usually provided by {package})")?;
+            }
+
+            if !plugin.alternatives.is_empty() {
+                write!(
+                    f,
+                    ", installed alternatives: {}",
+                    plugin.alternatives.join(", ")
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for MissingPlugins {}
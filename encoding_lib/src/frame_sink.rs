@@ -0,0 +1,326 @@
+#[cfg(feature = "image")]
+use std::ops::Deref;
+use std::sync::mpsc::{Sender, SyncSender};
+
+use gst_app::AppSrc;
+use gst_video::VideoInfo;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+#[cfg(feature = "image")]
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
+
+#[cfg(feature = "image")]
+use crate::data_provider_impls::{frame_info, write_dynamic_frame, write_frame};
+#[cfg(all(target_os = "linux", feature = "dmabuf"))]
+use crate::dmabuf::DmaBufFrame;
+#[cfg(any(all(target_os = "linux", feature = "dmabuf"), feature = "cuda"))]
+use crate::GpuFrame;
+use crate::{
+    data_provider_impls::{packed_plane_sizes, write_planes},
+    limits::LimitState,
+    pipeline::{frame_time, set_frame_timing},
+    pool::FramePool,
+    sei::FrameMetadata,
+    RawFrameSender, StopReason, VideoSettings,
+};
+
+/// Something frames can be pushed into, so code producing frames can be written once
+/// whether the encoder runs behind a channel or on the current thread
+///
+/// Implemented by the senders returned by [`start_encoding`](crate::start_encoding) and friends,
+/// [`RawFrameSender`] and the [`BlockingFrameSink`] of [`with_encoder`](crate::with_encoder).<br>
+/// The tokio sender of [`start_encoding_async`](crate::start_encoding_async) isn't one,
+/// as pushing can't wait for room in the channel inside the runtime, `send` it frames instead.
+pub trait FrameSink<Frame> {
+    /// Sends a frame to the encoder, failing once the encoder has stopped
+    fn push(&mut self, frame: Frame) -> anyhow::Result<()>;
+
+    /// Tells the encoder no more frames are coming, so the video can be finalized
+    ///
+    /// Dropping a channel sender does the same.
+    fn finish(self) -> anyhow::Result<()>
+    where
+        Self: Sized;
+}
+
+impl<Frame> FrameSink<Frame> for Sender<Frame> {
+    fn push(&mut self, frame: Frame) -> anyhow::Result<()> {
+        self.send(frame)
+            .map_err(|_| anyhow::Error::msg("The encoder has already stopped"))
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<Frame> FrameSink<Frame> for SyncSender<Frame> {
+    fn push(&mut self, frame: Frame) -> anyhow::Result<()> {
+        self.send(frame)
+            .map_err(|_| anyhow::Error::msg("The encoder has already stopped"))
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl FrameSink<Vec<u8>> for RawFrameSender {
+    fn push(&mut self, frame: Vec<u8>) -> anyhow::Result<()> {
+        self.send(frame)
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Pushes frames into an encoder started by [`with_encoder`](crate::with_encoder)
+///
+/// Pushing blocks whenever the encoder falls behind.
+pub struct BlockingFrameSink {
+    appsrc: AppSrc,
+    video_info: VideoInfo,
+    video_settings: VideoSettings,
+    frame_num: u64,
+    metadata: FrameMetadata,
+    pool: FramePool,
+    limits: LimitState,
+}
+
+impl BlockingFrameSink {
+    pub(crate) fn new(
+        appsrc: AppSrc,
+        video_info: VideoInfo,
+        video_settings: VideoSettings,
+        metadata: FrameMetadata,
+        limits: LimitState,
+    ) -> Self {
+        appsrc.set_block(true);
+
+        BlockingFrameSink {
+            pool: FramePool::new(video_info.size(), 4),
+            appsrc,
+            video_info,
+            video_settings,
+            frame_num: 0,
+            metadata,
+            limits,
+        }
+    }
+
+    /// Encodes a frame
+    #[cfg(feature = "image")]
+    pub fn push_frame<
+        Format: Pixel<Subpixel = u8> + 'static,
+        Container: Deref<Target = [Format::Subpixel]>,
+    >(
+        &mut self,
+        frame: &ImageBuffer<Format, Container>,
+    ) -> anyhow::Result<()> {
+        let frame_info = self.frame_info(frame.width(), frame.height())?;
+        let mut buffer = self.buffer_for(&frame_info)?;
+        write_frame(frame, buffer.get_mut().unwrap(), &frame_info);
+
+        self.push_buffer(buffer)
+    }
+
+    /// Encodes a frame of any pixel format
+    #[cfg(feature = "image")]
+    pub fn push_dynamic_frame(&mut self, frame: &DynamicImage) -> anyhow::Result<()> {
+        let frame_info = self.frame_info(frame.width(), frame.height())?;
+        let mut buffer = self.buffer_for(&frame_info)?;
+        write_dynamic_frame(frame, buffer.get_mut().unwrap(), &frame_info);
+
+        self.push_buffer(buffer)
+    }
+
+    /// Encodes a frame of raw bytes
+    ///
+    /// The bytes have to be laid out as described by the `VideoInfo` made from the settings.
+    pub fn push_raw_frame(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        // Image frames may have switched the appsrc to a different size
+        let caps = self.video_info.to_caps()?;
+        if self.appsrc.caps().as_ref() != Some(&caps) {
+            self.appsrc.set_caps(Some(&caps));
+        }
+
+        if frame.len() != self.video_info.size() {
+            return Err(anyhow::Error::msg(format!(
+                "Frame is {} bytes but the video format needs {} bytes",
+                frame.len(),
+                self.video_info.size()
+            )));
+        }
+
+        self.push_buffer(gst::Buffer::from_slice(frame.to_vec()))
+    }
+
+    /// Lays out tightly packed planes as the video format expects and encodes them,
+    /// see [`write_planes`](crate::data_provider_impls::write_planes) for how the planes should look
+    pub fn push_planes(&mut self, planes: &[&[u8]]) -> anyhow::Result<()> {
+        let caps = self.video_info.to_caps()?;
+        if self.appsrc.caps().as_ref() != Some(&caps) {
+            self.appsrc.set_caps(Some(&caps));
+        }
+
+        let mut buffer = self.pool.acquire_buffer();
+        {
+            let mut map = buffer.get_mut().unwrap().map_writable()?;
+            write_planes(planes, map.as_mut_slice(), &self.video_info)?;
+        }
+
+        self.push_buffer(buffer)
+    }
+
+    /// Encodes a frame exported from the GPU as a DMA-BUF without copying it to the CPU
+    ///
+    /// The frame has to be in the format and size of the video.
+    #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+    pub fn push_dmabuf_frame(&mut self, frame: DmaBufFrame) -> anyhow::Result<()> {
+        self.push_gpu_frame(GpuFrame::DmaBuf(frame))
+    }
+
+    /// Encodes a frame that's still in GPU memory without copying it to the CPU
+    ///
+    /// The frame has to be in the format and size of the video.<br>
+    /// Elements that can't use the memory, like `x264enc`, still download it.
+    #[cfg(any(all(target_os = "linux", feature = "dmabuf"), feature = "cuda"))]
+    pub fn push_gpu_frame(&mut self, frame: GpuFrame) -> anyhow::Result<()> {
+        let mut caps = self.video_info.to_caps()?;
+        if let Some(feature) = frame.caps_feature() {
+            caps.get_mut()
+                .unwrap()
+                .set_features(0, Some(gst::CapsFeatures::new(&[feature])));
+        }
+        if self.appsrc.caps().as_ref() != Some(&caps) {
+            self.appsrc.set_caps(Some(&caps));
+        }
+
+        let buffer = frame.into_buffer(&self.video_info)?;
+        self.push_buffer(buffer)
+    }
+
+    /// Writes `data` into the next frame pushed as an H.264 SEI message,
+    /// see [`EncodeHandle::attach_metadata`](crate::EncodeHandle::attach_metadata)
+    pub fn attach_metadata(&self, data: impl Into<Vec<u8>>) {
+        self.metadata.attach(
+            frame_time(self.frame_num, &self.video_settings),
+            data.into(),
+        );
+    }
+
+    /// How many frames have been pushed so far
+    pub fn frames_pushed(&self) -> u64 {
+        self.frame_num
+    }
+
+    /// Which of the [`EncodeLimits`](crate::EncodeLimits) ended the video,
+    /// loops pushing frames until they're told to stop should stop once this is set
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.limits.reason()
+    }
+
+    /// How many bytes each frame passed to [`push_raw_frame`](Self::push_raw_frame) needs to be
+    pub fn frame_size(&self) -> usize {
+        self.video_info.size()
+    }
+
+    /// How many bytes each plane passed to [`push_planes`](Self::push_planes) needs to be
+    pub fn plane_sizes(&self) -> Vec<usize> {
+        packed_plane_sizes(&self.video_info)
+    }
+
+    #[cfg(feature = "image")]
+    fn frame_info(&self, width: u32, height: u32) -> anyhow::Result<VideoInfo> {
+        frame_info(
+            &self.appsrc,
+            &self.video_info,
+            &self.video_settings,
+            width,
+            height,
+        )
+    }
+
+    /// Gets a buffer for a frame, reusing old frames when it's the size of the video
+    #[cfg(feature = "image")]
+    fn buffer_for(&self, frame_info: &VideoInfo) -> anyhow::Result<gst::Buffer> {
+        if frame_info.size() == self.pool.frame_size() {
+            Ok(self.pool.acquire_buffer())
+        } else {
+            Ok(gst::Buffer::with_size(frame_info.size())?)
+        }
+    }
+
+    fn push_buffer(&mut self, mut buffer: gst::Buffer) -> anyhow::Result<()> {
+        set_frame_timing(
+            buffer.get_mut().unwrap(),
+            self.frame_num,
+            &self.video_settings,
+        );
+
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|_| match self.limits.reason() {
+                Some(reason) => anyhow::Error::msg(reason.to_string()),
+                None => anyhow::Error::msg("The encoder has already stopped"),
+            })?;
+        self.frame_num += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn end_of_stream(&self) {
+        let _ = self.appsrc.end_of_stream();
+    }
+}
+
+#[cfg(feature = "image")]
+impl<Format: Pixel<Subpixel = u8> + 'static, Container: Deref<Target = [u8]>>
+    FrameSink<ImageBuffer<Format, Container>> for BlockingFrameSink
+{
+    fn push(&mut self, frame: ImageBuffer<Format, Container>) -> anyhow::Result<()> {
+        self.push_frame(&frame)
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        self.end_of_stream();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "image")]
+impl FrameSink<DynamicImage> for BlockingFrameSink {
+    fn push(&mut self, frame: DynamicImage) -> anyhow::Result<()> {
+        self.push_dynamic_frame(&frame)
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        self.end_of_stream();
+        Ok(())
+    }
+}
+
+impl FrameSink<Vec<u8>> for BlockingFrameSink {
+    fn push(&mut self, frame: Vec<u8>) -> anyhow::Result<()> {
+        self.push_raw_frame(&frame)
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        self.end_of_stream();
+        Ok(())
+    }
+}
+
+#[cfg(any(all(target_os = "linux", feature = "dmabuf"), feature = "cuda"))]
+impl FrameSink<GpuFrame> for BlockingFrameSink {
+    fn push(&mut self, frame: GpuFrame) -> anyhow::Result<()> {
+        self.push_gpu_frame(frame)
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        self.end_of_stream();
+        Ok(())
+    }
+}
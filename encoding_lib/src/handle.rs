@@ -0,0 +1,538 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{channel, Receiver},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use gst::{prelude::*, ClockTime, Pipeline};
+use gst_app::AppSrc;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+
+use crate::{
+    data_provider::wait_for_eos,
+    limits::LimitState,
+    pipeline::{filesink_location, finish_partial_output, set_chapters},
+    sei::FrameMetadata,
+    stats::StatsCollector,
+    Chapter, EncodeStats, EncodingError, PartialFile, StopReason, VideoSettings,
+};
+
+/// How long a dropped [`EncodeHandle`] waits for the file to be finalized by default
+pub const DEFAULT_FINALIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often [`EncodeHandle::stop_when`] checks its flag
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A handle to an encode running in the background
+///
+/// Dropping the handle without joining it finalizes the video with whatever frames were sent so far:
+/// the stream is ended, the muxer is given some time to finish writing the file, then the pipeline is stopped.<br>
+/// This means that if the thread sending frames panics the video is still playable,
+/// as long as the handle is dropped while unwinding.
+///
+/// Frames still waiting in the channel when the handle is dropped may be lost.<br>
+/// The encoder can't stop while it is waiting on a frame, so the frame sender has to be dropped too.
+pub struct EncodeHandle {
+    thread: Option<JoinHandle<()>>,
+    pipeline: Pipeline,
+    appsrcs: Vec<AppSrc>,
+    finished: Receiver<()>,
+    finalize_timeout: Duration,
+    pause: Arc<Mutex<PauseState>>,
+    chapters: Mutex<Vec<Chapter>>,
+    metadata: FrameMetadata,
+    stats: StatsCollector,
+    atomic_output: Option<PartialFile>,
+    failure: Failure,
+}
+
+/// The error that stopped an encode, shared with whatever has to know about it
+#[derive(Clone, Default)]
+struct Failure(Arc<Mutex<FailureState>>);
+
+#[derive(Default)]
+struct FailureState {
+    error: Option<EncodingError>,
+    /// Called once when the encode fails, e.g. to close the channel frames are sent through
+    on_failure: Vec<Box<dyn FnOnce() + Send>>,
+}
+
+impl Failure {
+    fn fail(&self, error: EncodingError) {
+        let on_failure = {
+            let mut state = self.0.lock().unwrap();
+            state.error.get_or_insert(error);
+            std::mem::take(&mut state.on_failure)
+        };
+
+        for callback in on_failure {
+            callback();
+        }
+    }
+
+    fn error(&self) -> Option<EncodingError> {
+        self.0.lock().unwrap().error.clone()
+    }
+
+    fn on_failure(&self, callback: impl FnOnce() + Send + 'static) {
+        let mut state = self.0.lock().unwrap();
+        if state.error.is_some() {
+            drop(state);
+            callback();
+        } else {
+            state.on_failure.push(Box::new(callback));
+        }
+    }
+}
+
+/// Tracks how much time has been cut out of the video by pausing
+#[derive(Default)]
+struct PauseState {
+    paused: bool,
+    resumed: bool,
+    offset: ClockTime,
+    last_pts: Option<ClockTime>,
+}
+
+/// Counts the frames thrown away by the leaky queue of a `BackpressurePolicy`
+#[derive(Clone, Default)]
+pub(crate) struct DropCounter {
+    queue: Option<gst::Element>,
+    entered: Arc<AtomicU64>,
+    left: Arc<AtomicU64>,
+}
+
+impl DropCounter {
+    pub(crate) fn install(pipeline: &Pipeline) -> Self {
+        let queue = match pipeline.by_name("backpressure queue") {
+            Some(queue) => queue,
+            None => return DropCounter::default(),
+        };
+
+        let counter = DropCounter {
+            queue: Some(queue.clone()),
+            ..Default::default()
+        };
+
+        for (pad, count) in [
+            ("sink", counter.entered.clone()),
+            ("src", counter.left.clone()),
+        ] {
+            queue
+                .static_pad(pad)
+                .unwrap()
+                .add_probe(gst::PadProbeType::BUFFER, move |_, _| {
+                    count.fetch_add(1, Ordering::Relaxed);
+                    gst::PadProbeReturn::Ok
+                });
+        }
+
+        counter
+    }
+
+    pub(crate) fn dropped(&self) -> u64 {
+        let queued = self.queue.as_ref().map_or(0, |queue| {
+            queue.property::<u32>("current-level-buffers") as u64
+        });
+
+        self.entered
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.left.load(Ordering::Relaxed) + queued)
+    }
+}
+
+impl EncodeHandle {
+    /// Starts watching the bus of an already playing pipeline on a new thread
+    pub(crate) fn spawn(
+        pipeline: Pipeline,
+        appsrc: AppSrc,
+        video_settings: &VideoSettings,
+    ) -> Self {
+        let pad = appsrc.static_pad("src").unwrap();
+        Self::spawn_with_sources(pipeline, vec![appsrc], pad, video_settings)
+    }
+
+    /// The same as [`spawn`](Self::spawn) for a pipeline fed by several appsrcs
+    /// whose frames all come out of `output_pad`, like the source pad of a `compositor`
+    pub(crate) fn spawn_with_sources(
+        pipeline: Pipeline,
+        appsrcs: Vec<AppSrc>,
+        output_pad: gst::Pad,
+        video_settings: &VideoSettings,
+    ) -> Self {
+        let (finished_sender, finished) = channel();
+        let on_message = video_settings.on_message.clone();
+
+        let pause = Arc::new(Mutex::new(PauseState::default()));
+        let frame_duration = ClockTime::from_nseconds(1_000_000_000 / video_settings.framerate);
+
+        // Frames are dropped while paused and everything after is shifted back
+        // so the pause doesn't show up as a frozen frame
+        let probe_pause = pause.clone();
+        output_pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            let mut pause = probe_pause.lock().unwrap();
+            if pause.paused {
+                return gst::PadProbeReturn::Drop;
+            }
+
+            if let Some(gst::PadProbeData::Buffer(ref mut buffer)) = info.data {
+                if let Some(pts) = buffer.pts() {
+                    if pause.resumed {
+                        if let Some(last_pts) = pause.last_pts {
+                            pause.offset += pts.saturating_sub(last_pts + frame_duration);
+                        }
+                        pause.resumed = false;
+                    }
+
+                    pause.last_pts = Some(pts);
+                    let offset = pause.offset;
+                    buffer.make_mut().set_pts(pts.saturating_sub(offset));
+                }
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+
+        let metadata = FrameMetadata::default();
+        metadata.install(&pipeline);
+
+        let stats = StatsCollector::install(&pipeline, &output_pad, &video_settings.limits);
+
+        let bus_pipeline = pipeline.clone();
+        let atomic_output = video_settings.atomic_output;
+        let failure = Failure::default();
+        let thread_failure = failure.clone();
+        let thread = std::thread::spawn(move || {
+            // Caught so the error reaches the handle as soon as it happens instead of at `join`
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| {
+                wait_for_eos(&bus_pipeline, on_message.as_ref())
+            })) {
+                Ok(result) => result.map_err(EncodingError::from),
+                Err(panic) => {
+                    let _ = bus_pipeline.set_state(gst::State::Null);
+                    Err(EncodingError::from_panic(panic))
+                }
+            };
+            if let Err(e) = &result {
+                println!("{e}");
+                thread_failure.fail(e.clone());
+            }
+            if let Err(e) = finish_partial_output(&bus_pipeline, atomic_output, result.is_ok()) {
+                println!("Couldn't finish the partial output: {e}");
+            }
+            let _ = finished_sender.send(());
+        });
+
+        EncodeHandle {
+            thread: Some(thread),
+            pipeline,
+            appsrcs,
+            finished,
+            finalize_timeout: DEFAULT_FINALIZE_TIMEOUT,
+            pause,
+            chapters: Mutex::new(video_settings.chapters.clone()),
+            metadata,
+            stats,
+            atomic_output,
+            failure,
+        }
+    }
+
+    /// The error that stopped the encode, available as soon as it happens
+    ///
+    /// Panics in data providers are caught and reported as [`EncodingError::Internal`].
+    pub fn error(&self) -> Option<EncodingError> {
+        self.failure.error()
+    }
+
+    /// Closes the channel frames are received from once the encode fails,
+    /// so sending into it returns an error and the producer can stop
+    pub(crate) fn close_on_failure<T: Send + 'static>(&self, receiver: Arc<Mutex<Receiver<T>>>) {
+        self.failure.on_failure(move || {
+            // The pipeline is already stopped, so nothing is waiting on the receiver anymore
+            *receiver.lock().unwrap() = channel().1;
+        });
+    }
+
+    /// Pauses the recording
+    ///
+    /// Frames sent while paused are thrown away, and the video continues
+    /// from the last frame before the pause once resumed.
+    pub fn pause(&self) -> anyhow::Result<()> {
+        self.pause.lock().unwrap().paused = true;
+        self.pipeline.set_state(gst::State::Paused)?;
+        Ok(())
+    }
+
+    /// Resumes a paused recording
+    pub fn resume(&self) -> anyhow::Result<()> {
+        {
+            let mut pause = self.pause.lock().unwrap();
+            if !pause.paused {
+                return Ok(());
+            }
+            pause.paused = false;
+            pause.resumed = true;
+        }
+
+        self.pipeline.set_state(gst::State::Playing)?;
+        Ok(())
+    }
+
+    /// Whether the recording is paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.lock().unwrap().paused
+    }
+
+    /// Changes a property of the encoder while it is running, e.g. `bitrate` to adapt to the network
+    ///
+    /// Not every encoder picks up every property change mid-stream,
+    /// a notice is printed when the encoder doesn't say the property can be changed while playing.
+    pub fn set_encoder_property(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        let encoder = self
+            .pipeline
+            .by_name("encoder")
+            .ok_or_else(|| anyhow::Error::msg("The pipeline has no encoder"))?;
+
+        if let Some(property) = encoder.find_property(name) {
+            if !property.flags().contains(gst::PARAM_FLAG_MUTABLE_PLAYING) {
+                println!("{name} may not take effect until the encoder restarts");
+            }
+        }
+
+        encoder.try_set_property_from_str(name, value)?;
+        Ok(())
+    }
+
+    /// Asks the encoder to make the next frame a keyframe
+    ///
+    /// Useful for lining keyframes up with segment boundaries or letting new viewers of a stream join quickly.
+    pub fn request_keyframe(&self) -> anyhow::Result<()> {
+        let encoder = self
+            .pipeline
+            .by_name("encoder")
+            .ok_or_else(|| anyhow::Error::msg("The pipeline has no encoder"))?;
+
+        let event = gst_video::DownstreamForceKeyUnitEvent::builder()
+            .all_headers(true)
+            .build();
+
+        if !encoder.static_pad("sink").unwrap().send_event(event) {
+            return Err(anyhow::Error::msg(
+                "The encoder didn't accept the keyframe request",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Marks a chapter starting at `timestamp`, e.g. at a level transition
+    ///
+    /// Chapters are written when the video is finalized,
+    /// and only by muxers that support a table of contents like `matroskamux`.
+    pub fn add_chapter(&self, title: impl Into<String>, timestamp: Duration) -> anyhow::Result<()> {
+        let muxer = self
+            .pipeline
+            .by_name("muxer")
+            .ok_or_else(|| anyhow::Error::msg("The pipeline has no muxer"))?;
+
+        let mut chapters = self.chapters.lock().unwrap();
+        chapters.push(Chapter::new(title, timestamp));
+        set_chapters(&muxer, &chapters);
+
+        Ok(())
+    }
+
+    /// Writes `data` into the frame shown at `timestamp` as an H.264 SEI message,
+    /// e.g. the game state or inputs of that frame
+    ///
+    /// Has to be called before the frame reaches the encoder, and does nothing for other codecs.
+    /// See [`FRAME_METADATA_UUID`](crate::sei::FRAME_METADATA_UUID) for reading it back.
+    pub fn attach_metadata(&self, timestamp: Duration, data: impl Into<Vec<u8>>) {
+        self.metadata.attach(
+            ClockTime::from_nseconds(timestamp.as_nanos() as u64),
+            data.into(),
+        );
+    }
+
+    pub(crate) fn frame_metadata(&self) -> FrameMetadata {
+        self.metadata.clone()
+    }
+
+    /// How many frames have been thrown away by the [`BackpressurePolicy`](crate::BackpressurePolicy) so far
+    pub fn dropped_frames(&self) -> u64 {
+        self.stats.drop_counter().dropped()
+    }
+
+    /// Which of the [`EncodeLimits`](crate::EncodeLimits) ended the video, `None` while none has been hit
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stats.limits().reason()
+    }
+
+    pub(crate) fn limit_state(&self) -> LimitState {
+        self.stats.limits().clone()
+    }
+
+    /// A paused pipeline can't finish, so it is set back to playing while still dropping frames
+    fn unpause_pipeline(&self) {
+        if self.is_paused() {
+            let _ = self.pipeline.set_state(gst::State::Playing);
+        }
+    }
+
+    /// Ends the stream so the video is finalized, [`join`](Self::join) then waits for it to be written
+    ///
+    /// Needed to finish recordings from a [`CaptureSource`](crate::CaptureSource),
+    /// which don't end on their own.
+    pub fn stop(&self) {
+        self.unpause_pipeline();
+        self.end_stream();
+    }
+
+    /// Stops the recording like [`stop`](Self::stop) once `flag` is set, e.g. by a Ctrl-C handler
+    ///
+    /// The flag is checked every 50ms on another thread until the encode finishes.
+    pub fn stop_when(&self, flag: Arc<AtomicBool>) {
+        let pipeline = self.pipeline.clone();
+        let appsrcs = self.appsrcs.clone();
+        let pause = self.pause.clone();
+
+        std::thread::spawn(move || {
+            while !flag.load(Ordering::Relaxed) {
+                // The bus thread shuts the pipeline down once the encode is over
+                if pipeline.current_state() == gst::State::Null {
+                    return;
+                }
+                std::thread::sleep(STOP_POLL_INTERVAL);
+            }
+
+            if pause.lock().unwrap().paused {
+                let _ = pipeline.set_state(gst::State::Playing);
+            }
+            end_stream(&pipeline, &appsrcs);
+        });
+    }
+
+    /// Waits for the encode to finish, ending it early once `flag` is set,
+    /// for recordings that run until they're told to stop
+    ///
+    /// With [`stop_signal`](crate::signal::stop_signal) this finalizes a capture
+    /// when the user hits Ctrl-C, instead of leaving a broken file.
+    pub fn run_until(self, flag: Arc<AtomicBool>) -> Result<EncodeStats, EncodingError> {
+        self.stop_when(flag);
+        self.join()
+    }
+
+    fn end_stream(&self) {
+        end_stream(&self.pipeline, &self.appsrcs);
+    }
+
+    /// Waits for the encode to finish, returning the error that stopped it if it failed
+    ///
+    /// # Deadlock
+    /// Joining before the frame sender is dropped will deadlock.
+    pub fn join(mut self) -> Result<EncodeStats, EncodingError> {
+        self.unpause_pipeline();
+        self.thread
+            .take()
+            .unwrap()
+            .join()
+            .map_err(EncodingError::from_panic)?;
+
+        match self.failure.error() {
+            Some(error) => Err(error),
+            None => Ok(self.stats.stats()),
+        }
+    }
+
+    /// Writes the pipeline as it is right now to `path` as a Graphviz DOT file,
+    /// with every element, property and negotiated caps
+    ///
+    /// Render it with e.g. `dot -Tsvg pipeline.dot -o pipeline.svg`,
+    /// useful for finding where a `not-negotiated` error comes from.
+    pub fn dump_graph(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let graph = self
+            .pipeline
+            .debug_to_dot_data(gst::DebugGraphDetails::all());
+        std::fs::write(path, graph.as_str())?;
+        Ok(())
+    }
+
+    /// The file the video is written to, with its [`output_template`](VideoSettings::output_template)
+    /// resolved, `None` when it isn't written to a single file
+    pub fn output_path(&self) -> Option<PathBuf> {
+        let location = filesink_location(&self.pipeline.by_name("sink")?)?;
+        let location = match self.atomic_output {
+            Some(_) => location
+                .strip_suffix(".part")
+                .unwrap_or(&location)
+                .to_owned(),
+            None => location,
+        };
+        Some(PathBuf::from(location))
+    }
+
+    /// Whether the encode has finished
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Sets how long dropping the handle waits for the file to be finalized
+    /// before giving up and stopping the pipeline
+    pub fn set_finalize_timeout(&mut self, timeout: Duration) {
+        self.finalize_timeout = timeout;
+    }
+}
+
+/// Ends the stream at the appsrcs, or at the sources of the pipeline when it has none
+fn end_stream(pipeline: &Pipeline, appsrcs: &[AppSrc]) {
+    if appsrcs.is_empty() {
+        pipeline.send_event(gst::event::Eos::new());
+    }
+    for appsrc in appsrcs {
+        let _ = appsrc.end_of_stream();
+    }
+}
+
+impl Drop for EncodeHandle {
+    fn drop(&mut self) {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return,
+        };
+
+        self.unpause_pipeline();
+
+        // End the stream ourselves in case the frame sender is still alive somewhere
+        self.end_stream();
+
+        if self.finished.recv_timeout(self.finalize_timeout).is_err() {
+            println!("Encoder didn't finalize in time, stopping it");
+
+            // The bus thread stops the pipeline once it sees this error,
+            // stopping it here first would flush the error off the bus and leave the thread waiting.
+            // It's an error so an atomic output isn't given its real name
+            if let Some(bus) = self.pipeline.bus() {
+                let _ = bus.post(
+                    &gst::message::Error::builder(
+                        gst::CoreError::Failed,
+                        "The encoder didn't finalize in time",
+                    )
+                    .build(),
+                );
+            }
+        }
+
+        let _ = thread.join();
+    }
+}
@@ -1,18 +1,85 @@
 #![doc = include_str!("../README.md")]
+use ::gstreamer as gst;
+use ::gstreamer::prelude::*;
 use ::gstreamer::Caps;
-use gstreamer_video::VideoFormat;
-use image::{DynamicImage, ImageBuffer, Pixel};
+#[cfg(feature = "image")]
+use gstreamer_app::{AppSink, AppSrc};
+use gstreamer_video::{
+    VideoColorMatrix, VideoColorPrimaries, VideoColorRange, VideoFormat, VideoInfo,
+    VideoTransferFunction,
+};
+#[cfg(feature = "image")]
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
 use std::collections::HashMap;
-use std::ops::Deref;
-use std::sync::{Arc, Mutex, RwLock};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "image")]
+use std::{borrow::Borrow, io::Write, ops::Deref};
 
-use std::{
-    sync::mpsc::{channel, Receiver, Sender},
-    thread::JoinHandle,
-};
+use std::sync::mpsc::{channel, Receiver, Sender};
+#[cfg(feature = "image")]
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::time::Duration;
 
+pub use crate::audio::{AudioFilter, AudioInput, AudioSource};
+pub use crate::capture::{start_capture, CaptureSource, WindowId};
+#[cfg(feature = "image")]
+pub use crate::compositor::{encode_grid, Compositor, FrameSource, Placement};
+pub use crate::compressed::{start_encoding_compressed, CompressedFrameSender, StillFormat};
+#[cfg(feature = "image")]
+use crate::concat::concat_files;
+pub use crate::controller::{EncodeEvent, EncodingController};
+#[cfg(feature = "cuda")]
+pub use crate::cuda::{CudaContext, CudaFrame};
+#[cfg(feature = "image")]
 use crate::data_provider::encode_video;
+use crate::data_provider::{start_pipeline, wait_for_eos};
+pub use crate::debug::{init_encoder_with, DebugConfig};
+#[cfg(feature = "image")]
+pub use crate::decoder::{decode_frames, extract_frame, generate_thumbnails, Decoder};
+#[cfg(all(target_os = "linux", feature = "dmabuf"))]
+pub use crate::dmabuf::DmaBufFrame;
+pub use crate::dry_run::DryRunReport;
+pub use crate::error::{EncodingError, MissingPlugins, PipelineError};
+pub use crate::frame_sink::{BlockingFrameSink, FrameSink};
+pub use crate::handle::EncodeHandle;
+pub use crate::limits::{EncodeLimits, StopReason};
+pub use crate::metrics::{Metrics, MetricsHook};
+pub use crate::natural_order::natural_cmp;
+pub use crate::output_template::resolve_output_path;
+pub use crate::passthrough::{mux_bitstream, Bitstream, BitstreamSink};
+#[cfg(feature = "image")]
+use crate::pipeline::filesink_location;
 pub use crate::pipeline::init_encoder;
+#[cfg(feature = "image")]
+use crate::pipeline::{acquire_frame_buffer, init_pipeline_with_sink, set_frame_timing};
+use crate::pipeline::{
+    finish_partial_output, init_pipeline, link_encoding, make_video_info, new_pipeline,
+};
+#[cfg(feature = "image")]
+pub use crate::pixel_format::PixelFormat;
+pub use crate::pool::{FramePool, PooledFrame};
+pub use crate::probe::{probe, MediaInfo};
+pub use crate::property::PropertyValue;
+#[cfg(feature = "image")]
+pub use crate::queue::{EncodeJob, EncodeQueue, JobId, JobStatus};
+pub use crate::replay_buffer::ReplayBuffer;
+#[cfg(feature = "image")]
+use crate::retry::PushedFrames;
+pub use crate::retry::RetryPolicy;
+#[cfg(feature = "signals")]
+pub use crate::signal::stop_signal;
+#[cfg(feature = "image")]
+pub use crate::slideshow::Slideshow;
+pub use crate::stats::EncodeStats;
+use crate::stats::StatsCollector;
+#[cfg(feature = "image")]
+pub use crate::stereo::{start_stereo_encoding, StereoFrame, StereoLayout};
+pub use crate::trim::{trim, TrimMode};
+#[cfg(feature = "watch")]
+pub use crate::watch::{encode_watch_folder, WatchFolder, WatchOrder};
+pub use crate::watchdog::Watchdog;
+pub use crate::y4m::{encode_y4m, Y4mReader};
 
 /// Re-exports from the gstreamer crates to allow extra customization
 pub mod gstreamer {
@@ -24,14 +91,73 @@ pub mod gstreamer {
     pub mod app {
         pub use gstreamer_app::*;
     }
+
+    pub mod pbutils {
+        pub use gstreamer_pbutils::*;
+    }
 }
 
+pub mod audio;
+pub mod capture;
+#[cfg(feature = "image")]
+pub mod compositor;
+pub mod compressed;
+#[cfg(feature = "image")]
+pub mod concat;
+pub mod controller;
+#[cfg(feature = "cuda")]
+pub mod cuda;
 pub mod data_provider;
 pub mod data_provider_impls;
+pub mod debug;
+#[cfg(feature = "image")]
+pub mod decoder;
+pub mod discovery;
+#[cfg(all(target_os = "linux", feature = "dmabuf"))]
+pub mod dmabuf;
+pub mod dry_run;
+pub mod encoder;
+pub mod error;
+pub mod frame_sink;
+pub mod handle;
+pub mod limits;
+pub mod metrics;
+pub mod natural_order;
+pub mod output_template;
+pub mod passthrough;
 pub mod pipeline;
+#[cfg(feature = "image")]
+pub mod pixel_format;
+pub mod pool;
+pub mod presets;
+pub mod probe;
+pub mod property;
+#[cfg(feature = "image")]
+pub mod queue;
+pub mod replay_buffer;
+pub mod retry;
+pub mod sei;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "signals")]
+pub mod signal;
+#[cfg(feature = "image")]
+pub mod slideshow;
+pub mod stats;
+#[cfg(feature = "image")]
+pub mod stereo;
+pub mod trim;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod watchdog;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+pub mod y4m;
 
 /// The different settings you can set for the encoder
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default = "VideoSettings::profile_defaults"))]
 pub struct VideoSettings {
     /// The framerate of the video
     pub framerate: u64,
@@ -43,12 +169,148 @@ pub struct VideoSettings {
     pub encoder: String,
     /// The muxer plugin to use
     pub muxer: String,
+    /// The kind of file to make
+    pub container: Container,
+    /// Where to write the encoded video
+    pub output: OutputTarget,
+    /// Whether to write the video to `<output>.part` and only give it its real name once it's
+    /// finalized, so anything watching the directory never picks up a half written file
+    ///
+    /// Only used for [`OutputTarget::File`], `None` writes straight to the output.
+    pub atomic_output: Option<PartialFile>,
+    /// Whether the output path is a template like `recording_%Y-%m-%d_%03n.mp4`,
+    /// resolved by [`resolve_output_path`] when the encode starts
+    ///
+    /// Also keeps existing files from being overwritten by adding a number to the name.
+    /// [`EncodeHandle::output_path`] tells where the video ended up.
+    pub output_template: bool,
+    /// Whether to put the index of MP4 and MOV files at the start
+    /// so they can be played before they're fully downloaded
+    ///
+    /// The muxer has to write everything to a temporary file first,
+    /// so this needs as much extra disk space as the video itself.
+    pub faststart: bool,
+    /// Whether to duplicate or drop frames so the video plays at exactly `framerate`
+    ///
+    /// Only useful when frames are sent with their own timestamps,
+    /// e.g. through [`start_encoding_timed`] with a source that doesn't keep a steady rate.
+    pub rate_conversion: bool,
+    /// How much faster the video plays than frames were captured, e.g. `10.0` for a timelapse
+    /// or `0.5` for slow motion
+    ///
+    /// Timestamps are scaled, so frames aren't dropped or repeated unless `rate_conversion` is set.
+    /// Frames timestamped by a `live_source` aren't scaled.
+    pub speed: SpeedFactor,
+    /// Whether to repeat the last frame when no new frame arrives within a frame interval,
+    /// so a live recording keeps its real time length while the source stalls
+    ///
+    /// Only used by [`start_encoding`] and [`start_encoding_bounded`].
+    pub fill_missing_frames: bool,
+    /// Whether frames come from a live source like a screen capture,
+    /// which makes the appsrc live and timestamp frames as they arrive
+    ///
+    /// Timestamps then follow the wall clock instead of being counted from `framerate`,
+    /// so a recording keeps its real length even when frames come late.
+    /// Frames sent with their own timestamps keep them.
+    pub live_source: bool,
+    /// The order frames are encoded in, e.g. reversed or as a boomerang
+    ///
+    /// Only used by [`encode_frames`] and [`encode_frames_ref`].
+    pub playback: Playback,
+    /// What to do with frames when the encoder can't keep up
+    pub backpressure: BackpressurePolicy,
+    /// Limits that end the video early, e.g. so an unattended capture can't fill the disk
+    pub limits: EncodeLimits,
+    /// Reports, and optionally fails, encodes whose buffers stop flowing
+    pub watchdog: Option<Watchdog>,
+    /// Falls back to other encoders when the encoder fails to start, see [`RetryPolicy`]
+    ///
+    /// Only used by [`encode_frames`] and [`encode_frames_ref`].
+    pub retry: Option<RetryPolicy>,
+    /// What to do with frames that aren't `width` by `height`
+    pub scaling: ScalingMode,
+    /// Images stamped onto every frame, drawn in order
+    pub overlays: Vec<Overlay>,
+    /// Text burned into every frame, drawn on top of the image overlays
+    pub text_overlay: Option<TextOverlay>,
+    /// Shows the video in a window while it is being encoded
+    pub preview: Option<PreviewTarget>,
+    /// Metadata written into the container by the muxer
+    pub tags: MediaTags,
+    /// A subtitle track muxed next to the video
+    ///
+    /// Only used when `output` is a single muxed file, `matroskamux` and `mp4mux` both support it.
+    pub subtitles: Option<SubtitleSource>,
+    /// Audio recorded next to the video, mixed into a single track
+    ///
+    /// Only used when `output` is a single muxed file.<br>
+    /// Microphones and system audio are captured as they play, so they only line up with frames
+    /// sent in real time, like a `live_source` or a [`CaptureSource`].
+    pub audio: Vec<AudioInput>,
+    /// Filters run in order on the mixed audio before it's encoded, e.g. loudness normalization
+    pub audio_filters: Vec<AudioFilter>,
+    /// The element audio is encoded with, `None` picks `opusenc` for WebM and Matroska
+    /// and `avenc_aac` for everything else
+    pub audio_encoder: Option<String>,
+    /// Chapters written into the container, more can be added while encoding
+    /// with [`EncodeHandle::add_chapter`]
+    ///
+    /// Only used by muxers that support a table of contents, like `matroskamux`.
+    pub chapters: Vec<Chapter>,
     /// The format of images sent into the app pipeline
+    ///
+    /// Set from the pixel type by functions generic over it, see [`PixelFormat`].<br>
+    /// `ImageBuffer` frames are converted to this as they're written, which only works for packed formats,
+    /// planar formats like I420 or NV12 need to be sent with [`RawFrameSender::push_planes`]
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::video_format"))]
     pub format: VideoFormat,
+    /// The raw format frames are converted to before being encoded
+    ///
+    /// When `None` the converter picks whatever the encoder prefers,
+    /// which may not keep the alpha channel.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_impls::option_video_format")
+    )]
+    pub encoder_format: Option<VideoFormat>,
+    /// The color space the video is encoded in, which players need to show colors correctly
+    pub color: ColorSettings,
     /// Restrictions on video format to put on the encoder
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::caps"))]
     pub caps: Caps,
-    pub encoder_settings: HashMap<String, String>,
-    pub muxer_settings: HashMap<String, String>,
+    /// How the encoder trades size for quality, mapped to the properties of the encoder
+    pub rate_control: RateControl,
+    /// Whether [`encode_frames`] and [`encode_frames_ref`] encode the frames twice,
+    /// using what the first pass learned to hit the bitrate more closely
+    ///
+    /// Only supported by `x264enc`, other encoders and frames sent one by one use a single pass.
+    pub two_pass: bool,
+    /// Properties set on the encoder, e.g. `speed-preset`
+    ///
+    /// These are set after `rate_control` so they can override it.
+    pub encoder_settings: HashMap<String, PropertyValue>,
+    /// The most threads the encoder may use, `None` lets it pick
+    ///
+    /// Supported by `x264enc`, `vp8enc` and `vp9enc`, set before `encoder_settings`.
+    pub encoder_threads: Option<u32>,
+    /// The nice level of the pipeline's threads, from -20 to 19 with higher values giving way to other threads
+    ///
+    /// Only supported on Linux, lowering it below 0 usually needs extra permissions.
+    pub nice: Option<i32>,
+    /// Properties set on the muxer
+    pub muxer_settings: HashMap<String, PropertyValue>,
+    /// Extra elements linked into the pipeline, see [`VideoSettings::insert_elements`]
+    pub custom_elements: Vec<(PipelineStage, ElementSpec)>,
+    /// Called with every message posted on the pipeline's bus
+    ///
+    /// When set, warnings and info messages are no longer printed.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub on_message: Option<MessageCallback>,
+    /// Told about frames as they're received, encoded and written, see [`Metrics`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub metrics: Option<MetricsHook>,
+    /// What the encoding pipeline's name starts with, see [`VideoSettings::namespace`]
+    pub namespace: Option<String>,
 }
 
 impl VideoSettings {
@@ -59,20 +321,837 @@ impl VideoSettings {
             height,
             encoder: "x264enc".to_owned(),
             muxer: "mp4mux".to_owned(),
+            container: Container::Video,
+            output: OutputTarget::File,
+            atomic_output: None,
+            output_template: false,
+            faststart: false,
+            rate_conversion: false,
+            speed: SpeedFactor::default(),
+            fill_missing_frames: false,
+            live_source: false,
+            playback: Playback::Forward,
+            backpressure: BackpressurePolicy::Block,
+            limits: EncodeLimits::default(),
+            watchdog: None,
+            retry: None,
+            scaling: ScalingMode::Strict,
+            overlays: Vec::new(),
+            text_overlay: None,
+            preview: None,
+            tags: MediaTags::default(),
+            chapters: Vec::new(),
+            subtitles: None,
+            audio: Vec::new(),
+            audio_filters: Vec::new(),
+            audio_encoder: None,
             format: VideoFormat::Bgrx,
+            encoder_format: None,
+            color: ColorSettings::default(),
             // TODO: somehow make this support any video encoding? idk how I would do that
             // it would be nice to change the video encoding without *having* to change the caps
             // though typically you would have to anyway
             caps: Caps::builder("video/x-h264").build(),
+            rate_control: RateControl::Default,
+            two_pass: false,
             encoder_settings: HashMap::new(),
+            encoder_threads: None,
+            nice: None,
             muxer_settings: HashMap::new(),
+            custom_elements: Vec::new(),
+            on_message: None,
+            metrics: None,
+            namespace: None,
+        }
+    }
+
+    /// Takes single channel `Luma<u8>` frames, e.g. visualizations of scientific data
+    ///
+    /// Frames are only expanded to color by the encoder's converter, so they're a quarter
+    /// of the size of BGRA frames while being sent.
+    pub fn grayscale(mut self) -> Self {
+        self.format = VideoFormat::Gray8;
+        self
+    }
+
+    /// Tunes the encoder to output frames as soon as they come in, for live streams
+    /// and virtual cameras that can't have seconds of video buffered in the encoder
+    ///
+    /// Uses the fastest preset, no B-frames and a keyframe every second,
+    /// which costs quality for the bitrate.<br>
+    /// Only x264, x265 and VP8/9 are tuned, the encoder has to be picked before calling this.
+    pub fn realtime(mut self) -> Self {
+        self.live_source = true;
+
+        let gop = self.framerate as u32;
+        let settings: Vec<(&str, PropertyValue)> = match self.encoder.as_str() {
+            "x264enc" => vec![
+                ("tune", PropertyValue::Str("zerolatency".to_owned())),
+                ("speed-preset", PropertyValue::Enum("ultrafast".to_owned())),
+                ("key-int-max", gop.into()),
+                ("bframes", 0u32.into()),
+            ],
+            "x265enc" => vec![
+                ("tune", PropertyValue::Enum("zerolatency".to_owned())),
+                ("speed-preset", PropertyValue::Enum("ultrafast".to_owned())),
+                ("key-int-max", (gop as i32).into()),
+            ],
+            "vp8enc" | "vp9enc" => vec![
+                ("deadline", 1i64.into()),
+                ("lag-in-frames", 0i32.into()),
+                ("keyframe-max-dist", (gop as i32).into()),
+            ],
+            encoder => {
+                println!("Don't know how to tune {encoder} for realtime, only the appsrc is live");
+                Vec::new()
+            }
+        };
+
+        self.encoder_settings.extend(
+            settings
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value)),
+        );
+        self
+    }
+
+    /// Sets a constant bitrate that keeps a video of `duration` under `bytes`, e.g. Discord's upload limit
+    ///
+    /// 2% is kept for the container and, when there is `audio`, 128 kbit/s for the audio track,
+    /// the usual bitrate of the default audio encoders.<br>
+    /// `x264enc` also gets a one second VBV buffer so the rate can't drift,
+    /// set [`two_pass`](Self::two_pass) to land closer to the budget.
+    pub fn for_target_size(mut self, bytes: u64, duration: Duration) -> Self {
+        const CONTAINER_OVERHEAD: f64 = 0.02;
+        const AUDIO_KBPS: f64 = 128.0;
+
+        let total_kbps = bytes as f64 * 8.0 * (1.0 - CONTAINER_OVERHEAD)
+            / duration.as_secs_f64().max(0.001)
+            / 1000.0;
+        let audio_kbps = if self.audio.is_empty() {
+            0.0
+        } else {
+            AUDIO_KBPS
+        };
+
+        let video_kbps = total_kbps - audio_kbps;
+        if video_kbps < 1.0 {
+            println!(
+                "{bytes} bytes isn't enough for {duration:?} of video, using the lowest bitrate"
+            );
+        }
+
+        self.rate_control = RateControl::Cbr {
+            kbps: video_kbps.max(1.0) as u32,
+        };
+        if self.encoder == "x264enc" {
+            self.encoder_settings
+                .insert("vbv-buf-capacity".to_owned(), 1000u32.into());
         }
+        self
+    }
+
+    /// Writes the video to `<output>.part` and renames it once it's finalized,
+    /// `on_error` decides what happens to the partial file when the encode fails
+    pub fn atomic_output(mut self, on_error: PartialFile) -> Self {
+        self.atomic_output = Some(on_error);
+        self
+    }
+
+    /// Treats the output path as a template, see [`output_template`](Self::output_template)
+    pub fn output_template(mut self) -> Self {
+        self.output_template = true;
+        self
+    }
+
+    /// Ends the video early once one of `limits` is hit
+    pub fn limits(mut self, limits: EncodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Watches the encode for buffers that stop flowing, see [`Watchdog`]
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Retries with the fallback encoders of `retry` when the encoder fails to start
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Pushes a few blank frames through the pipeline these settings build,
+    /// with a `fakesink` in place of every sink of the output target,
+    /// and reports the caps every element negotiated
+    ///
+    /// Fails the same way a real encode would on missing plugins, bad encoder properties
+    /// or formats that can't be converted, without writing anything.<br>
+    /// Previews and subtitles are left out.
+    pub fn dry_run(&self) -> anyhow::Result<DryRunReport> {
+        dry_run::dry_run(self)
+    }
+
+    /// Sends the encoded video into `sink` instead of a file,
+    /// e.g. a `souphttpclientsink` or `fdsink`
+    pub fn custom_sink(mut self, sink: gst::Element) -> Self {
+        self.output = OutputTarget::Custom(sink);
+        self
+    }
+
+    /// Links extra elements into the pipeline at `stage`, after any already inserted there
+    pub fn insert_elements(mut self, stage: PipelineStage, elements: Vec<ElementSpec>) -> Self {
+        self.custom_elements
+            .extend(elements.into_iter().map(|element| (stage, element)));
+        self
+    }
+
+    /// Calls `callback` with every message posted on the pipeline's bus
+    pub fn on_message(mut self, callback: impl Fn(&gst::Message) + Send + Sync + 'static) -> Self {
+        self.on_message = Some(MessageCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Reports telemetry for every frame to `metrics`,
+    /// e.g. to measure how long frames take from being sent to being encoded
+    pub fn metrics(mut self, metrics: impl Metrics + 'static) -> Self {
+        self.metrics = Some(MetricsHook(Arc::new(metrics)));
+        self
+    }
+
+    /// Records audio from `input` next to the video, mixed with any audio added before
+    pub fn audio(mut self, input: impl Into<AudioInput>) -> Self {
+        self.audio.push(input.into());
+        self
+    }
+
+    /// Runs `filter` on the mixed audio after the filters added before
+    pub fn audio_filter(mut self, filter: AudioFilter) -> Self {
+        self.audio_filters.push(filter);
+        self
+    }
+
+    /// Starts the name of the encoding pipeline with `namespace` instead of `encoding pipeline`
+    ///
+    /// Every pipeline gets a number after its name so encodes running at the same time never share one,
+    /// this only makes it easier to tell them apart in GStreamer's debug logs and [`EncodeHandle::dump_graph`].<br>
+    /// Elements keep names like `encoder` and `sink`, which only have to be unique within their pipeline.
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Reads settings from a JSON encoding profile
+    ///
+    /// Fields that are left out keep the values of `VideoSettings::new(30, 1920, 1080)`.<br>
+    /// `caps` are written as a string like `video/x-h264, profile=high`, and settings that only
+    /// exist at runtime, like `on_message`, `metrics` and custom sink elements, are never saved.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Reads settings from a TOML encoding profile, the same way as [`from_json`](Self::from_json)
+    #[cfg(feature = "serde")]
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// Writes the settings as a JSON encoding profile
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Writes the settings as a TOML encoding profile
+    #[cfg(feature = "serde")]
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// What fields missing from a profile are filled in with
+    #[cfg(feature = "serde")]
+    fn profile_defaults() -> Self {
+        VideoSettings::new(30, 1920, 1080)
+    }
+
+    /// Settings for a ProRes 4444 video in a MOV file which keeps the alpha channel of frames
+    ///
+    /// Needs `avenc_prores_ks` from gst-libav.<br>
+    /// Frames should be sent as `Rgba<u8>` or `Bgra<u8>` images.
+    pub fn prores_4444(framerate: u64, width: u32, height: u32) -> Self {
+        let mut settings = VideoSettings::new(framerate, width, height);
+        settings.encoder = "avenc_prores_ks".to_owned();
+        settings.muxer = "qtmux".to_owned();
+        settings.format = VideoFormat::Bgra;
+        settings.encoder_format = Some(VideoFormat::A44410le);
+        settings.caps = Caps::builder("video/x-prores").build();
+        settings
+            .encoder_settings
+            .insert("profile".to_owned(), PropertyValue::Enum("4444".to_owned()));
+        settings
+    }
+
+    /// Settings for a VP9 video in a WebM file which keeps the alpha channel of frames,
+    /// the usual format for transparent overlays in browsers and OBS
+    ///
+    /// Frames are converted to `A420` before encoding, so the `vp9enc` used has to accept it,
+    /// the pipeline fails to start with one that doesn't.<br>
+    /// Frames should be sent as `Rgba<u8>` or `Bgra<u8>` images.
+    pub fn vp9_alpha(framerate: u64, width: u32, height: u32) -> Self {
+        let mut settings = VideoSettings::new(framerate, width, height);
+        settings.encoder = "vp9enc".to_owned();
+        settings.muxer = "webmmux".to_owned();
+        settings.format = VideoFormat::Bgra;
+        settings.encoder_format = Some(VideoFormat::A420);
+        settings.caps = Caps::builder("video/x-vp9").build();
+        settings
     }
 }
 
-/// Spawns a thread to do encoding, returning a channel to send frame data through.
+/// The colorimetry of a video, see [`VideoSettings::color`]
 ///
-/// It is safe to detach the thread as it will automatically close when the encoding is finished.
+/// The primaries and transfer function are assumed to already match the frames,
+/// only the matrix and range are converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorSettings {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::glib_enum"))]
+    pub primaries: VideoColorPrimaries,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::glib_enum"))]
+    pub transfer: VideoTransferFunction,
+    /// How RGB is turned into YUV
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::glib_enum"))]
+    pub matrix: VideoColorMatrix,
+    /// Whether YUV values use the full 0-255 range or the limited 16-235 range most players expect
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::glib_enum"))]
+    pub range: VideoColorRange,
+}
+
+impl Default for ColorSettings {
+    /// BT.709 with limited range, the usual color space of HD video
+    fn default() -> Self {
+        ColorSettings {
+            primaries: VideoColorPrimaries::Bt709,
+            transfer: VideoTransferFunction::Bt709,
+            matrix: VideoColorMatrix::Bt709,
+            range: VideoColorRange::Range16_235,
+        }
+    }
+}
+
+/// A callback for the raw messages of a pipeline's bus, see [`VideoSettings::on_message`]
+#[derive(Clone)]
+pub struct MessageCallback(pub Arc<dyn Fn(&gst::Message) + Send + Sync>);
+
+impl std::fmt::Debug for MessageCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MessageCallback")
+    }
+}
+
+/// What happens to frames sent faster than the encoder can handle them,
+/// see [`VideoSettings::backpressure`]
+///
+/// The dropping policies are meant for live sources like game captures,
+/// where falling behind is worse than a skipped frame.<br>
+/// How many frames were dropped is reported by [`EncodeHandle::dropped_frames`] and [`EncodeStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BackpressurePolicy {
+    /// Wait for the encoder, queueing frames or blocking the sender depending on how frames are sent
+    Block,
+    /// Throw new frames away while a frame is already waiting for the encoder
+    DropNewest,
+    /// Keep the last `max_frames` frames, throwing the oldest ones away
+    DropOldest { max_frames: u32 },
+}
+
+/// A playback speed multiplier, see [`VideoSettings::speed`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "f64", into = "f64"))]
+pub struct SpeedFactor(f64);
+
+impl SpeedFactor {
+    /// Makes a speed multiplier, which has to be a finite number above zero
+    pub fn new(factor: f64) -> anyhow::Result<Self> {
+        if !factor.is_finite() || factor <= 0.0 {
+            return Err(anyhow::Error::msg(format!(
+                "A speed factor has to be above zero, not {factor}"
+            )));
+        }
+
+        Ok(SpeedFactor(factor))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl Default for SpeedFactor {
+    fn default() -> Self {
+        SpeedFactor(1.0)
+    }
+}
+
+impl TryFrom<f64> for SpeedFactor {
+    type Error = anyhow::Error;
+
+    fn try_from(factor: f64) -> anyhow::Result<Self> {
+        SpeedFactor::new(factor)
+    }
+}
+
+impl From<SpeedFactor> for f64 {
+    fn from(speed: SpeedFactor) -> Self {
+        speed.0
+    }
+}
+
+/// How the encoder spends bits, see [`VideoSettings::rate_control`]
+///
+/// Understood by the x264, x265, VP8/9, NVENC and VA-API encoders,
+/// other encoders have to be configured through `encoder_settings`.<br>
+/// Quality values use the scale of the encoder, lower is better for all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RateControl {
+    /// Whatever the encoder does by default
+    Default,
+    /// Constant quality, the bitrate goes up and down with how complex the video is
+    Crf(u8),
+    /// A constant bitrate in kilobits per second, e.g. for streaming
+    Cbr { kbps: u32 },
+    /// A variable bitrate averaging `target` and never going over `max`, in kilobits per second
+    Vbr { target: u32, max: u32 },
+    /// The same quantizer for every frame, mostly useful for testing
+    Cqp(u8),
+}
+
+/// The order a set of frames is encoded in, see [`VideoSettings::playback`]
+///
+/// Frames are repeated by reference, so nothing is copied in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Playback {
+    Forward,
+    /// Last frame first
+    Reverse,
+    /// Forwards then backwards, `loops` times, ending on the first frame
+    ///
+    /// The frames at the turns aren't shown twice.
+    PingPong {
+        loops: u32,
+    },
+    /// Forwards `count` times
+    Loop {
+        count: u32,
+    },
+}
+
+impl Playback {
+    /// The indices of `len` frames in the order they're encoded
+    #[cfg(feature = "image")]
+    fn order(self, len: usize) -> Box<dyn Iterator<Item = usize>> {
+        match self {
+            Playback::Forward => Box::new(0..len),
+            Playback::Reverse => Box::new((0..len).rev()),
+            Playback::PingPong { .. } if len < 2 => Box::new(0..len),
+            Playback::PingPong { loops } => Box::new(
+                (0..loops)
+                    .flat_map(move |_| (0..len).chain((1..len - 1).rev()))
+                    .chain((loops > 0).then_some(0)),
+            ),
+            Playback::Loop { count } => Box::new((0..count).flat_map(move |_| 0..len)),
+        }
+    }
+}
+
+/// How frames that don't match the size of the video are fitted into it
+///
+/// Only applies to frames sent as images, raw frames always have to be the right size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScalingMode {
+    /// Refuse frames of the wrong size with an error
+    Strict,
+    /// Scale frames to exactly the size of the video, ignoring their aspect ratio
+    Stretch,
+    /// Scale frames to fit inside the video, filling the rest with black bars
+    Letterbox,
+    /// Cut the edges off frames to match the aspect ratio of the video, then scale them
+    ///
+    /// Needs `aspectratiocrop` from gst-plugins-good.
+    Crop,
+}
+
+/// An image like a logo or watermark drawn on top of every frame
+///
+/// Needs `gdkpixbufoverlay` from gst-plugins-good.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Overlay {
+    /// The image file to draw, in any format gdk-pixbuf can load
+    pub location: PathBuf,
+    /// How far from the left of the frame the image is drawn, in pixels
+    pub x: i32,
+    /// How far from the top of the frame the image is drawn, in pixels
+    pub y: i32,
+    /// The size to draw the image at, `None` uses the size of the image
+    pub size: Option<(u32, u32)>,
+    /// How opaque the image is, from 0 to 1
+    pub alpha: f64,
+}
+
+impl Overlay {
+    /// Draws the image at full size and opacity with its top left corner at `x`, `y`
+    pub fn new(location: impl Into<PathBuf>, x: i32, y: i32) -> Self {
+        Overlay {
+            location: location.into(),
+            x,
+            y,
+            size: None,
+            alpha: 1.0,
+        }
+    }
+}
+
+/// Text drawn on top of every frame
+///
+/// Needs the pango plugin from gst-plugins-base.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextOverlay {
+    /// What to draw
+    pub kind: TextKind,
+    /// The text to draw, or to put in front of the time
+    pub text: String,
+    /// A pango font description like `Sans Bold 24`, `None` uses the default font
+    pub font: Option<String>,
+    /// Where in the frame to draw the text
+    pub position: TextPosition,
+    /// Whether to darken the area behind the text to keep it readable
+    pub shaded: bool,
+}
+
+impl TextOverlay {
+    /// Draws `kind` in the top left corner with the default font
+    pub fn new(kind: TextKind, text: impl Into<String>) -> Self {
+        TextOverlay {
+            kind,
+            text: text.into(),
+            font: None,
+            position: TextPosition::TopLeft,
+            shaded: false,
+        }
+    }
+}
+
+/// What a [`TextOverlay`] draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextKind {
+    /// Only the text
+    Text,
+    /// The timestamp of each frame, useful for checking frame pacing
+    StreamTime,
+    /// The wall clock time each frame was encoded at
+    Clock,
+}
+
+/// Where a [`TextOverlay`] is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextPosition {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// Where in the pipeline custom elements are linked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PipelineStage {
+    /// Between `videoconvert` and the encoder, for filters on raw video like `deinterlace` or `videobalance`
+    ///
+    /// When [`VideoSettings::encoder_format`] is set the last element has to be able to output that format.
+    PreEncode,
+    /// Between the encoder and the muxer, for elements working on encoded video like `h264parse`
+    PostEncode,
+}
+
+/// An element to create and link into the pipeline
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElementSpec {
+    /// The name of the element factory, e.g. `videobalance`
+    pub factory: String,
+    /// Properties set on the element
+    pub properties: Vec<(String, PropertyValue)>,
+}
+
+impl ElementSpec {
+    pub fn new(factory: impl Into<String>) -> Self {
+        ElementSpec {
+            factory: factory.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    /// Sets a property of the element
+    pub fn property(mut self, name: impl Into<String>, value: impl Into<PropertyValue>) -> Self {
+        self.properties.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Metadata stored in the video file, see [`VideoSettings::tags`]
+///
+/// Only used by muxers that support tags, like `mp4mux`, `qtmux` and `matroskamux`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MediaTags {
+    pub title: Option<String>,
+    /// Who made the video
+    pub artist: Option<String>,
+    pub comment: Option<String>,
+    /// When the video was recorded, e.g. `gst::DateTime::new_now_local_time()`
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_impls::option_date_time")
+    )]
+    pub creation_date: Option<gst::DateTime>,
+}
+
+impl MediaTags {
+    /// Whether no tag is set
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.artist.is_none()
+            && self.comment.is_none()
+            && self.creation_date.is_none()
+    }
+}
+
+/// A single subtitle, shown from `start` to `end`
+pub type SubtitleCue = (Duration, Duration, String);
+
+/// Where the subtitles of a video come from, see [`VideoSettings::subtitles`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SubtitleSource {
+    /// An SRT or WebVTT file, parsed by `subparse`
+    File(PathBuf),
+    /// Cues sent while encoding, see [`SubtitleSource::channel`]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Cues(SubtitleReceiver),
+}
+
+impl SubtitleSource {
+    /// Creates a source that cues can be sent into while encoding
+    ///
+    /// Cues have to be sent in order, and roughly as fast as the frames they go with,
+    /// as the muxer waits for them before writing the frames.<br>
+    /// The video can't be finalized until the sender is dropped.
+    pub fn channel() -> (Self, Sender<SubtitleCue>) {
+        let (sender, receiver) = channel();
+        (
+            SubtitleSource::Cues(SubtitleReceiver(Arc::new(Mutex::new(Some(receiver))))),
+            sender,
+        )
+    }
+}
+
+/// The receiving end of [`SubtitleSource::channel`], which can only be used by one encode
+#[derive(Clone)]
+pub struct SubtitleReceiver(pub(crate) Arc<Mutex<Option<Receiver<SubtitleCue>>>>);
+
+impl std::fmt::Debug for SubtitleReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SubtitleReceiver")
+    }
+}
+
+/// A named point in the video, see [`VideoSettings::chapters`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Chapter {
+    pub title: String,
+    /// Where the chapter starts, it lasts until the next chapter or the end of the video
+    pub start: Duration,
+}
+
+impl Chapter {
+    pub fn new(title: impl Into<String>, start: Duration) -> Self {
+        Chapter {
+            title: title.into(),
+            start,
+        }
+    }
+}
+
+/// Where a live preview of the encode is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PreviewTarget {
+    /// Whichever video sink GStreamer picks for this system
+    Window,
+    /// An OpenGL window, needs the gl plugin from gst-plugins-base
+    Gl,
+}
+
+/// The kind of file the pipeline writes
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Container {
+    /// A video encoded by `encoder` and muxed by `muxer`
+    Video,
+    /// A video encoded by `encoder` in an MPEG transport stream made by `mpegtsmux`
+    ///
+    /// `muxer` is ignored and H.264 and H.265 `caps` are switched to the byte-stream format
+    /// the muxer needs.
+    MpegTs,
+    /// A video encoded by `encoder` in a Matroska file made by `matroskamux`
+    ///
+    /// `muxer` is ignored.<br>
+    /// Unlike MP4 files, a recording that gets interrupted is still playable up to where it stopped.
+    Matroska,
+    /// An animated GIF made by `gifenc` from gst-plugins-rs
+    ///
+    /// `encoder`, `muxer` and `caps` are ignored.
+    Gif {
+        /// How much effort to put into picking each frame's palette,
+        /// from 1 (best quality) to 30 (fastest)
+        speed: u32,
+        /// How many times to repeat, -1 loops forever
+        repeat: i32,
+    },
+    /// An animated WebP made by `webpenc`
+    ///
+    /// `encoder`, `muxer` and `caps` are ignored.
+    WebP {
+        /// The quality of each frame from 0 to 100
+        quality: f32,
+        /// Whether to encode frames losslessly, ignoring `quality`
+        lossless: bool,
+        /// How many times to loop, 0 loops forever
+        loops: u32,
+    },
+    /// Uncompressed frames in a YUV4MPEG2 stream made by `y4menc`, for codec testing
+    /// or piping into other tools, see [`Y4mReader`] for reading them back
+    ///
+    /// `encoder`, `muxer` and `caps` are ignored.<br>
+    /// Frames are converted to I420 unless `encoder_format` picks `Y42b` or `Y444`.
+    Y4m,
+}
+
+/// Where the pipeline writes what it makes
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutputTarget {
+    /// A single file at the path given when starting the encode
+    File,
+    /// A numbered PNG for every frame, written by `multifilesink`
+    ///
+    /// The output path given when starting the encode and the `container` are ignored.
+    ImageSequence {
+        /// The directory to write images into, which must already exist
+        dir: PathBuf,
+        /// A printf style pattern for the file names, e.g. `frame_%05d.png`
+        pattern: String,
+    },
+    /// An already constructed sink element that the muxed video is sent into
+    ///
+    /// The output path given when starting the encode is ignored.<br>
+    /// An element can only be in one pipeline at a time, so settings using this can't be used for
+    /// two encodes at once.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(gst::Element),
+    /// Raw frames written to a v4l2loopback device, e.g. `/dev/video10`, making the video
+    /// show up as a webcam
+    ///
+    /// The output path given when starting the encode and the `container` are ignored.<br>
+    /// Frames are converted to whichever format the device accepts. Linux only.
+    V4l2Loopback { device: PathBuf },
+    /// An MPEG-DASH manifest with fragmented MP4 segments next to it, written by `dashsink`
+    ///
+    /// The output path given when starting the encode and `muxer` are ignored.<br>
+    /// Segments are cut at keyframes, which the sink requests from the encoder on its own.
+    Dash {
+        /// Where to write the manifest, e.g. `stream/manifest.mpd`
+        mpd_path: PathBuf,
+        /// How long each segment should be, rounded to whole seconds
+        segment_duration: Duration,
+    },
+    /// Several files encoded from the same frames at different sizes and encoder settings,
+    /// e.g. a 1080p, 720p and 480p version for adaptive streaming
+    ///
+    /// The output path given when starting the encode is ignored.<br>
+    /// Every rendition is encoded at the same time, so this takes as much CPU as encoding each
+    /// of them separately.
+    MultiOutput(Vec<Rendition>),
+}
+
+/// What happens to the `.part` file of an [`atomic_output`](VideoSettings::atomic_output)
+/// when the encode fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PartialFile {
+    /// Leaves it in place, e.g. to recover what was encoded
+    Keep,
+    /// Removes it
+    Delete,
+}
+
+/// One of the files written by [`OutputTarget::MultiOutput`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rendition {
+    /// Where to write this rendition
+    pub location: PathBuf,
+    /// The width frames are scaled to
+    pub width: u32,
+    /// The height frames are scaled to
+    pub height: u32,
+    /// Encoder properties set on top of the `encoder_settings` of the video, e.g. `bitrate`
+    pub encoder_settings: HashMap<String, PropertyValue>,
+}
+
+impl Rendition {
+    pub fn new(location: impl Into<PathBuf>, width: u32, height: u32) -> Self {
+        Rendition {
+            location: location.into(),
+            width,
+            height,
+            encoder_settings: HashMap::new(),
+        }
+    }
+
+    /// Sets a property of this rendition's encoder
+    pub fn encoder_setting(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<PropertyValue>,
+    ) -> Self {
+        self.encoder_settings.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Starts encoding in the background, returning a channel to send frame data through.
+///
+/// Fails if the pipeline can't be built, e.g. when an element is missing or a property doesn't exist.<br>
+/// Dropping the returned [`EncodeHandle`] without joining it ends the stream
+/// and finalizes the video with whatever frames were sent so far.
 ///
 /// The `BUFFER_SIZE` associated constant is how many frames the encoder
 /// will wait for before continuing the encoding.<br>
@@ -80,56 +1159,889 @@ impl VideoSettings {
 /// the encoder will exit properly and encode however many frames it was able to get.
 ///
 /// # Deadlock
-/// Joining the thread before dropping the sender will deadlock.
+/// Joining the handle before dropping the sender will deadlock.<br>
+/// [`with_encoder`] doesn't have this problem if the frames can be pushed from a single scope.
+#[cfg(feature = "image")]
 pub fn start_encoding<
-    Format: Pixel<Subpixel = u8> + Send + Sync + 'static,
+    Format: PixelFormat + Send + Sync + 'static,
     Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
     const BUFFER_SIZE: usize,
 >(
     output_path: &str,
     video_settings: VideoSettings,
-) -> (JoinHandle<()>, Sender<ImageBuffer<Format, Container>>) {
+) -> anyhow::Result<(EncodeHandle, Sender<ImageBuffer<Format, Container>>)> {
+    start_encoding_with_buffer_size(output_path, video_settings, BUFFER_SIZE)
+}
+
+/// The same as [`start_encoding`] but with the number of frames the encoder waits for
+/// given at runtime, e.g. from a config file
+///
+/// # Deadlock
+/// Joining the handle before dropping the sender will deadlock.
+#[cfg(feature = "image")]
+pub fn start_encoding_with_buffer_size<
+    Format: PixelFormat + Send + Sync + 'static,
+    Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+>(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    buffer_size: usize,
+) -> anyhow::Result<(EncodeHandle, Sender<ImageBuffer<Format, Container>>)> {
+    video_settings.format = Format::VIDEO_FORMAT;
     let (sender, recv) = channel();
+    let recv = Arc::new(Mutex::new(recv));
 
-    let path = output_path.to_owned();
+    let handle = spawn_encoder(
+        output_path,
+        video_settings,
+        data_provider_impls::sized_reciever_data_provider::<Format, Container>,
+        (
+            Arc::new(Mutex::new(0)),
+            recv.clone(),
+            Arc::new(Mutex::new(None)),
+            buffer_size,
+        ),
+    )?;
+    handle.close_on_failure(recv);
 
-    let handle = std::thread::spawn(|| {
-        start_encoding_internal::<Format, Container, BUFFER_SIZE>(recv, path, video_settings)
-    });
+    Ok((handle, sender))
+}
 
-    (handle, sender)
+/// The same as [`start_encoding`] but the channel holds at most `bound` frames
+///
+/// Sending blocks while the channel is full, so a renderer that's faster than the encoder
+/// is slowed down to its speed instead of queueing up frames in memory.
+///
+/// # Deadlock
+/// Joining the handle before dropping the sender will deadlock.
+#[cfg(feature = "image")]
+pub fn start_encoding_bounded<
+    Format: PixelFormat + Send + Sync + 'static,
+    Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+    const BUFFER_SIZE: usize,
+>(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    bound: usize,
+) -> anyhow::Result<(EncodeHandle, SyncSender<ImageBuffer<Format, Container>>)> {
+    video_settings.format = Format::VIDEO_FORMAT;
+    let (sender, recv) = sync_channel(bound);
+    let recv = Arc::new(Mutex::new(recv));
+
+    let handle = spawn_encoder(
+        output_path,
+        video_settings,
+        data_provider_impls::reciever_data_provider::<Format, Container, BUFFER_SIZE>,
+        (
+            Arc::new(Mutex::new(0)),
+            recv.clone(),
+            Arc::new(Mutex::new(None)),
+        ),
+    )?;
+    handle.close_on_failure(recv);
+
+    Ok((handle, sender))
 }
 
-fn start_encoding_internal<
-    Format: Pixel<Subpixel = u8> + Send + Sync + 'static,
+/// The same as [`start_encoding`] but every frame carries its own timestamp
+///
+/// Useful for variable framerate sources like screen captures, where frames don't arrive at a steady rate.<br>
+/// Timestamps are measured from the start of the video and have to increase with every frame.
+///
+/// # Deadlock
+/// Joining the handle before dropping the sender will deadlock.
+#[cfg(feature = "image")]
+pub fn start_encoding_timed<
+    Format: PixelFormat + Send + Sync + 'static,
     Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
     const BUFFER_SIZE: usize,
 >(
-    recv: Receiver<ImageBuffer<Format, Container>>,
-    output_path: String,
+    output_path: &str,
+    mut video_settings: VideoSettings,
+) -> anyhow::Result<(EncodeHandle, Sender<TimedFrame<Format, Container>>)> {
+    video_settings.format = Format::VIDEO_FORMAT;
+    let (sender, recv) = channel();
+    let recv = Arc::new(Mutex::new(recv));
+
+    let handle = spawn_encoder(
+        output_path,
+        video_settings,
+        data_provider_impls::timed_reciever_data_provider::<Format, Container, BUFFER_SIZE>,
+        (Arc::new(Mutex::new(0)), recv.clone()),
+    )?;
+    handle.close_on_failure(recv);
+
+    Ok((handle, sender))
+}
+
+/// A frame with the time it should be shown at, see [`start_encoding_timed`]
+#[cfg(feature = "image")]
+pub struct TimedFrame<Format: Pixel, Container> {
+    pub image: ImageBuffer<Format, Container>,
+    /// When the frame starts, measured from the start of the video
+    pub pts: Duration,
+}
+
+/// Starts encoding in the background, returning a [`RawFrameSender`] to send frame bytes through.
+///
+/// This is the same as [`start_encoding`] but without going through the `image` crate,
+/// useful when frames already exist as bytes in the right format.
+///
+/// # Deadlock
+/// Joining the handle before dropping the sender will deadlock.
+pub fn start_encoding_raw<const BUFFER_SIZE: usize>(
+    output_path: &str,
     video_settings: VideoSettings,
-) {
-    init_encoder();
+) -> anyhow::Result<(EncodeHandle, RawFrameSender)> {
+    let (sender, recv) = channel();
+    let recv = Arc::new(Mutex::new(recv));
+    let sender = RawFrameSender {
+        sender,
+        video_info: make_video_info(&video_settings),
+    };
 
-    encode_video::<_, _, _, Option<()>>(
+    let handle = spawn_encoder(
         output_path,
         video_settings,
-        data_provider_impls::reciever_data_provider::<Format, Container, BUFFER_SIZE>,
-        None,
-        (Arc::new(Mutex::new(0)), Arc::new(Mutex::new(recv))),
+        data_provider_impls::raw_reciever_data_provider::<BUFFER_SIZE>,
+        (Arc::new(Mutex::new(0)), recv.clone()),
+    )?;
+    handle.close_on_failure(recv);
+
+    Ok((handle, sender))
+}
+
+/// Builds and starts the pipeline on the calling thread, leaving only the bus to be watched in the background
+fn spawn_encoder<
+    S: Send + Sync + Clone + 'static,
+    O: Into<data_provider::DataGenReturn> + 'static,
+    P: data_provider::DataProvider<S, O> + Send + Sync + 'static,
+>(
+    output_path: &str,
+    video_settings: VideoSettings,
+    need_data: P,
+    state: S,
+) -> anyhow::Result<EncodeHandle> {
+    init_encoder();
+
+    let (pipeline, appsrc, video_info) =
+        init_pipeline(output_path.to_owned(), video_settings.clone())?;
+    let handle_settings = video_settings.clone();
+
+    start_pipeline(
+        &pipeline,
+        &appsrc,
+        video_info,
+        video_settings,
+        need_data,
+        None::<fn(&gstreamer_app::AppSrc, &VideoSettings, S) -> O>,
+        state,
     );
+
+    Ok(EncodeHandle::spawn(pipeline, appsrc, &handle_settings))
 }
 
-/// Encodes a set of frames
+/// Runs `f` with a [`BlockingFrameSink`] connected to a new encoder, then finalizes the video
+///
+/// The stream is always ended once `f` returns, even if it returned an error or panicked,
+/// so there is no sender to drop or thread to join.
 ///
 /// Blocks the current thread till the encoding is done
-pub fn encode_frames(output_path: &str, video_settings: VideoSettings, frames: Vec<DynamicImage>) {
+pub fn with_encoder<F: FnOnce(&mut BlockingFrameSink) -> anyhow::Result<()>>(
+    output_path: &str,
+    video_settings: VideoSettings,
+    f: F,
+) -> anyhow::Result<EncodeStats> {
+    init_encoder();
+
+    let (pipeline, appsrc, video_info) =
+        init_pipeline(output_path.to_owned(), video_settings.clone())?;
+    pipeline.set_state(gst::State::Playing)?;
+    let handle = EncodeHandle::spawn(pipeline, appsrc.clone(), &video_settings);
+    let mut sink = BlockingFrameSink::new(
+        appsrc,
+        video_info,
+        video_settings.clone(),
+        handle.frame_metadata(),
+        handle.limit_state(),
+    );
+
+    let result = f(&mut sink);
+    sink.end_of_stream();
+
+    let stats = handle.join();
+    // Pushing fails once a limit has ended the video, which isn't an error of the encode
+    if !matches!(&stats, Ok(stats) if stats.stop_reason.is_some()) {
+        result?;
+    }
+
+    Ok(stats?)
+}
+
+/// A frame that stays in GPU memory on its way to the encoder,
+/// pushed with [`BlockingFrameSink::push_gpu_frame`]
+#[cfg(any(all(target_os = "linux", feature = "dmabuf"), feature = "cuda"))]
+pub enum GpuFrame {
+    /// A DMA-BUF, e.g. exported from a Vulkan or GL texture, for `vaapi` encoders,
+    /// needs the `dmabuf` feature
+    #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+    DmaBuf(DmaBufFrame),
+    /// CUDA device memory for `nvh264enc`, needs the `cuda` feature and GStreamer 1.24
+    #[cfg(feature = "cuda")]
+    Cuda(CudaFrame),
+}
+
+#[cfg(any(all(target_os = "linux", feature = "dmabuf"), feature = "cuda"))]
+impl GpuFrame {
+    pub(crate) fn into_buffer(self, video_info: &VideoInfo) -> anyhow::Result<gst::Buffer> {
+        match self {
+            #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+            GpuFrame::DmaBuf(frame) => frame.into_buffer(video_info),
+            #[cfg(feature = "cuda")]
+            GpuFrame::Cuda(frame) => frame.into_buffer(video_info),
+        }
+    }
+
+    /// The caps feature telling downstream elements what kind of memory the frame is in
+    pub(crate) fn caps_feature(&self) -> Option<&'static str> {
+        match *self {
+            #[cfg(all(target_os = "linux", feature = "dmabuf"))]
+            GpuFrame::DmaBuf(_) => Some("memory:DMABuf"),
+            #[cfg(feature = "cuda")]
+            GpuFrame::Cuda(_) => Some("memory:CUDAMemory"),
+        }
+    }
+}
+
+/// Sends frames of raw bytes to an encoder started by [`start_encoding_raw`]
+///
+/// The caller is responsible for every frame being laid out exactly as the `VideoInfo`
+/// made from the `VideoSettings` describes, including any row padding.<br>
+/// Only the size of frames is checked.
+#[derive(Debug, Clone)]
+pub struct RawFrameSender {
+    sender: Sender<gst::Buffer>,
+    video_info: VideoInfo,
+}
+
+impl RawFrameSender {
+    /// Sends an owned frame to the encoder without copying it
+    pub fn send(&self, frame: Vec<u8>) -> anyhow::Result<()> {
+        self.check_size(frame.len())?;
+        self.send_buffer(gst::Buffer::from_mut_slice(frame))
+    }
+
+    /// Sends a frame from a [`FramePool`] without copying it,
+    /// the frame goes back to the pool once it has been encoded
+    pub fn send_pooled(&self, frame: PooledFrame) -> anyhow::Result<()> {
+        self.check_size(frame.len())?;
+        self.send_buffer(frame.into_buffer())
+    }
+
+    /// Creates a pool of frames the right size for this encoder
+    pub fn frame_pool(&self, max_free: usize) -> FramePool {
+        FramePool::new(self.frame_size(), max_free)
+    }
+
+    fn check_size(&self, size: usize) -> anyhow::Result<()> {
+        if size != self.frame_size() {
+            return Err(anyhow::Error::msg(format!(
+                "Frame is {size} bytes but the video format needs {} bytes",
+                self.frame_size()
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn send_buffer(&self, buffer: gst::Buffer) -> anyhow::Result<()> {
+        self.sender
+            .send(buffer)
+            .map_err(|_| anyhow::Error::msg("The encoder has already stopped"))
+    }
+
+    /// Copies a frame and sends it to the encoder
+    pub fn push_raw_frame(&self, frame: &[u8]) -> anyhow::Result<()> {
+        self.send(frame.to_vec())
+    }
+
+    /// Lays out tightly packed planes as the video format expects and sends them to the encoder
+    ///
+    /// This is the easiest way to send planar formats like I420 or NV12,
+    /// see [`write_planes`](data_provider_impls::write_planes) for how the planes should look.
+    pub fn push_planes(&self, planes: &[&[u8]]) -> anyhow::Result<()> {
+        let mut frame = vec![0; self.frame_size()];
+        data_provider_impls::write_planes(planes, &mut frame, &self.video_info)?;
+        self.send(frame)
+    }
+
+    /// How many bytes each frame needs to be
+    pub fn frame_size(&self) -> usize {
+        self.video_info.size()
+    }
+}
+
+/// The async version of [`start_encoding`].
+///
+/// Encoding still happens on a blocking thread, the returned `JoinHandle` can be awaited
+/// to wait for the encoder to finalize and get any error it hit.<br>
+/// The channel holds at most `BUFFER_SIZE` frames, so sending will wait while the encoder catches up.
+///
+/// Must be called from within a tokio runtime.
+///
+/// # Deadlock
+/// Awaiting the handle before dropping the sender will never complete.
+#[cfg(all(feature = "image", feature = "tokio"))]
+pub fn start_encoding_async<
+    Format: PixelFormat + Send + Sync + 'static,
+    Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+    const BUFFER_SIZE: usize,
+>(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+) -> (
+    tokio::task::JoinHandle<anyhow::Result<EncodeStats>>,
+    tokio::sync::mpsc::Sender<ImageBuffer<Format, Container>>,
+) {
+    video_settings.format = Format::VIDEO_FORMAT;
+    let (sender, recv) = tokio::sync::mpsc::channel(BUFFER_SIZE.max(1));
+
+    let path = output_path.to_owned();
+
+    let handle = tokio::task::spawn_blocking(move || {
+        init_encoder();
+
+        encode_video::<_, _, _, Option<()>>(
+            path,
+            video_settings,
+            data_provider_impls::async_reciever_data_provider::<Format, Container, BUFFER_SIZE>,
+            None,
+            (Arc::new(Mutex::new(0)), Arc::new(Mutex::new(recv))),
+        )
+    });
+
+    (handle, sender)
+}
+
+/// Encodes every frame produced by an async stream, e.g. frames read from the network
+///
+/// Frames are only pulled from the stream while the encoder has room for them,
+/// at most `BUFFER_SIZE` frames wait to be encoded, so a fast stream is slowed down to the encoder's speed.<br>
+/// The video is finalized once the stream ends.
+///
+/// Must be called from within a tokio runtime, see [`start_encoding_async`].
+#[cfg(all(feature = "image", feature = "tokio"))]
+pub async fn encode_stream<
+    Format: PixelFormat + Send + Sync + 'static,
+    Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+    const BUFFER_SIZE: usize,
+>(
+    output_path: &str,
+    video_settings: VideoSettings,
+    frames: impl futures_core::Stream<Item = ImageBuffer<Format, Container>>,
+) -> anyhow::Result<EncodeStats> {
+    let (handle, sender) =
+        start_encoding_async::<Format, Container, BUFFER_SIZE>(output_path, video_settings);
+
+    let mut frames = std::pin::pin!(frames);
+    while let Some(frame) = std::future::poll_fn(|cx| frames.as_mut().poll_next(cx)).await {
+        // The encoder only stops taking frames when it fails, which the handle returns
+        if sender.send(frame).await.is_err() {
+            break;
+        }
+    }
+
+    drop(sender);
+    handle.await?
+}
+
+/// Encodes every frame produced by an iterator
+///
+/// Frames are only pulled from the iterator as the encoder needs them,
+/// so large or lazily generated videos never have to be fully in memory.
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_iter<
+    Format: PixelFormat + Send + Sync + 'static,
+    Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+    Frames: Iterator<Item = ImageBuffer<Format, Container>> + Send + 'static,
+>(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    frames: Frames,
+) -> anyhow::Result<EncodeStats> {
+    video_settings.format = Format::VIDEO_FORMAT;
     init_encoder();
     encode_video::<_, _, _, Option<()>>(
         output_path.to_owned(),
         video_settings,
-        data_provider_impls::vec_data_provider,
+        data_provider_impls::iter_data_provider::<Format, Container, Frames>,
         None,
-        (Arc::new(Mutex::new(0)), Arc::new(RwLock::new(frames))),
+        (Arc::new(Mutex::new(0)), Arc::new(Mutex::new(frames))),
+    )
+}
+
+/// Encodes a set of frames in the order set by [`VideoSettings::playback`]
+///
+/// When played forwards each frame is dropped as soon as it has been sent to the encoder,
+/// otherwise they're all kept till the encoding is done.
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_frames(
+    output_path: &str,
+    video_settings: VideoSettings,
+    frames: Vec<DynamicImage>,
+) -> anyhow::Result<EncodeStats> {
+    match video_settings.playback {
+        Playback::Forward if !video_settings.two_pass => {
+            push_dynamic_frames(output_path, video_settings, frames.into_iter())
+        }
+        _ => encode_frames_ref(output_path, video_settings, &frames),
+    }
+}
+
+/// Encodes a set of frames with an audio file like a WAV or FLAC muxed under them,
+/// trimmed or padded with silence to the length of the video
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_frames_with_audio(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    frames: Vec<DynamicImage>,
+    audio_path: impl Into<PathBuf>,
+) -> anyhow::Result<EncodeStats> {
+    let length = pipeline::frame_time(frames.len() as u64, &video_settings);
+    video_settings
+        .audio
+        .push(AudioInput::new(AudioSource::File {
+            path: audio_path.into(),
+            length: Some(Duration::from_nanos(length.nseconds())),
+        }));
+
+    encode_frames(output_path, video_settings, frames)
+}
+
+/// Encodes a set of frames by splitting them into `chunks` parts that are encoded at the same time,
+/// then joining the parts without re-encoding them
+///
+/// Scales with the number of cores for offline renders, but every part starts on a keyframe
+/// and the encoder's rate control can't look across parts.<br>
+/// Only works for [`OutputTarget::File`], subtitles, previews and `playback` aren't supported.
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_frames_parallel(
+    output_path: &str,
+    video_settings: VideoSettings,
+    mut frames: Vec<DynamicImage>,
+    chunks: usize,
+) -> anyhow::Result<EncodeStats> {
+    if !matches!(video_settings.output, OutputTarget::File) {
+        return Err(anyhow::Error::msg(
+            "Parallel encoding only works when writing to a file",
+        ));
+    }
+
+    let start = std::time::Instant::now();
+
+    let chunks = chunks.clamp(1, frames.len().max(1));
+    if chunks == 1 {
+        return encode_frames(output_path, video_settings, frames);
+    }
+
+    // Resolved once so the parts, the joined file and the clean up all agree on the names
+    let output_path = if video_settings.output_template {
+        resolve_output_path(output_path)?
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        output_path.to_owned()
+    };
+
+    let chunk_len = frames.len().div_ceil(chunks);
+    let mut parts = Vec::new();
+    while !frames.is_empty() {
+        let rest = frames.split_off(chunk_len.min(frames.len()));
+        parts.push(std::mem::replace(&mut frames, rest));
+    }
+
+    // Tags and chapters are written when the parts are joined
+    let mut part_settings = video_settings.clone();
+    part_settings.tags = MediaTags::default();
+    part_settings.chapters = Vec::new();
+    part_settings.subtitles = None;
+    part_settings.preview = None;
+    part_settings.playback = Playback::Forward;
+    part_settings.output_template = false;
+
+    let paths = (0..parts.len())
+        .map(|i| format!("{output_path}.part{i}"))
+        .collect::<Vec<_>>();
+
+    let results = std::thread::scope(|scope| {
+        let handles = parts
+            .into_iter()
+            .zip(&paths)
+            .map(|(part, path)| {
+                let settings = part_settings.clone();
+                scope.spawn(move || encode_frames(path, settings, part))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let result = results
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .and_then(|parts| {
+            concat_files(&paths, &output_path, &video_settings)?;
+            Ok(EncodeStats::new(
+                parts.iter().map(|part| part.frames).sum(),
+                parts.iter().map(|part| part.dropped_frames).sum(),
+                parts.iter().map(|part| part.duration).sum(),
+                std::fs::metadata(&output_path)?.len(),
+                start.elapsed(),
+            ))
+        });
+
+    for path in &paths {
+        let _ = std::fs::remove_file(path);
+    }
+
+    result
+}
+
+/// Encodes a set of frames without taking ownership of them,
+/// in the order set by [`VideoSettings::playback`]
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_frames_ref(
+    output_path: &str,
+    video_settings: VideoSettings,
+    frames: &[DynamicImage],
+) -> anyhow::Result<EncodeStats> {
+    if video_settings.two_pass {
+        return encode_two_pass(output_path, video_settings, frames);
+    }
+
+    let order = video_settings.playback.order(frames.len());
+    push_dynamic_frames(output_path, video_settings, order.map(|i| &frames[i]))
+}
+
+/// Encodes the frames once to learn how complex they are, then again using the stats of the first pass
+#[cfg(feature = "image")]
+fn encode_two_pass(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    frames: &[DynamicImage],
+) -> anyhow::Result<EncodeStats> {
+    video_settings.two_pass = false;
+    if video_settings.encoder != "x264enc" {
+        println!(
+            "{} doesn't support two pass encoding, using a single pass",
+            video_settings.encoder
+        );
+        return encode_frames_ref(output_path, video_settings, frames);
+    }
+
+    let cache = std::env::temp_dir().join(format!(
+        "stream_encoder-{}-{}.stats",
+        std::process::id(),
+        pipeline::next_pipeline_id()
+    ));
+    let first_output = cache.with_extension("pass1");
+    video_settings.encoder_settings.insert(
+        "multipass-cache-file".to_owned(),
+        cache.to_string_lossy().as_ref().into(),
     );
+
+    // Only the stats of the first pass are kept, so it doesn't need the extra streams
+    let mut first_pass = video_settings.clone();
+    first_pass.output = OutputTarget::File;
+    first_pass.audio.clear();
+    first_pass.subtitles = None;
+    first_pass.preview = None;
+    first_pass
+        .encoder_settings
+        .insert("pass".to_owned(), PropertyValue::Enum("pass1".to_owned()));
+    let first = encode_frames_ref(&first_output.to_string_lossy(), first_pass, frames);
+    let _ = std::fs::remove_file(&first_output);
+    first?;
+
+    video_settings
+        .encoder_settings
+        .insert("pass".to_owned(), PropertyValue::Enum("pass2".to_owned()));
+    let second = encode_frames_ref(output_path, video_settings, frames);
+    let _ = std::fs::remove_file(&cache);
+    // x264 also leaves the macroblock tree stats next to the cache file
+    let _ = std::fs::remove_file(cache.with_extension("stats.mbtree"));
+    second
+}
+
+/// Encodes `duration` of `videotestsrc`'s test pattern with the settings instead of frames,
+/// e.g. to check in CI that GStreamer and the plugins the settings need are installed
+///
+/// Everything after the appsrc is built exactly as it would be for real frames.
+///
+/// Blocks the current thread till the encoding is done
+pub fn encode_test_pattern(
+    output_path: &str,
+    video_settings: VideoSettings,
+    duration: Duration,
+) -> anyhow::Result<EncodeStats> {
+    init_encoder();
+
+    let pipeline = new_pipeline(&video_settings);
+
+    let src = gst::ElementFactory::make("videotestsrc", Some("source"))?;
+    let frames = (duration.as_secs_f64() * video_settings.framerate as f64).round() as i32;
+    src.set_property("num-buffers", frames.max(1));
+
+    let filter = gst::ElementFactory::make("capsfilter", Some("source caps"))?;
+    filter.set_property("caps", make_video_info(&video_settings).to_caps()?);
+
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output_path);
+
+    link_encoding(&pipeline, vec![src.clone(), filter], sink, &video_settings)?;
+    let stats = StatsCollector::install(
+        &pipeline,
+        &src.static_pad("src").unwrap(),
+        &video_settings.limits,
+    );
+
+    pipeline.set_state(gst::State::Playing)?;
+    let result = wait_for_eos(&pipeline, video_settings.on_message.as_ref());
+    finish_partial_output(&pipeline, video_settings.atomic_output, result.is_ok())?;
+    result?;
+    Ok(stats.stats())
+}
+
+/// Pushes frames straight into the pipeline from the current thread,
+/// blocking whenever the encoder falls behind
+///
+/// When the encoder fails to start and [`VideoSettings::retry`] allows it,
+/// the pipeline is rebuilt with a fallback encoder and the frames pushed so far are pushed again.
+#[cfg(feature = "image")]
+fn push_dynamic_frames(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    mut frames: impl Iterator<Item = impl Borrow<DynamicImage>>,
+) -> anyhow::Result<EncodeStats> {
+    init_encoder();
+
+    let mut output_path = output_path.to_owned();
+    let mut pushed_frames = PushedFrames::new(video_settings.retry.as_ref());
+
+    loop {
+        let (pipeline, appsrc, video_info) =
+            init_pipeline(output_path.clone(), video_settings.clone())?;
+        appsrc.set_block(true);
+
+        pipeline.set_state(gst::State::Playing)?;
+
+        // The bus has to be watched while we push so errors can unblock the appsrc
+        let bus_pipeline = pipeline.clone();
+        let on_message = video_settings.on_message.clone();
+        let bus_thread =
+            std::thread::spawn(move || wait_for_eos(&bus_pipeline, on_message.as_ref()));
+
+        let stats = StatsCollector::install(
+            &pipeline,
+            &appsrc.static_pad("src").unwrap(),
+            &video_settings.limits,
+        );
+        let pushed = push_frames(
+            &appsrc,
+            &video_info,
+            &video_settings,
+            &mut frames,
+            &mut pushed_frames,
+        );
+        let result = bus_thread.join().unwrap();
+        finish_partial_output(
+            &pipeline,
+            video_settings.atomic_output,
+            pushed.is_ok() && result.is_ok(),
+        )?;
+
+        if let (Err(error), Some(retry)) = (&result, video_settings.retry.clone()) {
+            if pushed_frames.complete() && retry.fall_back(&mut video_settings, error) {
+                // The retry writes over the same file instead of resolving the template again
+                if let Some(location) = pipeline
+                    .by_name("sink")
+                    .and_then(|sink| filesink_location(&sink))
+                {
+                    output_path = location.trim_end_matches(".part").to_owned();
+                    video_settings.output_template = false;
+                }
+                continue;
+            }
+        }
+
+        pushed?;
+        result?;
+        return Ok(stats.stats());
+    }
+}
+
+/// Pushes the frames kept in `pushed_frames` then the rest of the frames into the appsrc
+/// from the current thread, ending the stream after, stopping early if the pipeline stops
+#[cfg(feature = "image")]
+fn push_frames(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+    frames: impl Iterator<Item = impl Borrow<DynamicImage>>,
+    pushed_frames: &mut PushedFrames,
+) -> anyhow::Result<()> {
+    let mut result = Ok(());
+
+    if !pushed_frames.replay(appsrc) {
+        let _ = appsrc.end_of_stream();
+        return result;
+    }
+
+    for image in frames {
+        let image = image.borrow();
+        let frame_info = match data_provider_impls::frame_info(
+            appsrc,
+            video_info,
+            video_settings,
+            image.width(),
+            image.height(),
+        ) {
+            Ok(frame_info) => frame_info,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+        let mut buffer = match acquire_frame_buffer(appsrc, &frame_info) {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        };
+
+        {
+            let buffer = buffer.get_mut().unwrap();
+            set_frame_timing(buffer, pushed_frames.count(), video_settings);
+            data_provider_impls::write_dynamic_frame(image, buffer, &frame_info);
+        }
+
+        pushed_frames.keep(&buffer);
+        if appsrc.push_buffer(buffer).is_err() {
+            break;
+        }
+    }
+
+    let _ = appsrc.end_of_stream();
+
+    result
+}
+
+/// Encodes a set of frames, writing the finished file into `writer` instead of onto disk
+///
+/// The written bytes can't be changed afterwards, so muxers like `mp4mux`
+/// which normally go back to fill in headers are switched to fragmented output.<br>
+/// Only works with [`OutputTarget::File`], the other targets have their own sinks.
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_to_writer<W: Write>(
+    writer: &mut W,
+    video_settings: VideoSettings,
+    frames: Vec<DynamicImage>,
+) -> anyhow::Result<()> {
+    if !matches!(video_settings.output, OutputTarget::File) {
+        return Err(anyhow::Error::msg(
+            "Encoding to a writer only works when the output is a file",
+        ));
+    }
+
+    init_encoder();
+
+    let sink = gst::ElementFactory::make("appsink", Some("sink"))?;
+    let (pipeline, appsrc, video_info) =
+        init_pipeline_with_sink(sink.clone(), video_settings.clone())?;
+    appsrc.set_block(true);
+
+    if let Some(muxer) = pipeline.by_name("muxer") {
+        if muxer.has_property("fragment-duration", None)
+            && !video_settings
+                .muxer_settings
+                .contains_key("fragment-duration")
+        {
+            muxer.set_property_from_str("fragment-duration", "1000");
+            muxer.set_property_from_str("streamable", "true");
+        }
+    }
+
+    let appsink = sink.dynamic_cast::<AppSink>().unwrap();
+    appsink.set_sync(false);
+
+    pipeline.set_state(gst::State::Playing)?;
+
+    let push_appsrc = appsrc.clone();
+    let push_thread = std::thread::spawn(move || {
+        push_frames(
+            &push_appsrc,
+            &video_info,
+            &video_settings,
+            frames.into_iter(),
+            &mut PushedFrames::new(None),
+        )
+    });
+
+    let bus = pipeline.bus().unwrap();
+
+    let result = loop {
+        if let Some(sample) = appsink.try_pull_sample(gst::ClockTime::from_mseconds(100)) {
+            if let Some(buffer) = sample.buffer() {
+                let map = buffer.map_readable()?;
+                if let Err(e) = writer.write_all(map.as_slice()) {
+                    break Err(e.into());
+                }
+            }
+        } else if appsink.is_eos() {
+            break Ok(());
+        }
+
+        if let Some(msg) = bus.pop_filtered(&[gst::MessageType::Error]) {
+            if let gst::MessageView::Error(e) = msg.view() {
+                break Err(PipelineError::from_message(&e).into());
+            }
+        }
+    };
+
+    // Stopping the pipeline also unblocks the pushing thread if we stopped early
+    pipeline.set_state(gst::State::Null)?;
+    push_thread.join().unwrap()?;
+
+    result
+}
+
+/// Encodes a set of frames into memory, returning the bytes of the finished file
+///
+/// See [`encode_to_writer`] for how muxers are configured.
+///
+/// Blocks the current thread till the encoding is done
+#[cfg(feature = "image")]
+pub fn encode_to_vec(
+    video_settings: VideoSettings,
+    frames: Vec<DynamicImage>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    encode_to_writer(&mut bytes, video_settings, frames)?;
+    Ok(bytes)
 }
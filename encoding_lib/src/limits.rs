@@ -0,0 +1,171 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use gst::prelude::*;
+use gstreamer as gst;
+
+/// How often the free space of the disk is checked against [`EncodeLimits::min_free_disk`]
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits that end an encode early, e.g. so an unattended capture can't fill the disk
+///
+/// When a limit is hit the stream is ended and the file is finalized like any other video,
+/// frames sent afterwards are thrown away.
+/// Which limit ended the video is reported in [`EncodeStats::stop_reason`](crate::EncodeStats::stop_reason).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodeLimits {
+    /// The longest the video may be
+    pub max_duration: Option<Duration>,
+    /// The most bytes that may be written
+    ///
+    /// The encoder and muxer still hold some frames when the limit is hit,
+    /// so the finished file ends up slightly bigger.
+    pub max_file_size: Option<u64>,
+    /// The fewest bytes that have to stay free on the disk the video is written to
+    ///
+    /// Only checked for [`OutputTarget::File`](crate::OutputTarget::File), once a second.
+    pub min_free_disk: Option<u64>,
+}
+
+impl EncodeLimits {
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    pub fn min_free_disk(mut self, bytes: u64) -> Self {
+        self.min_free_disk = Some(bytes);
+        self
+    }
+}
+
+/// Which of the [`EncodeLimits`] ended an encode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxDuration,
+    MaxFileSize,
+    LowDiskSpace,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::MaxDuration => write!(f, "The video reached its maximum duration"),
+            StopReason::MaxFileSize => write!(f, "The video reached its maximum file size"),
+            StopReason::LowDiskSpace => write!(f, "The disk is almost full"),
+        }
+    }
+}
+
+/// Ends the stream once a limit is hit, shared with whatever reports why the encode stopped
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LimitState(Arc<Mutex<Option<StopReason>>>);
+
+impl LimitState {
+    /// Checks every frame coming out of `source_pad` against the limits
+    ///
+    /// `bytes` is how many bytes have been written so far and `location` the file they're written to.
+    pub(crate) fn install(
+        limits: &EncodeLimits,
+        source_pad: &gst::Pad,
+        bytes: Arc<AtomicU64>,
+        location: Option<PathBuf>,
+    ) -> Self {
+        let state = LimitState::default();
+        if *limits == EncodeLimits::default() {
+            return state;
+        }
+
+        let limits = *limits;
+        let probe_state = state.clone();
+        let last_disk_check = Mutex::new(None::<Instant>);
+
+        source_pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            if probe_state.reason().is_some() {
+                return gst::PadProbeReturn::Drop;
+            }
+
+            let frame_end = match &info.data {
+                Some(gst::PadProbeData::Buffer(buffer)) => buffer
+                    .pts()
+                    .map(|pts| pts + buffer.duration().unwrap_or_default()),
+                _ => None,
+            };
+
+            let reason = if limits.max_duration.is_some_and(|max| {
+                frame_end.is_some_and(|end| end.nseconds() > max.as_nanos() as u64)
+            }) {
+                Some(StopReason::MaxDuration)
+            } else if limits
+                .max_file_size
+                .is_some_and(|max| bytes.load(Ordering::Relaxed) >= max)
+            {
+                Some(StopReason::MaxFileSize)
+            } else if limits.min_free_disk.is_some_and(|min| {
+                disk_check_due(&last_disk_check)
+                    && location
+                        .as_deref()
+                        .and_then(free_space)
+                        .is_some_and(|free| free < min)
+            }) {
+                Some(StopReason::LowDiskSpace)
+            } else {
+                None
+            };
+
+            match reason {
+                Some(reason) => {
+                    println!("{reason}, ending it");
+                    *probe_state.0.lock().unwrap() = Some(reason);
+
+                    // Sent past this pad so the sources can keep pushing frames into the probe
+                    if let Some(peer) = pad.peer() {
+                        peer.send_event(gst::event::Eos::new());
+                    }
+                    gst::PadProbeReturn::Drop
+                }
+                None => gst::PadProbeReturn::Ok,
+            }
+        });
+
+        state
+    }
+
+    pub(crate) fn reason(&self) -> Option<StopReason> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Whether a second has passed since the disk was last checked
+fn disk_check_due(last_check: &Mutex<Option<Instant>>) -> bool {
+    let mut last_check = last_check.lock().unwrap();
+    if last_check.is_some_and(|last_check| last_check.elapsed() < DISK_CHECK_INTERVAL) {
+        return false;
+    }
+
+    *last_check = Some(Instant::now());
+    true
+}
+
+/// The bytes free on the disk a file is written to
+fn free_space(location: &Path) -> Option<u64> {
+    let dir = match location.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    fs2::available_space(dir).ok()
+}
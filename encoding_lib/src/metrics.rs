@@ -0,0 +1,113 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use gst::{prelude::*, ClockTime, Pipeline};
+use gstreamer as gst;
+
+/// Receives telemetry from the encoder as frames move through it, see [`VideoSettings::metrics`](crate::VideoSettings::metrics)
+///
+/// Every method does nothing by default so only the events of interest have to be implemented.<br>
+/// They're called from GStreamer's streaming threads, so they should return quickly,
+/// e.g. by bumping a Prometheus counter or sending a StatsD packet without waiting for it.
+pub trait Metrics: Send + Sync {
+    /// The `frame_num`th frame was received from the application and is about to be pushed
+    fn frame_received(&self, _frame_num: u64, _at: Instant) {}
+
+    /// A frame left the appsrc and entered the rest of the pipeline
+    ///
+    /// `pts` is `None` if the frame has no timestamp.
+    fn buffer_pushed(&self, _pts: Option<Duration>, _at: Instant) {}
+
+    /// The encoder produced the frame shown at `pts`
+    ///
+    /// `latency` is how long it took since the frame was pushed,
+    /// including time spent waiting in queues and in the encoder's lookahead.<br>
+    /// It's `None` when the encoder changed the timestamp or the frame wasn't seen going in.
+    fn frame_encoded(&self, _pts: Option<Duration>, _latency: Option<Duration>, _at: Instant) {}
+
+    /// `bytes` of muxed video were handed to the sink
+    fn segment_written(&self, _bytes: usize, _at: Instant) {}
+}
+
+/// A [`Metrics`] implementation for use with [`VideoSettings::metrics`](crate::VideoSettings::metrics)
+#[derive(Clone)]
+pub struct MetricsHook(pub Arc<dyn Metrics>);
+
+impl std::fmt::Debug for MetricsHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MetricsHook")
+    }
+}
+
+impl MetricsHook {
+    /// Reports the frames coming out of `source_pad`, the encoder and the sink of the pipeline
+    ///
+    /// `source_pad` has to be linked already.
+    pub(crate) fn install(&self, pipeline: &Pipeline, source_pad: &gst::Pad) {
+        // When each frame was pushed, waiting for the encoded frame with the same timestamp
+        let pushed = Arc::new(Mutex::new(BTreeMap::<ClockTime, Instant>::new()));
+
+        // Watching the other end of the link runs after every probe on `source_pad`,
+        // so frames dropped while paused are skipped and the timestamps already
+        // have the pause offset of an EncodeHandle taken off, like the encoder will see them
+        let pushed_pad = source_pad.peer().unwrap_or_else(|| source_pad.clone());
+
+        let metrics = self.0.clone();
+        let probe_pushed = pushed.clone();
+        pushed_pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &info.data {
+                let at = Instant::now();
+                if let Some(pts) = buffer.pts() {
+                    probe_pushed.lock().unwrap().insert(pts, at);
+                }
+                metrics.buffer_pushed(buffer.pts().map(Into::into), at);
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        if let Some(pad) = pipeline
+            .by_name("encoder")
+            .and_then(|encoder| encoder.static_pad("src"))
+        {
+            let metrics = self.0.clone();
+            pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+                if let Some(gst::PadProbeData::Buffer(buffer)) = &info.data {
+                    let at = Instant::now();
+                    let latency = buffer.pts().and_then(|pts| {
+                        let mut pushed = pushed.lock().unwrap();
+                        // Anything older belongs to a frame that was dropped
+                        let later = pushed.split_off(&pts);
+                        *pushed = later;
+                        pushed.remove(&pts).map(|pushed_at| at - pushed_at)
+                    });
+                    metrics.frame_encoded(buffer.pts().map(Into::into), latency, at);
+                }
+                gst::PadProbeReturn::Ok
+            });
+        }
+
+        if let Some(pad) = pipeline
+            .by_name("sink")
+            .and_then(|sink| sink.static_pad("sink"))
+        {
+            let metrics = self.0.clone();
+            pad.add_probe(
+                gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+                move |_, info| {
+                    let bytes = match &info.data {
+                        Some(gst::PadProbeData::Buffer(buffer)) => buffer.size(),
+                        Some(gst::PadProbeData::BufferList(list)) => {
+                            list.iter().map(|buffer| buffer.size()).sum()
+                        }
+                        _ => 0,
+                    };
+                    metrics.segment_written(bytes, Instant::now());
+                    gst::PadProbeReturn::Ok
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+/// Compares strings with runs of digits compared as numbers,
+/// so `frame_2.png` comes before `frame_10.png`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        let ordering = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (short_x, short_y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                short_x
+                    .len()
+                    .cmp(&short_y.len())
+                    .then_with(|| short_x.cmp(short_y))
+                    .then_with(|| x.len().cmp(&y.len()))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                a.next();
+                b.next();
+                ordering
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(digit);
+    }
+    number
+}
@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+
+use gst::glib;
+use gstreamer as gst;
+
+/// Resolves an output path template into a path nothing has been written to yet,
+/// e.g. `recording_%Y-%m-%d_%H%M%S_%03n.mp4` into `recording_2024-05-01_183000_001.mp4`
+///
+/// Dates and times are filled in from the local time with the `strftime` style
+/// conversions of `g_date_time_format`, like `%Y`, `%m`, `%d`, `%H`, `%M` and `%S`.<br>
+/// `%n` is replaced by the lowest number from 1 that gives a file that doesn't exist yet,
+/// padded with zeros to a width with e.g. `%03n`.
+/// Templates without a counter get `_1`, `_2` and so on added before their extension
+/// when the file already exists, so existing recordings are never overwritten.
+///
+/// Files still being written as an [`atomic_output`](crate::VideoSettings::atomic_output)
+/// count as existing.
+pub fn resolve_output_path(template: &str) -> anyhow::Result<PathBuf> {
+    let now = glib::DateTime::now_local()?;
+
+    for number in 1.. {
+        let path = match fill_counter(template, number) {
+            Some(filled) => PathBuf::from(now.format(&filled)?.as_str()),
+            None => {
+                let path = PathBuf::from(now.format(template)?.as_str());
+                if number == 1 {
+                    path
+                } else {
+                    with_suffix(&path, number - 1)
+                }
+            }
+        };
+
+        if !path.exists() && !Path::new(&format!("{}.part", path.display())).exists() {
+            return Ok(path);
+        }
+    }
+
+    unreachable!("every number is taken")
+}
+
+/// Replaces the `%n` counters of a template with `number`, leaving everything else
+/// for `DateTime::format`, `None` when the template has no counter
+fn fill_counter(template: &str, number: u32) -> Option<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut found = false;
+
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            filled.push(c);
+            continue;
+        }
+
+        let mut width = String::new();
+        while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+            width.push(digit);
+        }
+
+        if chars.next_if_eq(&'n').is_some() {
+            found = true;
+            let width = width.parse().unwrap_or(0);
+            filled.push_str(&format!("{number:0width$}"));
+        } else {
+            filled.push('%');
+            filled.push_str(&width);
+            // Copied straight away so the second `%` of `%%` isn't read as a conversion
+            if let Some(next) = chars.next() {
+                filled.push(next);
+            }
+        }
+    }
+
+    found.then_some(filled)
+}
+
+/// Adds `_<number>` to the end of the file name, before the extension
+fn with_suffix(path: &Path, number: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}_{number}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{number}"),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use gst::{prelude::*, Caps, ClockTime};
+use gst_app::AppSrc;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+
+use crate::{
+    pipeline::{
+        configure_live, make_muxer, new_pipeline, report_frame_received, set_frame_timing,
+        use_partial_location, use_template_location,
+    },
+    Container, EncodeHandle, EncodeStats, OutputTarget, VideoSettings,
+};
+
+/// The codecs of already encoded bitstreams that can be muxed without encoding them again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bitstream {
+    /// H.264 in Annex B byte-stream format, parsed by `h264parse`
+    H264,
+    /// H.265 in Annex B byte-stream format, parsed by `h265parse`
+    H265,
+}
+
+impl Bitstream {
+    fn caps_name(self) -> &'static str {
+        match self {
+            Bitstream::H264 => "video/x-h264",
+            Bitstream::H265 => "video/x-h265",
+        }
+    }
+
+    fn parser(self) -> &'static str {
+        match self {
+            Bitstream::H264 => "h264parse",
+            Bitstream::H265 => "h265parse",
+        }
+    }
+}
+
+/// Pushes encoded access units into a muxer started by [`mux_bitstream`]
+pub struct BitstreamSink {
+    appsrc: AppSrc,
+    video_settings: VideoSettings,
+    frame_num: u64,
+}
+
+impl BitstreamSink {
+    /// Muxes the NAL units of one frame, timed as the next frame of the video
+    ///
+    /// Keyframes should carry their SPS and PPS, the first frame has to be a keyframe.
+    pub fn push_access_unit(&mut self, data: &[u8], keyframe: bool) -> anyhow::Result<()> {
+        let mut buffer = gst::Buffer::from_slice(data.to_vec());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            set_frame_timing(buffer, self.frame_num, &self.video_settings);
+            if !keyframe {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+
+        self.push_buffer(buffer)
+    }
+
+    /// Muxes the NAL units of one frame shown at `pts`,
+    /// for streams that don't have a constant framerate
+    pub fn push_access_unit_at(
+        &mut self,
+        data: &[u8],
+        pts: Duration,
+        keyframe: bool,
+    ) -> anyhow::Result<()> {
+        let mut buffer = gst::Buffer::from_slice(data.to_vec());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            report_frame_received(self.frame_num, &self.video_settings);
+            buffer.set_pts(ClockTime::from_nseconds(pts.as_nanos() as u64));
+            if !keyframe {
+                buffer.set_flags(gst::BufferFlags::DELTA_UNIT);
+            }
+        }
+
+        self.push_buffer(buffer)
+    }
+
+    /// How many frames have been pushed so far
+    pub fn frames_pushed(&self) -> u64 {
+        self.frame_num
+    }
+
+    fn push_buffer(&mut self, buffer: gst::Buffer) -> anyhow::Result<()> {
+        self.appsrc
+            .push_buffer(buffer)
+            .map_err(|_| anyhow::Error::msg("The muxer has already stopped"))?;
+        self.frame_num += 1;
+
+        Ok(())
+    }
+}
+
+/// Muxes an already encoded bitstream into a file without decoding or encoding it,
+/// for frames that come from a hardware encoder, a capture card or WebCodecs
+///
+/// Only the parser and the muxer of the settings run, so `encoder`, `caps`, `rate_control`
+/// and every filter are ignored.<br>
+/// `width`, `height` and `framerate` are only used to describe the stream to the muxer.
+pub fn mux_bitstream<F: FnOnce(&mut BitstreamSink) -> anyhow::Result<()>>(
+    output_path: &str,
+    bitstream: Bitstream,
+    video_settings: VideoSettings,
+    f: F,
+) -> anyhow::Result<EncodeStats> {
+    crate::init_encoder();
+
+    if !matches!(
+        video_settings.container,
+        Container::Video | Container::MpegTs | Container::Matroska
+    ) {
+        return Err(anyhow::Error::msg(
+            "Bitstreams can only be muxed into video containers",
+        ));
+    }
+    if video_settings.output != OutputTarget::File {
+        return Err(anyhow::Error::msg(
+            "Bitstreams can only be muxed into a single file",
+        ));
+    }
+
+    let pipeline = new_pipeline(&video_settings);
+
+    let src = gst::ElementFactory::make("appsrc", Some("source"))?;
+    let parser = gst::ElementFactory::make(bitstream.parser(), Some("parser"))?;
+    let muxer = make_muxer(&video_settings)?;
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output_path);
+    if video_settings.output_template {
+        use_template_location(&sink)?;
+    }
+    if video_settings.atomic_output.is_some() {
+        use_partial_location(&sink);
+    }
+
+    let elements = [&src, &parser, &muxer, &sink];
+    pipeline.add_many(&elements)?;
+    gst::Element::link_many(&elements)?;
+
+    let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+    appsrc.set_caps(Some(
+        &Caps::builder(bitstream.caps_name())
+            .field("stream-format", "byte-stream")
+            .field("alignment", "au")
+            .field("width", video_settings.width as i32)
+            .field("height", video_settings.height as i32)
+            .field(
+                "framerate",
+                gst::Fraction::new(video_settings.framerate as i32, 1),
+            )
+            .build(),
+    ));
+    appsrc.set_format(gst::Format::Time);
+    appsrc.set_block(true);
+    configure_live(&appsrc, &video_settings);
+
+    pipeline.set_state(gst::State::Playing)?;
+    let handle = EncodeHandle::spawn(pipeline, appsrc.clone(), &video_settings);
+    let mut sink = BitstreamSink {
+        appsrc,
+        video_settings,
+        frame_num: 0,
+    };
+
+    let result = f(&mut sink);
+    let _ = sink.appsrc.end_of_stream();
+
+    let stats = handle.join();
+    result?;
+    Ok(stats?)
+}
@@ -1,4 +1,11 @@
-use gst::{prelude::*, Caps, Pipeline};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use gst::{prelude::*, Caps, ClockTime, Pipeline};
 
 use gst_app::AppSrc;
 
@@ -7,7 +14,12 @@ use gstreamer as gst;
 use gstreamer_app as gst_app;
 use gstreamer_video as gst_video;
 
-use crate::VideoSettings;
+use crate::{
+    audio::link_audio, output_template::resolve_output_path, property::set_properties,
+    BackpressurePolicy, Chapter, Container, MediaTags, OutputTarget, PartialFile, PipelineStage,
+    PreviewTarget, PropertyValue, RateControl, Rendition, ScalingMode, SpeedFactor, SubtitleSource,
+    TextKind, TextOverlay, TextPosition, VideoSettings,
+};
 
 pub fn init_encoder() {
     // This *seems* to not panic when called twice
@@ -19,52 +31,1234 @@ pub fn init_encoder() {
 pub fn init_pipeline(
     output_path: String,
     video_settings: VideoSettings,
-) -> (Pipeline, AppSrc, VideoInfo) {
-    let pipeline = gst::Pipeline::new(Some("encoding pipeline"));
+) -> anyhow::Result<(Pipeline, AppSrc, VideoInfo)> {
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output_path);
+
+    init_pipeline_with_sink(sink, video_settings)
+}
+
+/// How many encoding pipelines have been made, used to give each a unique name
+static PIPELINE_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Makes an empty pipeline named after the namespace of the settings and a number no other pipeline has
+pub(crate) fn new_pipeline(video_settings: &VideoSettings) -> Pipeline {
+    let namespace = video_settings
+        .namespace
+        .as_deref()
+        .unwrap_or("encoding pipeline");
+    unique_pipeline(namespace)
+}
+
+/// Makes an empty pipeline named `name` followed by a number no other pipeline has,
+/// so several can run in one process without their names colliding
+pub(crate) fn unique_pipeline(name: &str) -> Pipeline {
+    gst::Pipeline::new(Some(&format!("{name} {}", next_pipeline_id())))
+}
+
+/// A number no other pipeline has, also used for naming temporary files of an encode
+pub(crate) fn next_pipeline_id() -> u64 {
+    PIPELINE_COUNT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The same as [`init_pipeline`] but writing into the given sink element instead of a file
+///
+/// The sink is only used by [`OutputTarget::File`](crate::OutputTarget::File).
+pub(crate) fn init_pipeline_with_sink(
+    sink: gst::Element,
+    video_settings: VideoSettings,
+) -> anyhow::Result<(Pipeline, AppSrc, VideoInfo)> {
+    let pipeline = new_pipeline(&video_settings);
 
     let src = gst::ElementFactory::make("appsrc", Some("source")).unwrap();
+
+    link_encoding(&pipeline, vec![src.clone()], sink, &video_settings)?;
+
+    let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+
+    let video_info = make_video_info(&video_settings);
+    configure_appsrc(&appsrc, &video_info, &video_settings);
+
+    Ok((pipeline, appsrc, video_info))
+}
+
+/// Adds everything that filters, encodes and writes frames to the pipeline,
+/// fed by the last element of `head`
+///
+/// The sink is only used by [`OutputTarget::File`](crate::OutputTarget::File).
+pub(crate) fn link_encoding(
+    pipeline: &Pipeline,
+    mut head: Vec<gst::Element>,
+    sink: gst::Element,
+    video_settings: &VideoSettings,
+) -> anyhow::Result<()> {
+    let source_pad = head[0].static_pad("src").unwrap();
+    head.extend(make_filter_elements(video_settings));
+
+    let mut branches = match &video_settings.output {
+        OutputTarget::MultiOutput(renditions) => renditions
+            .iter()
+            .enumerate()
+            .map(|(i, rendition)| Ok(vec![make_rendition_bin(i, rendition, video_settings)?]))
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        _ => vec![make_output_elements(sink, video_settings)?],
+    };
+    if let Some(preview) = &video_settings.preview {
+        branches.push(make_preview_elements(preview));
+    }
+
+    link_branches(pipeline, head, branches);
+
+    if let Some(subtitles) = &video_settings.subtitles {
+        if let Err(e) = link_subtitles(pipeline, subtitles, video_settings) {
+            println!("Could not add the subtitles, leaving them out: {e}");
+        }
+    }
+    if !video_settings.audio.is_empty() {
+        link_audio(pipeline, video_settings, &source_pad)?;
+    }
+
+    if let Some(metrics) = &video_settings.metrics {
+        metrics.install(pipeline, &source_pad);
+    }
+    if let Some(watchdog) = &video_settings.watchdog {
+        // Watching the output also catches a stuck encoder or muxer, which the source only notices once its queue is full
+        let pad = pipeline
+            .by_name("sink")
+            .and_then(|sink| sink.static_pad("sink"))
+            .unwrap_or_else(|| source_pad.clone());
+        watchdog.install(pipeline, &pad);
+    }
+    if let Some(nice) = video_settings.nice {
+        set_thread_nice(pipeline, nice);
+    }
+
+    Ok(())
+}
+
+/// Sets the caps and timing of an appsrc sending frames described by `video_info`
+pub(crate) fn configure_appsrc(
+    appsrc: &AppSrc,
+    video_info: &VideoInfo,
+    video_settings: &VideoSettings,
+) {
+    appsrc.set_caps(Some(&video_info.to_caps().unwrap()));
+    appsrc.set_format(gst::Format::Time);
+    configure_live(appsrc, video_settings);
+    install_buffer_pool(appsrc, video_info);
+}
+
+/// Sets up a pipeline where everything after the appsrc is built from a `gst-launch` style description
+///
+/// Any unlinked sink pad of the description is linked to the appsrc.
+pub(crate) fn init_pipeline_with_launch(
+    description: &str,
+    video_settings: VideoSettings,
+) -> anyhow::Result<(Pipeline, AppSrc, VideoInfo)> {
+    let pipeline = new_pipeline(&video_settings);
+
+    let src = gst::ElementFactory::make("appsrc", Some("source"))?;
+    let bin = gst::parse_bin_from_description(description, true)?;
+
+    pipeline.add_many(&[&src, bin.upcast_ref()])?;
+    src.link(&bin)?;
+
+    let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+
+    let video_info = make_video_info(&video_settings);
+
+    configure_appsrc(&appsrc, &video_info, &video_settings);
+    if let Some(metrics) = &video_settings.metrics {
+        metrics.install(&pipeline, &appsrc.static_pad("src").unwrap());
+    }
+    if let Some(nice) = video_settings.nice {
+        set_thread_nice(&pipeline, nice);
+    }
+
+    Ok((pipeline, appsrc, video_info))
+}
+
+/// Makes the appsrc live when frames come from a live source
+///
+/// A live appsrc timestamps frames with the pipeline clock when they're pushed,
+/// and reports a frame of latency so sinks wait for it.
+pub(crate) fn configure_live(appsrc: &AppSrc, video_settings: &VideoSettings) {
+    appsrc.set_is_live(video_settings.live_source);
+    appsrc.set_do_timestamp(video_settings.live_source);
+    if video_settings.live_source {
+        appsrc.set_min_latency(frame_pts(1, video_settings.framerate).nseconds() as i64);
+    }
+}
+
+/// The key the appsrc's buffer pool and its buffer size are stored under
+const BUFFER_POOL_KEY: &str = "frame buffer pool";
+
+/// Gives the appsrc a pool of buffers for frames of the negotiated caps,
+/// which [`acquire_frame_buffer`] takes buffers from
+///
+/// Reusing buffers takes pressure off the allocator, and hardware encoders
+/// can import pooled memory without copying it.
+pub(crate) fn install_buffer_pool(appsrc: &AppSrc, video_info: &VideoInfo) {
+    let pool = gst_video::VideoBufferPool::new().upcast::<gst::BufferPool>();
+
+    let mut config = pool.config();
+    config.set_params(
+        video_info.to_caps().ok().as_ref(),
+        video_info.size() as u32,
+        2,
+        0,
+    );
+
+    if pool.set_config(config).is_err() || pool.set_active(true).is_err() {
+        println!("Could not set up a buffer pool, frames will be allocated one by one");
+        return;
+    }
+
+    // Safety: the key is only ever used with this type
+    unsafe { appsrc.set_data(BUFFER_POOL_KEY, (pool, video_info.size())) };
+}
+
+/// Gets a buffer for a frame described by `frame_info`, from the appsrc's buffer pool
+/// when the frame is the size it was set up for
+#[cfg(feature = "image")]
+pub(crate) fn acquire_frame_buffer(
+    appsrc: &AppSrc,
+    frame_info: &VideoInfo,
+) -> anyhow::Result<gst::Buffer> {
+    // Safety: the key is only ever used with this type
+    let pool = unsafe {
+        appsrc
+            .data::<(gst::BufferPool, usize)>(BUFFER_POOL_KEY)
+            .map(|data| data.as_ref().clone())
+    };
+
+    if let Some((pool, size)) = pool {
+        if size == frame_info.size() {
+            if let Ok(buffer) = pool.acquire_buffer(None) {
+                return Ok(buffer);
+            }
+        }
+    }
+
+    Ok(gst::Buffer::with_size(frame_info.size())?)
+}
+
+/// Adds `head` to the pipeline and links it to each branch,
+/// going through a `tee` when there is more than one
+pub(crate) fn link_branches(
+    pipeline: &Pipeline,
+    mut head: Vec<gst::Element>,
+    mut branches: Vec<Vec<gst::Element>>,
+) {
+    if branches.len() == 1 {
+        head.extend(branches.pop().unwrap());
+
+        let elements = head.iter().collect::<Vec<_>>();
+        pipeline.add_many(&elements).unwrap();
+        gst::Element::link_many(&elements).unwrap();
+        return;
+    }
+
+    head.push(gst::ElementFactory::make("tee", Some("tee")).unwrap());
+
+    let elements = head.iter().collect::<Vec<_>>();
+    pipeline.add_many(&elements).unwrap();
+    gst::Element::link_many(&elements).unwrap();
+
+    let tee = head.last().unwrap();
+
+    for branch in branches {
+        // Each branch needs its own thread so a slow one can't stall the others
+        let mut elements = vec![gst::ElementFactory::make("queue", None).unwrap()];
+        elements.extend(branch);
+
+        let elements = elements.iter().collect::<Vec<_>>();
+        pipeline.add_many(&elements).unwrap();
+        gst::Element::link_many(&elements).unwrap();
+        tee.link(elements[0]).unwrap();
+    }
+}
+
+/// Adds a subtitle stream to the pipeline and links it to the muxer
+fn link_subtitles(
+    pipeline: &Pipeline,
+    subtitles: &SubtitleSource,
+    video_settings: &VideoSettings,
+) -> anyhow::Result<()> {
+    let muxer = match (&video_settings.output, &video_settings.container) {
+        (OutputTarget::File | OutputTarget::Custom(_), Container::Video | Container::Matroska) => {
+            pipeline.by_name("muxer").unwrap()
+        }
+        _ => {
+            println!("Subtitles can only be muxed into a single video file, ignoring them");
+            return Ok(());
+        }
+    };
+
+    // Muxers only take plain text, without it subparse would output pango markup
+    let text_caps = Caps::builder("text/x-raw").field("format", "utf8").build();
+
+    let src = match subtitles {
+        SubtitleSource::File(path) => {
+            let filesrc = gst::ElementFactory::make("filesrc", Some("subtitle file"))?;
+            filesrc.set_property("location", path.to_string_lossy().as_ref());
+            let parse = gst::ElementFactory::make("subparse", Some("subtitle parse"))?;
+            let filter = gst::ElementFactory::make("capsfilter", Some("subtitle format"))?;
+            filter.set_property("caps", &text_caps);
+
+            pipeline.add_many(&[&filesrc, &parse, &filter])?;
+            gst::Element::link_many(&[&filesrc, &parse, &filter])?;
+            filter
+        }
+        SubtitleSource::Cues(receiver) => {
+            let receiver = match receiver.0.lock().unwrap().take() {
+                Some(receiver) => receiver,
+                None => {
+                    return Err(anyhow::Error::msg(
+                        "A subtitle channel can only be used by one encode",
+                    ))
+                }
+            };
+
+            let appsrc = gst::ElementFactory::make("appsrc", Some("subtitle source"))?
+                .dynamic_cast::<AppSrc>()
+                .unwrap();
+            appsrc.set_caps(Some(&text_caps));
+            appsrc.set_format(gst::Format::Time);
+
+            let thread_src = appsrc.clone();
+            std::thread::spawn(move || {
+                for (start, end, text) in receiver {
+                    let mut buffer = gst::Buffer::from_slice(text.into_bytes());
+                    {
+                        let buffer = buffer.get_mut().unwrap();
+                        buffer.set_pts(ClockTime::from_nseconds(start.as_nanos() as u64));
+                        buffer.set_duration(ClockTime::from_nseconds(
+                            end.saturating_sub(start).as_nanos() as u64,
+                        ));
+                    }
+
+                    if thread_src.push_buffer(buffer).is_err() {
+                        return;
+                    }
+                }
+
+                let _ = thread_src.end_of_stream();
+            });
+
+            pipeline.add(&appsrc)?;
+            appsrc.upcast()
+        }
+    };
+
+    src.link(&muxer)?;
+
+    Ok(())
+}
+
+/// Creates the leaky queue that throws frames away when the encoder falls behind
+fn make_backpressure_queue(policy: BackpressurePolicy) -> Option<gst::Element> {
+    let (leaky, max_frames) = match policy {
+        BackpressurePolicy::Block => return None,
+        BackpressurePolicy::DropNewest => ("upstream", 1),
+        BackpressurePolicy::DropOldest { max_frames } => ("downstream", max_frames.max(1)),
+    };
+
+    let queue = gst::ElementFactory::make("queue", Some("backpressure queue")).unwrap();
+    queue.set_property_from_str("leaky", leaky);
+    queue.set_property("max-size-buffers", max_frames);
+    queue.set_property("max-size-bytes", 0u32);
+    queue.set_property("max-size-time", 0u64);
+
+    Some(queue)
+}
+
+/// Creates the elements that show the video in a window
+fn make_preview_elements(preview: &PreviewTarget) -> Vec<gst::Element> {
+    let factory = match preview {
+        PreviewTarget::Window => "autovideosink",
+        PreviewTarget::Gl => "glimagesink",
+    };
+
+    let sink = gst::ElementFactory::make(factory, Some("preview")).unwrap();
+    // The preview shouldn't hold up encoding when frames come in faster than realtime
+    sink.set_property("sync", false);
+
+    vec![
+        gst::ElementFactory::make("videoconvert", Some("preview convert")).unwrap(),
+        sink,
+    ]
+}
+
+/// Creates the elements that change the raw frames before they're encoded,
+/// in the order they should be linked
+pub(crate) fn make_filter_elements(video_settings: &VideoSettings) -> Vec<gst::Element> {
+    let mut elements = Vec::new();
+
+    if let Some(queue) = make_backpressure_queue(video_settings.backpressure) {
+        elements.push(queue);
+    }
+
+    if video_settings.rate_conversion {
+        let videorate = gst::ElementFactory::make("videorate", Some("rate")).unwrap();
+        let rate_filter = gst::ElementFactory::make("capsfilter", Some("rate filter")).unwrap();
+        rate_filter.set_property(
+            "caps",
+            Caps::builder("video/x-raw")
+                .field(
+                    "framerate",
+                    gst::Fraction::new(video_settings.framerate as i32, 1),
+                )
+                .build(),
+        );
+
+        elements.push(videorate);
+        elements.push(rate_filter);
+    }
+
+    if video_settings.scaling != ScalingMode::Strict {
+        if video_settings.scaling == ScalingMode::Crop {
+            let crop = gst::ElementFactory::make("aspectratiocrop", Some("crop")).unwrap();
+            crop.set_property(
+                "aspect-ratio",
+                gst::Fraction::new(video_settings.width as i32, video_settings.height as i32),
+            );
+            elements.push(crop);
+        }
+
+        let videoscale = gst::ElementFactory::make("videoscale", Some("scale")).unwrap();
+        videoscale.set_property(
+            "add-borders",
+            video_settings.scaling == ScalingMode::Letterbox,
+        );
+
+        let scale_filter = gst::ElementFactory::make("capsfilter", Some("scale filter")).unwrap();
+        scale_filter.set_property(
+            "caps",
+            Caps::builder("video/x-raw")
+                .field("width", video_settings.width as i32)
+                .field("height", video_settings.height as i32)
+                .field("pixel-aspect-ratio", gst::Fraction::new(1, 1))
+                .build(),
+        );
+
+        elements.push(videoscale);
+        elements.push(scale_filter);
+    }
+
+    for (i, overlay) in video_settings.overlays.iter().enumerate() {
+        let element =
+            gst::ElementFactory::make("gdkpixbufoverlay", Some(&format!("overlay {i}"))).unwrap();
+        element.set_property("location", overlay.location.to_string_lossy().as_ref());
+        element.set_property("offset-x", overlay.x);
+        element.set_property("offset-y", overlay.y);
+        element.set_property("alpha", overlay.alpha);
+
+        if let Some((width, height)) = overlay.size {
+            element.set_property("overlay-width", width as i32);
+            element.set_property("overlay-height", height as i32);
+        }
+
+        elements.push(element);
+    }
+
+    if let Some(text_overlay) = &video_settings.text_overlay {
+        elements.push(make_text_overlay(text_overlay));
+    }
+
+    elements
+}
+
+/// Creates the element that draws a [`TextOverlay`]
+fn make_text_overlay(text_overlay: &TextOverlay) -> gst::Element {
+    let factory = match text_overlay.kind {
+        TextKind::Text => "textoverlay",
+        TextKind::StreamTime => "timeoverlay",
+        TextKind::Clock => "clockoverlay",
+    };
+    let element = gst::ElementFactory::make(factory, Some("text overlay")).unwrap();
+
+    let (halignment, valignment) = match text_overlay.position {
+        TextPosition::TopLeft => ("left", "top"),
+        TextPosition::Top => ("center", "top"),
+        TextPosition::TopRight => ("right", "top"),
+        TextPosition::Left => ("left", "center"),
+        TextPosition::Center => ("center", "center"),
+        TextPosition::Right => ("right", "center"),
+        TextPosition::BottomLeft => ("left", "bottom"),
+        TextPosition::Bottom => ("center", "bottom"),
+        TextPosition::BottomRight => ("right", "bottom"),
+    };
+
+    element.set_property("text", &text_overlay.text);
+    element.set_property_from_str("halignment", halignment);
+    element.set_property_from_str("valignment", valignment);
+    element.set_property("shaded-background", text_overlay.shaded);
+
+    if let Some(font) = &text_overlay.font {
+        element.set_property("font-desc", font);
+    }
+
+    element
+}
+
+/// The file written by a `filesink`, `None` for any other sink
+pub(crate) fn filesink_location(sink: &gst::Element) -> Option<String> {
+    sink.factory()
+        .filter(|factory| factory.name() == "filesink")
+        .and_then(|_| sink.property::<Option<String>>("location"))
+}
+
+/// Resolves the template a `filesink` was given as its location, see [`VideoSettings::output_template`]
+pub(crate) fn use_template_location(sink: &gst::Element) -> anyhow::Result<()> {
+    if let Some(template) = filesink_location(sink) {
+        let location = resolve_output_path(&template)?;
+        sink.set_property("location", location.to_string_lossy().as_ref());
+    }
+
+    Ok(())
+}
+
+/// Points a `filesink` at `<location>.part`, see [`VideoSettings::atomic_output`]
+pub(crate) fn use_partial_location(sink: &gst::Element) {
+    if let Some(location) = filesink_location(sink) {
+        sink.set_property("location", format!("{location}.part"));
+    }
+}
+
+/// Gives the `.part` file of an atomic output its real name once the pipeline has stopped,
+/// or removes it when the encode failed and it isn't kept
+pub(crate) fn finish_partial_output(
+    pipeline: &Pipeline,
+    atomic_output: Option<PartialFile>,
+    succeeded: bool,
+) -> std::io::Result<()> {
+    let on_error = match atomic_output {
+        Some(on_error) => on_error,
+        None => return Ok(()),
+    };
+    let sink = match pipeline.by_name("sink") {
+        Some(sink) => sink,
+        None => return Ok(()),
+    };
+    let part = match filesink_location(&sink) {
+        Some(part) if part.ends_with(".part") => part,
+        _ => return Ok(()),
+    };
+    let output = &part[..part.len() - ".part".len()];
+
+    if succeeded {
+        std::fs::rename(&part, output)?;
+        // So the stats are read from the finished file
+        sink.set_property("location", output);
+    } else if on_error == PartialFile::Delete {
+        std::fs::remove_file(&part)?;
+    }
+
+    Ok(())
+}
+
+/// Creates every element after the filters, in the order they should be linked
+fn make_output_elements(
+    sink: gst::Element,
+    video_settings: &VideoSettings,
+) -> anyhow::Result<Vec<gst::Element>> {
+    let mut elements = Vec::new();
+
+    match &video_settings.output {
+        OutputTarget::File => {
+            if video_settings.output_template {
+                use_template_location(&sink)?;
+            }
+            if video_settings.atomic_output.is_some() {
+                use_partial_location(&sink);
+            }
+            elements.extend(make_container_elements(video_settings)?);
+            elements.push(sink);
+        }
+        OutputTarget::Custom(custom) => {
+            elements.extend(make_container_elements(video_settings)?);
+            elements.push(custom.clone());
+        }
+        OutputTarget::ImageSequence { dir, pattern } => {
+            let encoder = gst::ElementFactory::make("pngenc", Some("encoder")).unwrap();
+            // Otherwise only the first frame is written
+            encoder.set_property("snapshot", false);
+
+            let sink = gst::ElementFactory::make("multifilesink", Some("sink")).unwrap();
+            sink.set_property("location", dir.join(pattern).to_string_lossy().as_ref());
+
+            elements.push(gst::ElementFactory::make("videoconvert", Some("convert")).unwrap());
+            elements.push(encoder);
+            elements.push(sink);
+        }
+        OutputTarget::Dash {
+            mpd_path,
+            segment_duration,
+        } => {
+            elements.extend(make_encode_elements(video_settings)?);
+            elements.push(make_dash_sink(mpd_path, *segment_duration));
+        }
+        OutputTarget::MultiOutput(_) => {
+            unreachable!("renditions are split into their own branches")
+        }
+        OutputTarget::V4l2Loopback { device } => {
+            let sink = gst::ElementFactory::make("v4l2sink", Some("sink")).unwrap();
+            sink.set_property("device", device.to_string_lossy().as_ref());
+            check_device_caps(&sink);
+
+            // videoconvert negotiates a format the device accepts with v4l2sink
+            elements.push(gst::ElementFactory::make("videoconvert", Some("convert")).unwrap());
+            elements.push(sink);
+        }
+    }
+
+    Ok(elements)
+}
+
+/// Creates a bin that scales frames to the size of `rendition` then encodes and writes them
+///
+/// The elements are in their own bin so their names don't clash with the other renditions.
+fn make_rendition_bin(
+    index: usize,
+    rendition: &Rendition,
+    video_settings: &VideoSettings,
+) -> anyhow::Result<gst::Element> {
+    let bin = gst::Bin::new(Some(&format!("rendition {index}")));
+
+    let mut settings = video_settings.clone();
+    settings.width = rendition.width;
+    settings.height = rendition.height;
+    settings.output = OutputTarget::File;
+    settings.encoder_settings.extend(
+        rendition
+            .encoder_settings
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone())),
+    );
+
+    let scale_filter = gst::ElementFactory::make("capsfilter", Some("scale filter")).unwrap();
+    scale_filter.set_property(
+        "caps",
+        Caps::builder("video/x-raw")
+            .field("width", rendition.width as i32)
+            .field("height", rendition.height as i32)
+            .build(),
+    );
+
+    let sink = gst::ElementFactory::make("filesink", Some("sink")).unwrap();
+    sink.set_property("location", rendition.location.to_string_lossy().as_ref());
+
+    let mut elements = vec![
+        gst::ElementFactory::make("videoscale", Some("scale")).unwrap(),
+        scale_filter,
+    ];
+    elements.extend(make_container_elements(&settings)?);
+    elements.push(sink);
+
+    let elements = elements.iter().collect::<Vec<_>>();
+    bin.add_many(&elements).unwrap();
+    gst::Element::link_many(&elements).unwrap();
+
+    let pad = elements[0].static_pad("sink").unwrap();
+    bin.add_pad(&gst::GhostPad::with_target(Some("sink"), &pad).unwrap())
+        .unwrap();
+
+    Ok(bin.upcast())
+}
+
+/// Creates a `dashsink` writing fragmented MP4 segments next to `mpd_path`
+fn make_dash_sink(mpd_path: &Path, segment_duration: Duration) -> gst::Element {
+    let sink = gst::ElementFactory::make("dashsink", Some("sink")).unwrap();
+
+    let root = mpd_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    sink.set_property("mpd-root-path", root.to_string_lossy().as_ref());
+    sink.set_property(
+        "mpd-filename",
+        mpd_path.file_name().unwrap().to_string_lossy().as_ref(),
+    );
+    sink.set_property_from_str("muxer", "mp4");
+    sink.set_property(
+        "target-duration",
+        (segment_duration.as_secs_f64().round() as u32).max(1),
+    );
+
+    sink
+}
+
+/// Opens the device of a `v4l2sink` to warn early when it can't take raw video
+///
+/// A loopback device with `exclusive_caps=1` only reports its formats once something is writing,
+/// so an empty result isn't an error.
+fn check_device_caps(sink: &gst::Element) {
+    if sink.set_state(gst::State::Ready).is_err() {
+        println!(
+            "Could not open {}, is the v4l2loopback module loaded?",
+            sink.property::<String>("device")
+        );
+        return;
+    }
+
+    let caps = sink.static_pad("sink").unwrap().query_caps(None);
+    if !caps.is_empty() && !caps.can_intersect(&Caps::new_simple("video/x-raw", &[])) {
+        println!(
+            "{} doesn't accept raw video, it only supports {caps}",
+            sink.property::<String>("device")
+        );
+    }
+
+    sink.set_state(gst::State::Null).unwrap();
+}
+
+/// Creates the elements that turn raw frames into the bytes of the container
+fn make_container_elements(video_settings: &VideoSettings) -> anyhow::Result<Vec<gst::Element>> {
+    let elements = match video_settings.container {
+        Container::Video | Container::MpegTs | Container::Matroska => {
+            let mut elements = make_encode_elements(video_settings)?;
+            elements.push(make_muxer(video_settings)?);
+            elements
+        }
+        // Animated images are encoded and written by a single element
+        Container::Gif { .. } | Container::WebP { .. } => vec![
+            gst::ElementFactory::make("videoconvert", Some("convert")).unwrap(),
+            make_image_encoder(&video_settings.container),
+        ],
+        // Raw frames only get a header in front of each of them
+        Container::Y4m => {
+            let format = video_settings
+                .encoder_format
+                .unwrap_or(gst_video::VideoFormat::I420);
+            let filter = gst::ElementFactory::make("capsfilter", Some("encoder format")).unwrap();
+            filter.set_property(
+                "caps",
+                Caps::builder("video/x-raw")
+                    .field("format", format.to_str())
+                    .build(),
+            );
+
+            vec![
+                gst::ElementFactory::make("videoconvert", Some("convert")).unwrap(),
+                filter,
+                gst::ElementFactory::make("y4menc", Some("encoder")).unwrap(),
+            ]
+        }
+    };
+
+    Ok(elements)
+}
+
+/// Creates the elements that take raw frames from the appsrc and encode them,
+/// in the order they should be linked
+pub(crate) fn make_encode_elements(
+    video_settings: &VideoSettings,
+) -> anyhow::Result<Vec<gst::Element>> {
     let videoconvert = gst::ElementFactory::make("videoconvert", Some("convert")).unwrap();
-    let encoder = gst::ElementFactory::make(&video_settings.encoder, Some("encoder")).unwrap();
+    let mut elements = vec![videoconvert];
+    elements.extend(make_custom_elements(
+        video_settings,
+        PipelineStage::PreEncode,
+    )?);
+
+    let mut format_caps = Caps::builder("video/x-raw").field(
+        "colorimetry",
+        output_colorimetry(video_settings).to_string(),
+    );
+    if let Some(format) = video_settings.encoder_format {
+        format_caps = format_caps.field("format", format.to_str());
+    }
+
+    let format_filter = gst::ElementFactory::make("capsfilter", Some("encoder format")).unwrap();
+    format_filter.set_property("caps", format_caps.build());
+    elements.push(format_filter);
+
     let filter = gst::ElementFactory::make("capsfilter", None).unwrap();
-    let muxer = gst::ElementFactory::make(&video_settings.muxer, Some("muxer")).unwrap();
-    // let sink = gst::ElementFactory::make("filesink", Some("sink")).unwrap();
-    let sink = gst::ElementFactory::make("filesink", Some("sink")).unwrap();
+    filter.set_property("caps", encoded_caps(video_settings));
 
-    sink.set_property("location", output_path);
+    elements.push(make_encoder(video_settings)?);
+    elements.push(filter);
+    elements.extend(make_custom_elements(
+        video_settings,
+        PipelineStage::PostEncode,
+    )?);
+
+    Ok(elements)
+}
 
-    for (key, val) in video_settings.encoder_settings {
-        encoder.set_property_from_str(&key, &val);
+/// Creates the custom elements inserted at `stage`
+fn make_custom_elements(
+    video_settings: &VideoSettings,
+    stage: PipelineStage,
+) -> anyhow::Result<Vec<gst::Element>> {
+    video_settings
+        .custom_elements
+        .iter()
+        .filter(|(element_stage, _)| *element_stage == stage)
+        .map(|(_, spec)| {
+            let element = gst::ElementFactory::make(&spec.factory, None)?;
+            set_properties(
+                &element,
+                spec.properties.iter().map(|(key, val)| (key, val)),
+            )?;
+            Ok(element)
+        })
+        .collect()
+}
+
+/// Creates the element that encodes a whole animated image
+fn make_image_encoder(container: &Container) -> gst::Element {
+    match container {
+        Container::Gif { speed, repeat } => {
+            let encoder = gst::ElementFactory::make("gifenc", Some("encoder")).unwrap();
+            encoder.set_property_from_str("speed", &speed.to_string());
+            encoder.set_property_from_str("repeat", &repeat.to_string());
+            encoder
+        }
+        Container::WebP {
+            quality,
+            lossless,
+            loops,
+        } => {
+            let encoder = gst::ElementFactory::make("webpenc", Some("encoder")).unwrap();
+            encoder.set_property_from_str("animated", "true");
+            encoder.set_property_from_str("quality", &quality.to_string());
+            encoder.set_property_from_str("lossless", &lossless.to_string());
+            encoder.set_property_from_str("animation-loops", &loops.to_string());
+            encoder
+        }
+        Container::Video | Container::MpegTs | Container::Matroska | Container::Y4m => {
+            unreachable!("videos don't use an image encoder")
+        }
     }
+}
+
+/// Creates the encoder element described by the settings
+pub(crate) fn make_encoder(video_settings: &VideoSettings) -> anyhow::Result<gst::Element> {
+    let encoder = gst::ElementFactory::make(&video_settings.encoder, Some("encoder"))?;
 
-    for (key, val) in video_settings.muxer_settings {
-        muxer.set_property_from_str(&key, &val);
+    let mut settings = rate_control_settings(&video_settings.encoder, video_settings.rate_control);
+    if let Some(threads) = video_settings.encoder_threads {
+        settings.extend(thread_limit_settings(&video_settings.encoder, threads));
     }
+    for (name, value) in &video_settings.encoder_settings {
+        let value = match (settings.get(name), value) {
+            // x264 and x265 read options in order, so the user's override the rate control ones
+            (Some(PropertyValue::Str(ours)), PropertyValue::Str(theirs))
+                if name == "option-string" =>
+            {
+                PropertyValue::Str(format!("{ours}:{theirs}"))
+            }
+            _ => value.clone(),
+        };
+        settings.insert(name.clone(), value);
+    }
+    set_properties(&encoder, &settings)?;
 
-    let output_info = Caps::builder("video/x-h264")
-        .field("profile", "baseline")
-        .field("speed-preset", "ultrafast")
-        .build();
+    Ok(encoder)
+}
 
-    filter.set_property("caps", &output_info);
+/// The encoder properties that give the rate control mode, which depend on the encoder family
+fn rate_control_settings(
+    encoder: &str,
+    rate_control: RateControl,
+) -> HashMap<String, PropertyValue> {
+    let enum_value = |nick: &str| PropertyValue::Enum(nick.to_owned());
 
-    pipeline
-        .add_many(&[&src, &videoconvert, &encoder, &filter, &muxer, &sink])
-        .unwrap();
-    gst::Element::link_many(&[&src, &videoconvert, &encoder, &filter, &muxer, &sink]).unwrap();
+    let settings: Vec<(&str, PropertyValue)> = match (encoder, rate_control) {
+        (_, RateControl::Default) => Vec::new(),
 
-    let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+        ("x264enc", RateControl::Crf(quality)) => vec![
+            ("pass", enum_value("qual")),
+            ("quantizer", x264_quantizer(quality).into()),
+        ],
+        ("x264enc", RateControl::Cbr { kbps }) => {
+            vec![("pass", enum_value("cbr")), ("bitrate", kbps.into())]
+        }
+        ("x264enc", RateControl::Vbr { target, max }) => vec![
+            ("pass", enum_value("cbr")),
+            ("bitrate", target.into()),
+            // The buffer is sized in milliseconds of `target`, this makes it a second at `max`
+            (
+                "vbv-buf-capacity",
+                (max as u64 * 1000 / target.max(1) as u64)
+                    .clamp(1, 10_000)
+                    .into(),
+            ),
+            ("option-string", format!("vbv-maxrate={max}").into()),
+        ],
+        ("x264enc", RateControl::Cqp(quantizer)) => vec![
+            ("pass", enum_value("quant")),
+            ("quantizer", x264_quantizer(quantizer).into()),
+        ],
+
+        ("x265enc", RateControl::Crf(quality)) => {
+            vec![("option-string", format!("crf={quality}").into())]
+        }
+        ("x265enc", RateControl::Cbr { kbps }) => vec![
+            ("bitrate", kbps.into()),
+            (
+                "option-string",
+                format!("vbv-maxrate={kbps}:vbv-bufsize={kbps}").into(),
+            ),
+        ],
+        ("x265enc", RateControl::Vbr { target, max }) => vec![
+            ("bitrate", target.into()),
+            (
+                "option-string",
+                format!("vbv-maxrate={max}:vbv-bufsize={max}").into(),
+            ),
+        ],
+        ("x265enc", RateControl::Cqp(quantizer)) => vec![("qp", (quantizer as i32).into())],
+
+        ("vp8enc" | "vp9enc", RateControl::Crf(quality)) => vec![
+            ("end-usage", enum_value("q")),
+            ("cq-level", (quality as i32).into()),
+        ],
+        ("vp8enc" | "vp9enc", RateControl::Cbr { kbps }) => vec![
+            ("end-usage", enum_value("cbr")),
+            ("target-bitrate", (kbps * 1000).into()),
+        ],
+        ("vp8enc" | "vp9enc", RateControl::Vbr { target, .. }) => {
+            println!("{encoder} has no maximum bitrate, only the target is used");
+            vec![
+                ("end-usage", enum_value("vbr")),
+                ("target-bitrate", (target * 1000).into()),
+            ]
+        }
+        ("vp8enc" | "vp9enc", RateControl::Cqp(quantizer)) => vec![
+            ("end-usage", enum_value("q")),
+            ("min-quantizer", (quantizer as u32).into()),
+            ("max-quantizer", (quantizer as u32).into()),
+        ],
+
+        ("nvh264enc" | "nvh265enc", RateControl::Crf(quality)) => vec![
+            ("rc-mode", enum_value("vbr")),
+            ("bitrate", 0u32.into()),
+            ("const-quality", (quality as f64).into()),
+        ],
+        ("nvh264enc" | "nvh265enc", RateControl::Cbr { kbps }) => {
+            vec![("rc-mode", enum_value("cbr")), ("bitrate", kbps.into())]
+        }
+        ("nvh264enc" | "nvh265enc", RateControl::Vbr { target, max }) => vec![
+            ("rc-mode", enum_value("vbr")),
+            ("bitrate", target.into()),
+            ("max-bitrate", max.into()),
+        ],
+        ("nvh264enc" | "nvh265enc", RateControl::Cqp(quantizer)) => vec![
+            ("rc-mode", enum_value("constqp")),
+            ("qp-const", (quantizer as i32).into()),
+        ],
+
+        ("vaapih264enc" | "vaapih265enc", RateControl::Crf(quality)) => vec![
+            ("rate-control", enum_value("icq")),
+            ("quality-factor", (quality as u32).into()),
+        ],
+        ("vaapih264enc" | "vaapih265enc", RateControl::Cbr { kbps }) => {
+            vec![
+                ("rate-control", enum_value("cbr")),
+                ("bitrate", kbps.into()),
+            ]
+        }
+        ("vaapih264enc" | "vaapih265enc", RateControl::Vbr { target, max }) => vec![
+            ("rate-control", enum_value("vbr")),
+            ("bitrate", max.into()),
+            (
+                "target-percentage",
+                (target * 100 / max.max(1)).clamp(1, 100).into(),
+            ),
+        ],
+        ("vaapih264enc" | "vaapih265enc", RateControl::Cqp(quantizer)) => vec![
+            ("rate-control", enum_value("cqp")),
+            ("init-qp", (quantizer as u32).into()),
+        ],
 
-    let video_info = gst_video::VideoInfo::builder(
+        _ => {
+            println!("{encoder} doesn't support the rate control settings, ignoring them");
+            Vec::new()
+        }
+    };
+
+    settings
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+/// Fits a quality or quantizer into the 0 to 50 `quantizer` of `x264enc`
+fn x264_quantizer(quantizer: u8) -> u32 {
+    if quantizer > 50 {
+        println!("x264enc's quantizer only goes up to 50, using 50 instead of {quantizer}");
+    }
+    quantizer.min(50) as u32
+}
+
+/// The encoder properties that limit how many threads it encodes with
+fn thread_limit_settings(encoder: &str, threads: u32) -> HashMap<String, PropertyValue> {
+    let settings: Vec<(&str, PropertyValue)> = match encoder {
+        "x264enc" => vec![("threads", threads.into())],
+        "vp8enc" | "vp9enc" => vec![("threads", (threads as i32).into())],
+        _ => {
+            println!("{encoder} can't limit its threads, ignoring the limit");
+            Vec::new()
+        }
+    };
+
+    settings
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+/// Sets the nice level of every streaming thread of the pipeline as it starts
+///
+/// Threads started from them, like the worker threads of `x264enc`, inherit it.
+#[cfg(target_os = "linux")]
+pub(crate) fn set_thread_nice(pipeline: &Pipeline, nice: i32) {
+    extern "C" {
+        fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    }
+    const PRIO_PROCESS: i32 = 0;
+
+    pipeline.bus().unwrap().set_sync_handler(move |_, msg| {
+        if let gst::MessageView::StreamStatus(status) = msg.view() {
+            // Posted from the new thread itself, and on Linux
+            // a `who` of 0 is only the calling thread instead of the whole process
+            if status.get().0 == gst::StreamStatusType::Enter
+                && unsafe { setpriority(PRIO_PROCESS, 0, nice) } != 0
+            {
+                println!("Could not set the nice level of a streaming thread to {nice}");
+            }
+        }
+        gst::BusSyncReply::Pass
+    });
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_thread_nice(_pipeline: &Pipeline, _nice: i32) {
+    println!("Nice levels are only supported on Linux, ignoring it");
+}
+
+/// The caps the encoder is restricted to, adjusted for what the muxer accepts
+fn encoded_caps(video_settings: &VideoSettings) -> Caps {
+    let mut caps = video_settings.caps.clone();
+
+    if video_settings.container == Container::MpegTs {
+        // mpegtsmux only takes H.264 and H.265 with the parameter sets in the stream
+        for structure in caps.make_mut().iter_mut() {
+            if structure.name() == "video/x-h264" || structure.name() == "video/x-h265" {
+                structure.set("stream-format", "byte-stream");
+                structure.set("alignment", "au");
+            }
+        }
+    }
+
+    caps
+}
+
+/// The name of the muxer factory the settings use
+pub(crate) fn muxer_factory(video_settings: &VideoSettings) -> &str {
+    match video_settings.container {
+        Container::MpegTs => "mpegtsmux",
+        Container::Matroska => "matroskamux",
+        _ => &video_settings.muxer,
+    }
+}
+
+/// Creates the muxer element described by the settings
+pub(crate) fn make_muxer(video_settings: &VideoSettings) -> anyhow::Result<gst::Element> {
+    let factory = muxer_factory(video_settings);
+    let muxer = gst::ElementFactory::make(factory, Some("muxer"))?;
+
+    if video_settings.faststart {
+        if muxer.has_property("faststart", None) {
+            muxer.set_property("faststart", true);
+        } else {
+            println!("{factory} doesn't support faststart, ignoring it");
+        }
+    }
+
+    set_properties(&muxer, &video_settings.muxer_settings)?;
+
+    if !video_settings.tags.is_empty() {
+        match muxer.dynamic_cast_ref::<gst::TagSetter>() {
+            Some(setter) => setter.merge_tags(
+                &make_tag_list(&video_settings.tags),
+                gst::TagMergeMode::Replace,
+            ),
+            None => println!("{factory} doesn't support tags, ignoring them"),
+        }
+    }
+
+    if !video_settings.chapters.is_empty() {
+        set_chapters(&muxer, &video_settings.chapters);
+    }
+
+    Ok(muxer)
+}
+
+/// Replaces the table of contents the muxer writes with `chapters`
+pub(crate) fn set_chapters(muxer: &gst::Element, chapters: &[Chapter]) {
+    let setter = match muxer.dynamic_cast_ref::<gst::TocSetter>() {
+        Some(setter) => setter,
+        None => {
+            println!(
+                "{} doesn't support chapters, ignoring them",
+                muxer
+                    .factory()
+                    .map_or_else(|| muxer.name().to_string(), |f| f.name().to_string())
+            );
+            return;
+        }
+    };
+
+    let mut chapters = chapters.to_vec();
+    chapters.sort_by_key(|chapter| chapter.start);
+
+    let mut edition = gst::TocEntry::new(gst::TocEntryType::Edition, "edition");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let mut entry = gst::TocEntry::new(gst::TocEntryType::Chapter, &format!("chapter {i}"));
+        let stop = chapters
+            .get(i + 1)
+            .map_or(-1, |next| next.start.as_nanos() as i64);
+
+        let mut tags = gst::TagList::new();
+        tags.get_mut()
+            .unwrap()
+            .add::<gst::tags::Title>(&chapter.title.as_str(), gst::TagMergeMode::Replace);
+
+        {
+            let entry = entry.get_mut().unwrap();
+            entry.set_start_stop_times(chapter.start.as_nanos() as i64, stop);
+            entry.set_tags(tags);
+        }
+        edition.get_mut().unwrap().append_sub_entry(entry);
+    }
+
+    let mut toc = gst::Toc::new(gst::TocScope::Global);
+    toc.get_mut().unwrap().append_entry(edition);
+
+    setter.set_toc(Some(&toc));
+}
+
+/// Creates the `TagList` written into the container
+fn make_tag_list(media_tags: &MediaTags) -> gst::TagList {
+    let mut tags = gst::TagList::new();
+
+    {
+        let tags = tags.get_mut().unwrap();
+        let mode = gst::TagMergeMode::Replace;
+
+        if let Some(title) = &media_tags.title {
+            tags.add::<gst::tags::Title>(&title.as_str(), mode);
+        }
+        if let Some(artist) = &media_tags.artist {
+            tags.add::<gst::tags::Artist>(&artist.as_str(), mode);
+        }
+        if let Some(comment) = &media_tags.comment {
+            tags.add::<gst::tags::Comment>(&comment.as_str(), mode);
+        }
+        if let Some(date) = &media_tags.creation_date {
+            tags.add::<gst::tags::DateTime>(date, mode);
+        }
+    }
+
+    tags
+}
+
+/// Creates the `VideoInfo` of the raw frames sent into the pipeline
+pub(crate) fn make_video_info(video_settings: &VideoSettings) -> VideoInfo {
+    gst_video::VideoInfo::builder(
         video_settings.format,
         video_settings.width,
         video_settings.height,
     )
-    .fps(gst::Fraction::new(60, 1))
+    .fps(gst::Fraction::new(video_settings.framerate as i32, 1))
+    .colorimetry(&input_colorimetry(video_settings))
     .build()
-    .unwrap();
+    .unwrap()
+}
 
-    appsrc.set_caps(Some(&video_info.to_caps().unwrap()));
-    appsrc.set_format(gst::Format::Time);
+/// The colorimetry of the frames sent into the pipeline
+///
+/// RGB and grayscale frames can't use a YUV matrix or limited range,
+/// so only the primaries and transfer apply to them.
+fn input_colorimetry(video_settings: &VideoSettings) -> gst_video::VideoColorimetry {
+    let color = &video_settings.color;
+
+    let format_info = gst_video::VideoFormatInfo::from_format(video_settings.format);
+
+    if format_info.is_rgb() || format_info.is_gray() {
+        gst_video::VideoColorimetry::new(
+            gst_video::VideoColorRange::Range0_255,
+            if format_info.is_rgb() {
+                gst_video::VideoColorMatrix::Rgb
+            } else {
+                gst_video::VideoColorMatrix::Unknown
+            },
+            color.transfer,
+            color.primaries,
+        )
+    } else {
+        output_colorimetry(video_settings)
+    }
+}
+
+/// The colorimetry frames are converted to before being encoded, which encoders signal in the stream
+fn output_colorimetry(video_settings: &VideoSettings) -> gst_video::VideoColorimetry {
+    let color = &video_settings.color;
+    gst_video::VideoColorimetry::new(color.range, color.matrix, color.transfer, color.primaries)
+}
+
+/// The timestamp of a frame in a constant framerate video
+///
+/// Computed in nanoseconds so framerates that don't divide a second evenly don't drift.
+pub fn frame_pts(frame_num: u64, framerate: u64) -> gst::ClockTime {
+    gst::ClockTime::from_nseconds(
+        (frame_num as u128 * gst::ClockTime::SECOND.nseconds() as u128 / framerate as u128) as u64,
+    )
+}
+
+/// Sets the timestamp and duration of the `frame_num`th frame of a constant framerate video,
+/// reporting it to the [`Metrics`](crate::Metrics) of the settings
+///
+/// Frames from a live source only get a duration, the appsrc timestamps them as they arrive.
+pub(crate) fn set_frame_timing(
+    buffer: &mut gst::BufferRef,
+    frame_num: u64,
+    video_settings: &VideoSettings,
+) {
+    report_frame_received(frame_num, video_settings);
+
+    let pts = frame_time(frame_num, video_settings);
+    if !video_settings.live_source {
+        buffer.set_pts(pts);
+    }
+    buffer.set_duration(frame_time(frame_num + 1, video_settings) - pts);
+}
+
+/// Tells the [`Metrics`](crate::Metrics) of the settings that the `frame_num`th frame arrived,
+/// for frames that are timed without [`set_frame_timing`]
+pub(crate) fn report_frame_received(frame_num: u64, video_settings: &VideoSettings) {
+    if let Some(metrics) = &video_settings.metrics {
+        metrics.0.frame_received(frame_num, Instant::now());
+    }
+}
+
+/// When the `frame_num`th frame is shown in the video, sped up or slowed down by the settings
+pub(crate) fn frame_time(frame_num: u64, video_settings: &VideoSettings) -> gst::ClockTime {
+    scale_pts(
+        frame_pts(frame_num, video_settings.framerate),
+        video_settings,
+    )
+}
+
+/// Turns the time a frame was captured at into when it's shown in the video
+pub(crate) fn scale_pts(pts: gst::ClockTime, video_settings: &VideoSettings) -> gst::ClockTime {
+    if video_settings.speed == SpeedFactor::default() {
+        return pts;
+    }
 
-    (pipeline, appsrc, video_info)
+    gst::ClockTime::from_nseconds((pts.nseconds() as f64 / video_settings.speed.get()) as u64)
 }
@@ -0,0 +1,31 @@
+use gstreamer_video::VideoFormat;
+use image::{Bgr, Bgra, Luma, Pixel, Rgb, Rgba};
+
+/// An `image` pixel type that GStreamer can take without converting it first
+///
+/// Functions generic over the pixel type like [`start_encoding`](crate::start_encoding)
+/// set `VideoSettings::format` from this, so the two can't disagree.
+pub trait PixelFormat: Pixel<Subpixel = u8> {
+    /// The GStreamer format with the same memory layout as the pixel type
+    const VIDEO_FORMAT: VideoFormat;
+}
+
+impl PixelFormat for Rgb<u8> {
+    const VIDEO_FORMAT: VideoFormat = VideoFormat::Rgb;
+}
+
+impl PixelFormat for Rgba<u8> {
+    const VIDEO_FORMAT: VideoFormat = VideoFormat::Rgba;
+}
+
+impl PixelFormat for Bgr<u8> {
+    const VIDEO_FORMAT: VideoFormat = VideoFormat::Bgr;
+}
+
+impl PixelFormat for Bgra<u8> {
+    const VIDEO_FORMAT: VideoFormat = VideoFormat::Bgra;
+}
+
+impl PixelFormat for Luma<u8> {
+    const VIDEO_FORMAT: VideoFormat = VideoFormat::Gray8;
+}
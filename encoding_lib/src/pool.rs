@@ -0,0 +1,171 @@
+use std::{
+    alloc::{self, Layout},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::{Arc, Mutex},
+};
+
+use gstreamer as gst;
+
+/// The alignment of pooled frames, a page on most systems
+const FRAME_ALIGNMENT: usize = 4096;
+
+/// Recycles frame sized, page aligned allocations so high resolution encodes
+/// don't allocate and free a whole frame for every frame
+///
+/// Frames go back to the pool when dropped, including when they were turned into a
+/// `gst::Buffer` and the pipeline is done with it.<br>
+/// Cloning the pool gives another handle to the same frames.
+#[derive(Clone)]
+pub struct FramePool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    frame_size: usize,
+    max_free: usize,
+    free: Mutex<Vec<AlignedBytes>>,
+}
+
+impl FramePool {
+    /// Creates a pool of `frame_size` byte frames keeping at most `max_free` unused frames around
+    pub fn new(frame_size: usize, max_free: usize) -> Self {
+        FramePool {
+            inner: Arc::new(PoolInner {
+                frame_size,
+                max_free,
+                free: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Takes a frame from the pool, allocating a new one if none are free
+    ///
+    /// The contents of a recycled frame are whatever was last written to it.
+    pub fn acquire(&self) -> PooledFrame {
+        let bytes = self
+            .inner
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| AlignedBytes::new(self.inner.frame_size));
+
+        PooledFrame {
+            bytes: Some(bytes),
+            pool: self.inner.clone(),
+        }
+    }
+
+    /// Takes a frame from the pool already wrapped in a `gst::Buffer`, e.g. for a data provider
+    pub fn acquire_buffer(&self) -> gst::Buffer {
+        self.acquire().into_buffer()
+    }
+
+    /// The size in bytes of every frame in the pool
+    pub fn frame_size(&self) -> usize {
+        self.inner.frame_size
+    }
+
+    /// How many unused frames are waiting in the pool
+    pub fn free_frames(&self) -> usize {
+        self.inner.free.lock().unwrap().len()
+    }
+}
+
+/// A frame taken from a [`FramePool`], which goes back to the pool when dropped
+pub struct PooledFrame {
+    bytes: Option<AlignedBytes>,
+    pool: Arc<PoolInner>,
+}
+
+impl PooledFrame {
+    /// Wraps the frame in a `gst::Buffer` without copying it
+    ///
+    /// The frame goes back to the pool once the pipeline drops the buffer.
+    pub fn into_buffer(self) -> gst::Buffer {
+        gst::Buffer::from_mut_slice(self)
+    }
+}
+
+impl Deref for PooledFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.bytes.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledFrame {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.bytes.as_mut().unwrap()
+    }
+}
+
+impl AsRef<[u8]> for PooledFrame {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for PooledFrame {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl Drop for PooledFrame {
+    fn drop(&mut self) {
+        let mut free = self.pool.free.lock().unwrap();
+        if free.len() < self.pool.max_free {
+            free.push(self.bytes.take().unwrap());
+        }
+    }
+}
+
+/// A page aligned heap allocation
+struct AlignedBytes {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// The allocation is owned and only reachable through `&self` or `&mut self`
+unsafe impl Send for AlignedBytes {}
+unsafe impl Sync for AlignedBytes {}
+
+impl AlignedBytes {
+    fn new(len: usize) -> Self {
+        let layout = Self::layout(len);
+        // Zeroed so a fresh frame never exposes uninitialized memory
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+
+        AlignedBytes {
+            ptr: NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout)),
+            len,
+        }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len.max(1), FRAME_ALIGNMENT).unwrap()
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBytes {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
@@ -0,0 +1,158 @@
+//! Ready made [`VideoSettings`] for common uses, and a registry of named presets
+//! that applications can add their own to
+//!
+//! The built-in presets other than [`webm_alpha`] use x264, so they need gst-plugins-ugly.
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use gstreamer::Caps;
+use gstreamer_video::VideoFormat;
+
+use crate::{Container, PropertyValue, RateControl, VideoSettings};
+
+/// The names of the built-in presets, in the order [`names`] lists them
+const BUILT_IN: [&str; 5] = [
+    "youtube_1080p60",
+    "discord_8mb",
+    "archival_lossless",
+    "stream_low_latency",
+    "webm_alpha",
+];
+
+/// Presets added with [`register`] or [`load_file`], by name
+static CUSTOM_PRESETS: Mutex<BTreeMap<String, VideoSettings>> = Mutex::new(BTreeMap::new());
+
+/// 1080p at 60 fps in an MP4 at YouTube's recommended bitrate, ready to upload as it's written
+///
+/// Uses the high profile with a keyframe every two seconds and the index at the start of the file.
+pub fn youtube_1080p60() -> VideoSettings {
+    let mut settings = VideoSettings::new(60, 1920, 1080);
+    settings.caps = Caps::builder("video/x-h264")
+        .field("profile", "high")
+        .build();
+    settings.rate_control = RateControl::Vbr {
+        target: 12_000,
+        max: 15_000,
+    };
+    settings.faststart = true;
+    settings.encoder_settings.extend([
+        ("key-int-max".to_owned(), 120u32.into()),
+        ("bframes".to_owned(), 2u32.into()),
+    ]);
+    settings
+}
+
+/// 720p at 30 fps at a bitrate that keeps clips up to a minute under Discord's 8MB upload limit
+///
+/// For longer clips use [`VideoSettings::for_target_size`] with the length of the clip.
+pub fn discord_8mb() -> VideoSettings {
+    let mut settings = VideoSettings::new(30, 1280, 720);
+    settings.caps = Caps::builder("video/x-h264")
+        .field("profile", "main")
+        .build();
+    settings.rate_control = RateControl::Cbr { kbps: 1_000 };
+    settings.faststart = true;
+    settings
+}
+
+/// Lossless 4:4:4 H.264 in a Matroska file, for keeping masters around
+///
+/// Change the size and framerate to the ones of the frames, the files are large.
+pub fn archival_lossless() -> VideoSettings {
+    let mut settings = VideoSettings::new(60, 1920, 1080);
+    settings.container = Container::Matroska;
+    settings.encoder_format = Some(VideoFormat::Y444);
+    settings.caps = Caps::builder("video/x-h264")
+        .field("profile", "high-4:4:4")
+        .build();
+    settings.rate_control = RateControl::Cqp(0);
+    settings.encoder_settings.insert(
+        "speed-preset".to_owned(),
+        PropertyValue::Enum("slower".to_owned()),
+    );
+    settings
+}
+
+/// 720p at 60 fps in an MPEG transport stream at a constant bitrate, tuned with
+/// [`VideoSettings::realtime`] so frames come out as soon as they go in
+pub fn stream_low_latency() -> VideoSettings {
+    let mut settings = VideoSettings::new(60, 1280, 720);
+    settings.container = Container::MpegTs;
+    settings.rate_control = RateControl::Cbr { kbps: 4_500 };
+    settings.realtime()
+}
+
+/// 1080p at 60 fps VP9 with an alpha channel in a WebM file, for overlays and stingers,
+/// see [`VideoSettings::vp9_alpha`]
+pub fn webm_alpha() -> VideoSettings {
+    let mut settings = VideoSettings::vp9_alpha(60, 1920, 1080);
+    settings.rate_control = RateControl::Crf(31);
+    settings
+}
+
+/// Gets a preset by name, presets added with [`register`] take the place of built-in ones
+pub fn get(name: &str) -> Option<VideoSettings> {
+    if let Some(settings) = CUSTOM_PRESETS.lock().unwrap().get(name) {
+        return Some(settings.clone());
+    }
+
+    match name {
+        "youtube_1080p60" => Some(youtube_1080p60()),
+        "discord_8mb" => Some(discord_8mb()),
+        "archival_lossless" => Some(archival_lossless()),
+        "stream_low_latency" => Some(stream_low_latency()),
+        "webm_alpha" => Some(webm_alpha()),
+        _ => None,
+    }
+}
+
+/// Adds a named preset that [`get`] returns, replacing any preset of the same name
+pub fn register(name: impl Into<String>, settings: VideoSettings) {
+    CUSTOM_PRESETS.lock().unwrap().insert(name.into(), settings);
+}
+
+/// The names of every preset, built-in ones first
+pub fn names() -> Vec<String> {
+    let custom = CUSTOM_PRESETS.lock().unwrap();
+    BUILT_IN
+        .iter()
+        .map(|name| name.to_string())
+        .filter(|name| !custom.contains_key(name))
+        .chain(custom.keys().cloned())
+        .collect()
+}
+
+/// Registers every preset in a JSON or TOML file, picked by its extension,
+/// returning the names of the presets it had
+///
+/// The file is a table of presets by name, each written the way
+/// [`VideoSettings::from_json`] reads them:
+/// ```toml
+/// [small_webm]
+/// width = 640
+/// height = 360
+/// encoder = "vp9enc"
+/// muxer = "webmmux"
+/// caps = "video/x-vp9"
+/// ```
+#[cfg(feature = "serde")]
+pub fn load_file(path: impl AsRef<std::path::Path>) -> anyhow::Result<Vec<String>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)?;
+
+    let presets: BTreeMap<String, VideoSettings> =
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            Some("toml") => toml::from_str(&contents)?,
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "Can't tell the format of {}, use .json or .toml",
+                    path.display()
+                )))
+            }
+        };
+
+    let names = presets.keys().cloned().collect();
+    CUSTOM_PRESETS.lock().unwrap().extend(presets);
+    Ok(names)
+}
@@ -0,0 +1,122 @@
+use std::{path::Path, time::Duration};
+
+use gst::{glib, prelude::*, Caps, ClockTime};
+use gst_pbutils::{
+    prelude::*, DiscovererAudioInfo, DiscovererContainerInfo, DiscovererStreamInfo,
+    DiscovererVideoInfo,
+};
+use gstreamer as gst;
+use gstreamer_pbutils as gst_pbutils;
+
+use crate::pipeline::init_encoder;
+
+/// What [`probe`] found out about a media file
+#[derive(Debug, Clone)]
+pub struct MediaInfo {
+    /// The length of the file, if it's known
+    pub duration: Option<Duration>,
+    /// Whether the file can be seeked in
+    pub seekable: bool,
+    /// A human readable name of the container, e.g. `Quicktime`
+    pub container: Option<String>,
+    pub video_streams: Vec<VideoStreamInfo>,
+    pub audio_streams: Vec<AudioStreamInfo>,
+}
+
+/// A video stream of a media file
+#[derive(Debug, Clone)]
+pub struct VideoStreamInfo {
+    /// A human readable name of the codec, e.g. `H.264 (Constrained Baseline Profile)`
+    pub codec: Option<String>,
+    /// The caps of the encoded stream
+    pub caps: Option<Caps>,
+    pub width: u32,
+    pub height: u32,
+    /// The framerate as a fraction, `0/1` for variable framerates
+    pub framerate: (i32, i32),
+    /// The average bitrate in bits per second, if the file says
+    pub bitrate: Option<u32>,
+}
+
+/// An audio stream of a media file
+#[derive(Debug, Clone)]
+pub struct AudioStreamInfo {
+    /// A human readable name of the codec, e.g. `MPEG-4 AAC`
+    pub codec: Option<String>,
+    /// The caps of the encoded stream
+    pub caps: Option<Caps>,
+    pub channels: u32,
+    pub sample_rate: u32,
+    /// The average bitrate in bits per second, if the file says
+    pub bitrate: Option<u32>,
+}
+
+/// Reads the streams and properties of the media file at `path` without decoding it,
+/// built on `GstDiscoverer`
+pub fn probe(path: impl AsRef<Path>) -> anyhow::Result<MediaInfo> {
+    init_encoder();
+
+    let uri = glib::filename_to_uri(path.as_ref().canonicalize()?, None)?;
+    let discoverer = gst_pbutils::Discoverer::new(ClockTime::from_seconds(10))?;
+    let info = discoverer.discover_uri(&uri)?;
+
+    let container = info
+        .stream_info()
+        .filter(|stream| stream.is::<DiscovererContainerInfo>())
+        .and_then(|stream| codec_description(&stream));
+
+    let video_streams = info
+        .video_streams()
+        .into_iter()
+        .filter_map(|stream| {
+            let codec = codec_description(&stream);
+            let caps = stream.caps();
+            let video = stream.downcast::<DiscovererVideoInfo>().ok()?;
+            let framerate = video.framerate();
+
+            Some(VideoStreamInfo {
+                codec,
+                caps,
+                width: video.width(),
+                height: video.height(),
+                framerate: (framerate.numer(), framerate.denom()),
+                bitrate: Some(video.bitrate()).filter(|bitrate| *bitrate != 0),
+            })
+        })
+        .collect();
+
+    let audio_streams = info
+        .audio_streams()
+        .into_iter()
+        .filter_map(|stream| {
+            let codec = codec_description(&stream);
+            let caps = stream.caps();
+            let audio = stream.downcast::<DiscovererAudioInfo>().ok()?;
+
+            Some(AudioStreamInfo {
+                codec,
+                caps,
+                channels: audio.channels(),
+                sample_rate: audio.sample_rate(),
+                bitrate: Some(audio.bitrate()).filter(|bitrate| *bitrate != 0),
+            })
+        })
+        .collect();
+
+    Ok(MediaInfo {
+        duration: info
+            .duration()
+            .map(|duration| Duration::from_nanos(duration.nseconds())),
+        seekable: info.is_seekable(),
+        container,
+        video_streams,
+        audio_streams,
+    })
+}
+
+fn codec_description(stream: &DiscovererStreamInfo) -> Option<String> {
+    let caps = stream.caps()?;
+    gst_pbutils::pb_utils_get_codec_description(&caps)
+        .ok()
+        .map(|description| description.to_string())
+}
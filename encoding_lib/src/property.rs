@@ -0,0 +1,182 @@
+use gst::{glib, prelude::*};
+use gstreamer as gst;
+
+/// The value of an element property, as set through `encoder_settings`, `muxer_settings` or an
+/// [`ElementSpec`](crate::ElementSpec)
+///
+/// Numbers are converted to whatever integer or float type the property uses,
+/// failing if they don't fit.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PropertyValue {
+    /// Parsed the same way as `gst-launch` would
+    Str(String),
+    Int(i64),
+    UInt(u64),
+    Double(f64),
+    Bool(bool),
+    /// The name or nick of an enum value, e.g. `qual` for the `pass` of `x264enc`
+    Enum(String),
+    Fraction(i32, i32),
+}
+
+impl From<&str> for PropertyValue {
+    fn from(value: &str) -> Self {
+        PropertyValue::Str(value.to_owned())
+    }
+}
+
+impl From<String> for PropertyValue {
+    fn from(value: String) -> Self {
+        PropertyValue::Str(value)
+    }
+}
+
+impl From<i32> for PropertyValue {
+    fn from(value: i32) -> Self {
+        PropertyValue::Int(value as i64)
+    }
+}
+
+impl From<i64> for PropertyValue {
+    fn from(value: i64) -> Self {
+        PropertyValue::Int(value)
+    }
+}
+
+impl From<u32> for PropertyValue {
+    fn from(value: u32) -> Self {
+        PropertyValue::UInt(value as u64)
+    }
+}
+
+impl From<u64> for PropertyValue {
+    fn from(value: u64) -> Self {
+        PropertyValue::UInt(value)
+    }
+}
+
+impl From<f64> for PropertyValue {
+    fn from(value: f64) -> Self {
+        PropertyValue::Double(value)
+    }
+}
+
+impl From<bool> for PropertyValue {
+    fn from(value: bool) -> Self {
+        PropertyValue::Bool(value)
+    }
+}
+
+impl PropertyValue {
+    /// Converts the value into the type of `pspec`
+    fn to_value(&self, pspec: &glib::ParamSpec) -> anyhow::Result<glib::Value> {
+        let value_type = pspec.value_type();
+        let mismatch = || {
+            anyhow::Error::msg(format!(
+                "{self:?} can't be used for {} which is a {value_type}",
+                pspec.name()
+            ))
+        };
+
+        let value = match self {
+            PropertyValue::Str(value) | PropertyValue::Enum(value) => {
+                glib::Value::deserialize(value, value_type).map_err(|_| mismatch())?
+            }
+            PropertyValue::Bool(value) if value_type == glib::Type::BOOL => value.to_value(),
+            PropertyValue::Fraction(num, den) if value_type == gst::Fraction::static_type() => {
+                gst::Fraction::new(*num, *den).to_value()
+            }
+            PropertyValue::Double(value) if value_type == glib::Type::F64 => value.to_value(),
+            PropertyValue::Double(value) if value_type == glib::Type::F32 => {
+                (*value as f32).to_value()
+            }
+            PropertyValue::Int(value) => {
+                int_value(*value as i128, value_type).ok_or_else(mismatch)?
+            }
+            PropertyValue::UInt(value) => {
+                int_value(*value as i128, value_type).ok_or_else(mismatch)?
+            }
+            _ => return Err(mismatch()),
+        };
+
+        Ok(value)
+    }
+}
+
+/// Converts an integer to whichever number type a property uses, if it fits
+fn int_value(value: i128, value_type: glib::Type) -> Option<glib::Value> {
+    let value = match value_type {
+        glib::Type::I32 => i32::try_from(value).ok()?.to_value(),
+        glib::Type::U32 => u32::try_from(value).ok()?.to_value(),
+        glib::Type::I64 => i64::try_from(value).ok()?.to_value(),
+        glib::Type::U64 => u64::try_from(value).ok()?.to_value(),
+        glib::Type::F64 => (value as f64).to_value(),
+        glib::Type::F32 => (value as f32).to_value(),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Sets every property on `element`, checking the names against the properties it actually has
+pub(crate) fn set_properties<'a>(
+    element: &gst::Element,
+    properties: impl IntoIterator<Item = (&'a String, &'a PropertyValue)>,
+) -> anyhow::Result<()> {
+    for (name, value) in properties {
+        let pspec = element.find_property(name).ok_or_else(|| {
+            let mut message = format!(
+                "{} has no property called {name}",
+                element.factory().map_or_else(
+                    || element.name().to_string(),
+                    |factory| factory.name().to_string()
+                )
+            );
+
+            if let Some(suggestion) = closest_property(element, name) {
+                message.push_str(&format!(", did you mean {suggestion}?"));
+            }
+
+            anyhow::Error::msg(message)
+        })?;
+
+        element.try_set_property_from_value(name, &value.to_value(&pspec)?)?;
+    }
+
+    Ok(())
+}
+
+/// Finds the property of `element` with the name closest to `name`, if any is close enough to be a typo
+fn closest_property(element: &gst::Element, name: &str) -> Option<String> {
+    element
+        .list_properties()
+        .iter()
+        .map(|pspec| (edit_distance(name, pspec.name()), pspec.name().to_owned()))
+        .filter(|(distance, _)| *distance <= 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// The number of single character edits needed to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+
+        for (j, b_char) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if a_char == *b_char {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(current)
+            };
+            previous = current;
+        }
+    }
+
+    row[b.len()]
+}
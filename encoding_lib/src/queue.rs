@@ -0,0 +1,402 @@
+use std::{
+    any::Any,
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::{resolve_output_path, with_encoder, Decoder, EncodeStats, FrameSource, VideoSettings};
+
+/// Identifies a job submitted to an [`EncodeQueue`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JobId(pub u64);
+
+/// An offline encode to run on an [`EncodeQueue`]
+#[derive(Debug, Clone)]
+pub struct EncodeJob {
+    /// Where the video is written
+    pub output_path: String,
+    /// How the video is encoded
+    pub video_settings: VideoSettings,
+    /// The frames to encode, a [`FrameSource::File`] transcodes the file
+    pub input: FrameSource,
+    /// Jobs with a higher priority are started first, 0 by default
+    pub priority: i32,
+}
+
+impl EncodeJob {
+    pub fn new(output_path: &str, video_settings: VideoSettings, input: FrameSource) -> Self {
+        EncodeJob {
+            output_path: output_path.to_owned(),
+            video_settings,
+            input,
+            priority: 0,
+        }
+    }
+
+    /// Starts the job before any queued job with a lower priority
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Limits the encoder to `threads` threads, see [`VideoSettings::encoder_threads`]
+    pub fn threads(mut self, threads: u32) -> Self {
+        self.video_settings.encoder_threads = Some(threads);
+        self
+    }
+
+    /// Runs the job's threads at a nice level, see [`VideoSettings::nice`]
+    ///
+    /// A background job with a nice level of 10 or so leaves the CPU to a live recording
+    /// in the same process whenever it needs it.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.video_settings.nice = Some(nice);
+        self
+    }
+}
+
+/// Where a job of an [`EncodeQueue`] is at
+#[derive(Debug, Clone)]
+pub enum JobStatus {
+    /// Waiting for a free slot
+    Queued,
+    /// Being encoded
+    Running {
+        /// How many frames have been encoded so far
+        frames: u64,
+        /// How many frames there are, if that's known
+        total_frames: Option<u64>,
+    },
+    /// Done, with the output finalized
+    Finished(EncodeStats),
+    /// Stopped by an error, the output may be incomplete
+    Failed(String),
+    /// Stopped by [`EncodeQueue::cancel`] and its output removed
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job won't change status anymore
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Finished(_) | JobStatus::Failed(_) | JobStatus::Cancelled
+        )
+    }
+}
+
+/// What the queue knows about a job
+struct JobEntry {
+    status: JobStatus,
+    cancel: Arc<AtomicBool>,
+    frames: Arc<AtomicU64>,
+    total_frames: Arc<Mutex<Option<u64>>>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    next_id: u64,
+    pending: VecDeque<(JobId, EncodeJob)>,
+    jobs: HashMap<JobId, JobEntry>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<QueueState>,
+    /// Notified when a job is submitted, finishes or the queue shuts down
+    changed: Condvar,
+}
+
+/// Runs offline encodes in the background, at most `concurrency` at a time,
+/// e.g. as the building block of a batch render farm
+///
+/// Jobs are started by [`priority`](EncodeJob::priority), then in the order they're submitted.<br>
+/// Dropping the queue cancels every job that hasn't finished and waits for the running ones to stop,
+/// use [`join`](EncodeQueue::join) to wait for them to finish instead.
+pub struct EncodeQueue {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl EncodeQueue {
+    /// Starts a queue running up to `concurrency` jobs at once, at least one
+    pub fn new(concurrency: usize) -> Self {
+        let shared = Arc::new(Shared::default());
+
+        let workers = (0..concurrency.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || run_worker(&shared))
+            })
+            .collect();
+
+        EncodeQueue { shared, workers }
+    }
+
+    /// Adds a job to the queue, behind any job with the same or a higher priority
+    pub fn submit(&self, job: EncodeJob) -> JobId {
+        let mut state = self.shared.state.lock().unwrap();
+
+        let id = JobId(state.next_id);
+        state.next_id += 1;
+        state.jobs.insert(
+            id,
+            JobEntry {
+                status: JobStatus::Queued,
+                cancel: Arc::new(AtomicBool::new(false)),
+                frames: Arc::new(AtomicU64::new(0)),
+                total_frames: Arc::new(Mutex::new(None)),
+            },
+        );
+        state.pending.push_back((id, job));
+
+        self.shared.changed.notify_all();
+        id
+    }
+
+    /// The status of a job, `None` if it was never submitted to this queue
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        let state = self.shared.state.lock().unwrap();
+        state.jobs.get(&id).map(current_status)
+    }
+
+    /// The status of every job submitted so far, in the order they were submitted
+    pub fn jobs(&self) -> Vec<(JobId, JobStatus)> {
+        let state = self.shared.state.lock().unwrap();
+        let mut jobs = state
+            .jobs
+            .iter()
+            .map(|(id, entry)| (*id, current_status(entry)))
+            .collect::<Vec<_>>();
+        jobs.sort_by_key(|(id, _)| *id);
+        jobs
+    }
+
+    /// Whether any worker thread is still alive to run jobs,
+    /// `false` means queued jobs will never finish
+    pub fn is_running(&self) -> bool {
+        self.workers.iter().any(|worker| !worker.is_finished())
+    }
+
+    /// Stops a job, returning whether it was still queued or running
+    ///
+    /// A running job stops after the frame it's on and its output is removed.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(position) = state.pending.iter().position(|(job, _)| *job == id) {
+            state.pending.remove(position);
+            state.jobs.get_mut(&id).unwrap().status = JobStatus::Cancelled;
+            self.shared.changed.notify_all();
+            return true;
+        }
+
+        match state.jobs.get(&id) {
+            Some(entry) if !entry.status.is_done() => {
+                entry.cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Blocks till a job is done, returning how it ended
+    pub fn wait(&self, id: JobId) -> Option<JobStatus> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            match state.jobs.get(&id) {
+                Some(entry) if entry.status.is_done() => return Some(entry.status.clone()),
+                Some(_) => state = self.shared.changed.wait(state).unwrap(),
+                None => return None,
+            }
+        }
+    }
+
+    /// Waits for every job to be done, then stops the queue
+    pub fn join(mut self) -> Vec<(JobId, JobStatus)> {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutdown = true;
+            self.shared.changed.notify_all();
+        }
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+
+        self.jobs()
+    }
+}
+
+impl Drop for EncodeQueue {
+    fn drop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutdown = true;
+            for (id, _) in std::mem::take(&mut state.pending) {
+                state.jobs.get_mut(&id).unwrap().status = JobStatus::Cancelled;
+            }
+            for entry in state.jobs.values() {
+                entry.cancel.store(true, Ordering::Relaxed);
+            }
+            self.shared.changed.notify_all();
+        }
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The status of a job with the progress of running jobs filled in
+fn current_status(entry: &JobEntry) -> JobStatus {
+    match entry.status {
+        JobStatus::Running { .. } => JobStatus::Running {
+            frames: entry.frames.load(Ordering::Relaxed),
+            total_frames: *entry.total_frames.lock().unwrap(),
+        },
+        ref status => status.clone(),
+    }
+}
+
+/// Takes jobs off the queue till it's shut down and empty
+fn run_worker(shared: &Shared) {
+    loop {
+        let (id, mut job, cancel, frames, total_frames) = {
+            let mut state = shared.state.lock().unwrap();
+            let (id, job) = loop {
+                let next = state
+                    .pending
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, (id, job))| (job.priority, Reverse(*id)))
+                    .map(|(i, _)| i);
+                if let Some(next) = next {
+                    break state.pending.remove(next).unwrap();
+                }
+                if state.shutdown {
+                    return;
+                }
+                state = shared.changed.wait(state).unwrap();
+            };
+
+            let entry = state.jobs.get_mut(&id).unwrap();
+            entry.status = JobStatus::Running {
+                frames: 0,
+                total_frames: None,
+            };
+            (
+                id,
+                job,
+                entry.cancel.clone(),
+                entry.frames.clone(),
+                entry.total_frames.clone(),
+            )
+        };
+
+        // Resolved up front so a cancelled job removes the file it actually wrote
+        let resolved = resolve_output_template(&mut job);
+        let output_path = job.output_path.clone();
+        // A panicking job only fails itself, the worker moves on to the next one
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            resolved?;
+            run_job(job, &cancel, &frames, &total_frames)
+        }));
+        let status = match result {
+            _ if cancel.load(Ordering::Relaxed) => {
+                let _ = std::fs::remove_file(&output_path);
+                JobStatus::Cancelled
+            }
+            Ok(Ok(stats)) => JobStatus::Finished(stats),
+            Ok(Err(e)) => JobStatus::Failed(e.to_string()),
+            Err(payload) => JobStatus::Failed(panic_message(&*payload)),
+        };
+
+        let mut state = shared.state.lock().unwrap();
+        state.jobs.get_mut(&id).unwrap().status = status;
+        shared.changed.notify_all();
+    }
+}
+
+/// Replaces the output path of a job with the file its
+/// [`output_template`](VideoSettings::output_template) resolves to
+fn resolve_output_template(job: &mut EncodeJob) -> anyhow::Result<()> {
+    if job.video_settings.output_template {
+        job.output_path = resolve_output_path(&job.output_path)?
+            .to_string_lossy()
+            .into_owned();
+        job.video_settings.output_template = false;
+    }
+
+    Ok(())
+}
+
+/// The message a job panicked with
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+    {
+        Some(message) => format!("The encode panicked: {message}"),
+        None => String::from("The encode panicked"),
+    }
+}
+
+/// Encodes a job, counting frames as they're pushed and stopping early when cancelled
+fn run_job(
+    job: EncodeJob,
+    cancel: &AtomicBool,
+    frames: &AtomicU64,
+    total_frames: &Mutex<Option<u64>>,
+) -> anyhow::Result<EncodeStats> {
+    match job.input {
+        FrameSource::Frames(images) => {
+            *total_frames.lock().unwrap() = Some(images.len() as u64);
+            with_encoder(&job.output_path, job.video_settings, |sink| {
+                for image in &images {
+                    if cancel.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    sink.push_dynamic_frame(image)?;
+                    frames.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(())
+            })
+        }
+        FrameSource::File(path) => {
+            let mut decoder = Decoder::open(&path)?;
+            *total_frames.lock().unwrap() =
+                decoder
+                    .duration()
+                    .zip(decoder.video_info())
+                    .map(|(duration, info)| {
+                        let fps = info.fps();
+                        (duration.as_secs_f64() * fps.numer() as f64 / fps.denom() as f64).round()
+                            as u64
+                    });
+
+            with_encoder(&job.output_path, job.video_settings, |sink| {
+                for image in decoder.by_ref() {
+                    if cancel.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    sink.push_frame(&image)?;
+                    frames.fetch_add(1, Ordering::Relaxed);
+                }
+
+                match decoder.error() {
+                    Some(e) => Err(e.clone().into()),
+                    None => Ok(()),
+                }
+            })
+        }
+    }
+}
@@ -0,0 +1,297 @@
+#[cfg(feature = "image")]
+use std::ops::Deref;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use gst::{prelude::*, Caps, ClockTime, MessageView, Pipeline};
+use gst_app::{AppSink, AppSrc};
+use gst_video::VideoInfo;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use gstreamer_video as gst_video;
+#[cfg(feature = "image")]
+use image::{ImageBuffer, Pixel};
+
+#[cfg(feature = "image")]
+use crate::data_provider_impls::{frame_info, write_frame};
+#[cfg(feature = "image")]
+use crate::pipeline::acquire_frame_buffer;
+use crate::{
+    pipeline::{
+        configure_appsrc, make_encode_elements, make_filter_elements, make_muxer, make_video_info,
+        set_frame_timing, unique_pipeline,
+    },
+    VideoSettings,
+};
+
+/// Keeps the last stretch of a recording in memory so it can be saved after the fact.
+///
+/// Frames are encoded as they are pushed and kept as whole GOPs,
+/// so saving a clip only has to mux the already encoded data.
+/// Nothing is written to disk until [`ReplayBuffer::save_last`] is called.
+pub struct ReplayBuffer {
+    pipeline: Pipeline,
+    appsrc: AppSrc,
+    video_info: VideoInfo,
+    video_settings: VideoSettings,
+    ring: Arc<Mutex<ReplayRing>>,
+    frame_num: u64,
+}
+
+/// The encoded GOPs currently held by a [`ReplayBuffer`]
+struct ReplayRing {
+    length: ClockTime,
+    caps: Option<Caps>,
+    gops: VecDeque<Vec<gst::Buffer>>,
+}
+
+impl ReplayRing {
+    fn push(&mut self, buffer: gst::Buffer) {
+        if self.gops.is_empty() || !buffer.flags().contains(gst::BufferFlags::DELTA_UNIT) {
+            self.gops.push_back(Vec::new());
+        }
+        self.gops.back_mut().unwrap().push(buffer);
+
+        // Only throw away a GOP once the ones after it still cover the whole length
+        while self.gops.len() > 1
+            && self.end().saturating_sub(gop_start(&self.gops[1])) >= self.length
+        {
+            self.gops.pop_front();
+        }
+    }
+
+    fn end(&self) -> ClockTime {
+        self.gops
+            .back()
+            .and_then(|gop| gop.iter().filter_map(|buffer| buffer.pts()).max())
+            .unwrap_or(ClockTime::ZERO)
+    }
+
+    /// Gets every buffer needed to play back the last `length` of video,
+    /// starting on the keyframe at or before the start of the window
+    fn window(&self, length: ClockTime) -> Vec<gst::Buffer> {
+        let start = self.end().saturating_sub(length);
+        let first = self
+            .gops
+            .iter()
+            .rposition(|gop| gop_start(gop) <= start)
+            .unwrap_or(0);
+
+        self.gops.iter().skip(first).flatten().cloned().collect()
+    }
+}
+
+fn gop_start(gop: &[gst::Buffer]) -> ClockTime {
+    gop.first()
+        .and_then(|buffer| buffer.pts())
+        .unwrap_or(ClockTime::ZERO)
+}
+
+impl ReplayBuffer {
+    /// Starts encoding into a buffer that holds at least `length` of video.
+    ///
+    /// The muxer in `video_settings` is only used when saving.
+    pub fn new(video_settings: VideoSettings, length: Duration) -> Self {
+        let pipeline = unique_pipeline("replay pipeline");
+
+        let src = gst::ElementFactory::make("appsrc", Some("source")).unwrap();
+        let sink = gst::ElementFactory::make("appsink", Some("sink")).unwrap();
+
+        let mut elements = vec![src.clone()];
+        elements.extend(make_filter_elements(&video_settings));
+        elements.extend(make_encode_elements(&video_settings).unwrap());
+        elements.push(sink.clone());
+
+        let elements = elements.iter().collect::<Vec<_>>();
+        pipeline.add_many(&elements).unwrap();
+        gst::Element::link_many(&elements).unwrap();
+
+        let video_info = make_video_info(&video_settings);
+
+        let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+        configure_appsrc(&appsrc, &video_info, &video_settings);
+
+        let ring = Arc::new(Mutex::new(ReplayRing {
+            length: ClockTime::from_nseconds(length.as_nanos() as u64),
+            caps: None,
+            gops: VecDeque::new(),
+        }));
+
+        let appsink = sink.dynamic_cast::<AppSink>().unwrap();
+        // Frames are pushed as they're rendered, so there's no reason to wait on the clock
+        appsink.set_sync(false);
+
+        let ring_clone = ring.clone();
+        appsink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let mut ring = ring_clone.lock().unwrap();
+
+                    if ring.caps.is_none() {
+                        ring.caps = sample.caps_owned();
+                    }
+
+                    if let Some(buffer) = sample.buffer_owned() {
+                        ring.push(buffer);
+                    }
+
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        ReplayBuffer {
+            pipeline,
+            appsrc,
+            video_info,
+            video_settings,
+            ring,
+            frame_num: 0,
+        }
+    }
+
+    /// Encodes a frame into the buffer
+    #[cfg(feature = "image")]
+    pub fn push_frame<
+        Format: Pixel<Subpixel = u8> + 'static,
+        Container: Deref<Target = [Format::Subpixel]>,
+    >(
+        &mut self,
+        frame: &ImageBuffer<Format, Container>,
+    ) -> anyhow::Result<()> {
+        let frame_info = frame_info(
+            &self.appsrc,
+            &self.video_info,
+            &self.video_settings,
+            frame.width(),
+            frame.height(),
+        )?;
+        let mut buffer = acquire_frame_buffer(&self.appsrc, &frame_info)?;
+        write_frame(frame, buffer.get_mut().unwrap(), &frame_info);
+
+        self.push_buffer(buffer)
+    }
+
+    /// Encodes a frame of raw bytes into the buffer
+    ///
+    /// The bytes have to be laid out as described by the `VideoInfo` made from the settings.
+    pub fn push_raw_frame(&mut self, frame: &[u8]) -> anyhow::Result<()> {
+        if frame.len() != self.video_info.size() {
+            return Err(anyhow::Error::msg(format!(
+                "Frame is {} bytes but the video format needs {} bytes",
+                frame.len(),
+                self.video_info.size()
+            )));
+        }
+
+        self.push_buffer(gst::Buffer::from_slice(frame.to_vec()))
+    }
+
+    fn push_buffer(&mut self, mut buffer: gst::Buffer) -> anyhow::Result<()> {
+        set_frame_timing(
+            buffer.get_mut().unwrap(),
+            self.frame_num,
+            &self.video_settings,
+        );
+
+        self.frame_num += 1;
+        self.appsrc.push_buffer(buffer)?;
+
+        Ok(())
+    }
+
+    /// Writes the last `length` of buffered video to `output_path`.
+    ///
+    /// The clip always starts on a keyframe, so it may be up to one GOP longer than requested.
+    /// Frames still inside the encoder when this is called won't be included.
+    ///
+    /// Blocks until the file has been finalized.
+    pub fn save_last(&self, length: Duration, output_path: &str) -> anyhow::Result<()> {
+        let (caps, buffers) = {
+            let ring = self.ring.lock().unwrap();
+            let caps = ring.caps.clone().ok_or_else(|| {
+                anyhow::Error::msg("The replay buffer hasn't encoded any frames yet")
+            })?;
+
+            (
+                caps,
+                ring.window(ClockTime::from_nseconds(length.as_nanos() as u64)),
+            )
+        };
+
+        let pipeline = unique_pipeline("replay save pipeline");
+
+        let src = gst::ElementFactory::make("appsrc", Some("source"))?;
+        let muxer = make_muxer(&self.video_settings)?;
+        let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+
+        sink.set_property("location", output_path);
+
+        pipeline.add_many(&[&src, &muxer, &sink])?;
+        gst::Element::link_many(&[&src, &muxer, &sink])?;
+
+        let appsrc = src.dynamic_cast::<AppSrc>().unwrap();
+        appsrc.set_caps(Some(&caps));
+        appsrc.set_format(gst::Format::Time);
+
+        pipeline.set_state(gst::State::Playing)?;
+
+        // Shift everything so the clip starts at zero
+        let base = buffers
+            .first()
+            .and_then(|buffer| buffer.dts().or_else(|| buffer.pts()))
+            .unwrap_or(ClockTime::ZERO);
+
+        for buffer in buffers {
+            let mut buffer = buffer.copy();
+            {
+                let buffer = buffer.get_mut().unwrap();
+                let pts = buffer.pts().map(|pts| pts.saturating_sub(base));
+                let dts = buffer.dts().map(|dts| dts.saturating_sub(base));
+                buffer.set_pts(pts);
+                buffer.set_dts(dts);
+            }
+            appsrc.push_buffer(buffer)?;
+        }
+
+        appsrc.end_of_stream()?;
+
+        let bus = pipeline.bus().unwrap();
+
+        for msg in bus.iter_timed(gst::ClockTime::NONE) {
+            match msg.view() {
+                MessageView::Eos(_) => break,
+                MessageView::Error(e) => {
+                    pipeline.set_state(gst::State::Null)?;
+                    return Err(anyhow::Error::msg(format!(
+                        "Failed to save replay: {}",
+                        e.error()
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        pipeline.set_state(gst::State::Null)?;
+
+        Ok(())
+    }
+
+    /// Throws away everything currently buffered
+    pub fn clear(&self) {
+        self.ring.lock().unwrap().gops.clear();
+    }
+}
+
+impl Drop for ReplayBuffer {
+    fn drop(&mut self) {
+        let _ = self.appsrc.end_of_stream();
+        let _ = self.pipeline.set_state(gst::State::Null);
+    }
+}
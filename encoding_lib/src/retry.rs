@@ -0,0 +1,167 @@
+#[cfg(feature = "image")]
+use gst::prelude::*;
+#[cfg(feature = "image")]
+use gst_app::AppSrc;
+#[cfg(feature = "image")]
+use gstreamer as gst;
+#[cfg(feature = "image")]
+use gstreamer_app as gst_app;
+
+#[cfg(feature = "image")]
+use crate::{error::PipelineError, SubtitleSource, VideoSettings};
+
+/// Restarts an encode with another encoder when the first one fails to start,
+/// e.g. falling back from `nvh264enc` to `x264enc` when every hardware encoding session is busy
+///
+/// The frames pushed before the error are kept and pushed again into the new encoder,
+/// so nothing is lost as long as the error comes within `max_buffered_frames`.
+/// Later errors fail the encode as usual.
+///
+/// Only errors posted by the encoder that are likely to go away with another encoder are retried:
+/// busy or unopenable devices and libraries that fail to initialize.
+/// Encodes with [`SubtitleSource::Cues`](crate::SubtitleSource::Cues) aren't retried,
+/// as the cues already sent are gone.<br>
+/// Only used by [`encode_frames`](crate::encode_frames) and [`encode_frames_ref`](crate::encode_frames_ref).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    /// The encoders tried in order after [`VideoSettings::encoder`] fails, e.g. `x264enc`
+    ///
+    /// Encoders that aren't installed are skipped.
+    pub fallback_encoders: Vec<String>,
+    /// How many frames are kept to be pushed again, an error after that many frames isn't retried
+    pub max_buffered_frames: usize,
+}
+
+impl RetryPolicy {
+    /// Falls back to `fallback_encoders` in order, keeping the first second of 60 fps video
+    pub fn new(fallback_encoders: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        RetryPolicy {
+            fallback_encoders: fallback_encoders.into_iter().map(Into::into).collect(),
+            max_buffered_frames: 60,
+        }
+    }
+
+    pub fn max_buffered_frames(mut self, frames: usize) -> Self {
+        self.max_buffered_frames = frames;
+        self
+    }
+
+    /// Switches the settings to the next installed fallback encoder after `error`,
+    /// `false` when the error can't be retried or every encoder has been tried
+    ///
+    /// Encoder properties the fallback doesn't have are dropped.
+    #[cfg(feature = "image")]
+    pub(crate) fn fall_back(
+        &self,
+        video_settings: &mut VideoSettings,
+        error: &PipelineError,
+    ) -> bool {
+        if !is_transient(error) {
+            return false;
+        }
+
+        if matches!(video_settings.subtitles, Some(SubtitleSource::Cues(_))) {
+            println!("Not retrying, the subtitle cues already sent can't be sent again");
+            return false;
+        }
+
+        let failed = video_settings.encoder.clone();
+        // The original encoder isn't in the list, so every fallback is still left after it fails
+        let next = self
+            .fallback_encoders
+            .iter()
+            .position(|encoder| *encoder == failed)
+            .map_or(0, |tried| tried + 1);
+
+        let fallback = match self.fallback_encoders[next..]
+            .iter()
+            .find_map(|encoder| gst::ElementFactory::make(encoder, None).ok())
+        {
+            Some(fallback) => fallback,
+            None => return false,
+        };
+        let name = fallback.factory().unwrap().name().to_string();
+
+        video_settings.encoder_settings.retain(|property, _| {
+            let supported = fallback.find_property(property).is_some();
+            if !supported {
+                println!("{name} has no {property} property, leaving it out");
+            }
+            supported
+        });
+
+        println!("{failed} failed ({}), retrying with {name}", error.code);
+        video_settings.encoder = name;
+        true
+    }
+}
+
+/// Whether an error from the encoder could go away by using another encoder
+#[cfg(feature = "image")]
+fn is_transient(error: &PipelineError) -> bool {
+    let from_encoder = error
+        .source_element
+        .as_deref()
+        .is_some_and(|path| path.ends_with(":encoder"));
+
+    let transient = matches!(
+        error.code.kind::<gst::ResourceError>(),
+        Some(
+            gst::ResourceError::Busy
+                | gst::ResourceError::OpenRead
+                | gst::ResourceError::OpenWrite
+                | gst::ResourceError::OpenReadWrite
+        )
+    ) || error.code.kind::<gst::LibraryError>() == Some(gst::LibraryError::Init);
+
+    from_encoder && transient
+}
+
+/// The frames pushed into an encode so far, kept while a [`RetryPolicy`] could still push them again
+#[cfg(feature = "image")]
+pub(crate) struct PushedFrames {
+    frames: Vec<gst::Buffer>,
+    count: u64,
+    limit: usize,
+}
+
+#[cfg(feature = "image")]
+impl PushedFrames {
+    pub(crate) fn new(retry: Option<&RetryPolicy>) -> Self {
+        PushedFrames {
+            frames: Vec::new(),
+            count: 0,
+            limit: retry.map_or(0, |retry| retry.max_buffered_frames),
+        }
+    }
+
+    /// How many frames have been pushed, which is the number of the next frame
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether every pushed frame has been kept, so the encode can be restarted without losing any
+    pub(crate) fn complete(&self) -> bool {
+        self.frames.len() as u64 == self.count
+    }
+
+    /// Counts a frame about to be pushed, keeping it while under the limit
+    pub(crate) fn keep(&mut self, buffer: &gst::Buffer) {
+        self.count += 1;
+
+        if self.count as usize <= self.limit {
+            // Only a reference is kept, the frame isn't copied
+            self.frames.push(buffer.clone());
+        } else {
+            self.frames.clear();
+        }
+    }
+
+    /// Pushes the kept frames into a new encode, `false` if it stopped before taking them all
+    pub(crate) fn replay(&self, appsrc: &AppSrc) -> bool {
+        self.frames
+            .iter()
+            .all(|buffer| appsrc.push_buffer(buffer.clone()).is_ok())
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use gst::{prelude::*, ClockTime, Pipeline};
+use gstreamer as gst;
+
+/// The UUID that marks the SEI messages carrying frame metadata
+///
+/// Tools reading the metadata back should look for `user_data_unregistered` SEI messages
+/// (payload type 5) starting with these 16 bytes.
+pub const FRAME_METADATA_UUID: [u8; 16] = [
+    0x5f, 0x1d, 0x3a, 0x8e, 0x6b, 0x02, 0x4c, 0x47, 0x9e, 0x3b, 0x71, 0xa4, 0x0d, 0xc2, 0x58, 0x96,
+];
+
+/// Metadata waiting for the encoded frame with the same timestamp,
+/// which gets it written into an SEI message
+#[derive(Clone, Default)]
+pub(crate) struct FrameMetadata(Arc<Mutex<BTreeMap<ClockTime, Vec<Vec<u8>>>>>);
+
+impl FrameMetadata {
+    /// Watches the output of the pipeline's encoder for frames with metadata
+    pub(crate) fn install(&self, pipeline: &Pipeline) {
+        let pad = match pipeline
+            .by_name("encoder")
+            .and_then(|encoder| encoder.static_pad("src"))
+        {
+            Some(pad) => pad,
+            None => return,
+        };
+
+        let pending = self.0.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |pad, info| {
+            let pts = match &info.data {
+                Some(gst::PadProbeData::Buffer(buffer)) => buffer.pts(),
+                _ => None,
+            };
+            let pts = match pts {
+                Some(pts) => pts,
+                None => return gst::PadProbeReturn::Ok,
+            };
+
+            let messages = {
+                let mut pending = pending.lock().unwrap();
+                // Anything older belongs to a frame that was dropped
+                let later = pending.split_off(&pts);
+                *pending = later;
+                pending.remove(&pts)
+            };
+            let messages = match messages {
+                Some(messages) => messages,
+                None => return gst::PadProbeReturn::Ok,
+            };
+
+            let format = pad.current_caps().and_then(|caps| {
+                let structure = caps.structure(0)?;
+                if structure.name() != "video/x-h264" {
+                    return None;
+                }
+                Some(structure.get::<String>("stream-format").unwrap_or_default())
+            });
+            let length_prefixed = match format {
+                Some(format) => format == "avc" || format == "avc3",
+                None => return gst::PadProbeReturn::Ok,
+            };
+
+            if let Some(gst::PadProbeData::Buffer(ref mut buffer)) = info.data {
+                let data = buffer.map_readable().unwrap().to_vec();
+                let data = insert_sei(&data, &messages, length_prefixed);
+
+                let mut new_buffer = gst::Buffer::from_mut_slice(data);
+                buffer
+                    .copy_into(
+                        new_buffer.get_mut().unwrap(),
+                        gst::BufferCopyFlags::FLAGS
+                            | gst::BufferCopyFlags::TIMESTAMPS
+                            | gst::BufferCopyFlags::META,
+                        0,
+                        None,
+                    )
+                    .unwrap();
+                *buffer = new_buffer;
+            }
+
+            gst::PadProbeReturn::Ok
+        });
+    }
+
+    /// Queues `data` to be written into the frame shown at `pts`
+    pub(crate) fn attach(&self, pts: ClockTime, data: Vec<u8>) {
+        self.0.lock().unwrap().entry(pts).or_default().push(data);
+    }
+}
+
+/// Inserts an SEI NAL unit for each message before the first slice of an H.264 access unit
+fn insert_sei(access_unit: &[u8], messages: &[Vec<u8>], length_prefixed: bool) -> Vec<u8> {
+    let mut sei = Vec::new();
+    for message in messages {
+        let nal = sei_nal(message);
+        if length_prefixed {
+            sei.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        } else {
+            sei.extend_from_slice(&[0, 0, 0, 1]);
+        }
+        sei.extend(nal);
+    }
+
+    let position = first_slice(access_unit, length_prefixed).unwrap_or(0);
+
+    let mut data = Vec::with_capacity(access_unit.len() + sei.len());
+    data.extend_from_slice(&access_unit[..position]);
+    data.extend(sei);
+    data.extend_from_slice(&access_unit[position..]);
+    data
+}
+
+/// Finds where the first slice NAL unit starts, including its start code or length
+fn first_slice(access_unit: &[u8], length_prefixed: bool) -> Option<usize> {
+    let is_slice = |header: u8| (1..=5).contains(&(header & 0x1f));
+
+    if length_prefixed {
+        let mut position = 0;
+        while position + 4 < access_unit.len() {
+            let length =
+                u32::from_be_bytes(access_unit[position..position + 4].try_into().unwrap());
+            if is_slice(access_unit[position + 4]) {
+                return Some(position);
+            }
+            position += 4 + length as usize;
+        }
+    } else {
+        let mut position = 0;
+        while position + 3 < access_unit.len() {
+            if access_unit[position..position + 3] == [0, 0, 1] {
+                if is_slice(access_unit[position + 3]) {
+                    // Keep the leading zero of a 4 byte start code with the slice
+                    let start = if position > 0 && access_unit[position - 1] == 0 {
+                        position - 1
+                    } else {
+                        position
+                    };
+                    return Some(start);
+                }
+                position += 3;
+            } else {
+                position += 1;
+            }
+        }
+    }
+
+    None
+}
+
+/// Builds a `user_data_unregistered` SEI NAL unit carrying `message`
+fn sei_nal(message: &[u8]) -> Vec<u8> {
+    let mut payload = FRAME_METADATA_UUID.to_vec();
+    payload.extend_from_slice(message);
+
+    let mut rbsp = vec![5];
+    let mut size = payload.len();
+    while size >= 255 {
+        rbsp.push(255);
+        size -= 255;
+    }
+    rbsp.push(size as u8);
+    rbsp.extend(payload);
+    rbsp.push(0x80);
+
+    // Stop the payload from looking like a start code
+    let mut nal = vec![0x06];
+    let mut zeros = 0;
+    for byte in rbsp {
+        if zeros == 2 && byte <= 3 {
+            nal.push(3);
+            zeros = 0;
+        }
+        nal.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+
+    nal
+}
@@ -0,0 +1,132 @@
+//! Serde representations of the GStreamer types used in [`VideoSettings`](crate::VideoSettings),
+//! used through `#[serde(with = "...")]`
+
+use gst::glib;
+use gstreamer as gst;
+use gstreamer_video as gst_video;
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+/// Caps as their `gst-launch` style string, e.g. `video/x-h264, profile=(string)high`
+pub mod caps {
+    use std::str::FromStr;
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(caps: &gst::Caps, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&caps.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<gst::Caps, D::Error> {
+        let caps = String::deserialize(deserializer)?;
+        gst::Caps::from_str(&caps)
+            .map_err(|_| D::Error::custom(format!("{caps} aren't valid caps")))
+    }
+}
+
+/// Video formats as their GStreamer name, e.g. `BGRx` or `I420`
+pub mod video_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        format: &gst_video::VideoFormat,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(format.to_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<gst_video::VideoFormat, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match gst_video::VideoFormat::from_string(&name) {
+            gst_video::VideoFormat::Unknown => {
+                Err(D::Error::custom(format!("{name} isn't a video format")))
+            }
+            format => Ok(format),
+        }
+    }
+}
+
+/// Optional video formats, see [`video_format`]
+pub mod option_video_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        format: &Option<gst_video::VideoFormat>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match format {
+            Some(format) => serializer.serialize_some(format.to_str()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<gst_video::VideoFormat>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(name) => match gst_video::VideoFormat::from_string(&name) {
+                gst_video::VideoFormat::Unknown => {
+                    Err(D::Error::custom(format!("{name} isn't a video format")))
+                }
+                format => Ok(Some(format)),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// GLib enums like `VideoColorMatrix` as their nick, e.g. `bt709`
+pub mod glib_enum {
+    use glib::{prelude::*, EnumClass, EnumValue};
+
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: ToValue>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let value = value.to_value();
+        let (_, enum_value) = EnumValue::from_value(&value)
+            .ok_or_else(|| serde::ser::Error::custom("not an enum value"))?;
+        serializer.serialize_str(enum_value.nick())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: StaticType + for<'a> glib::value::FromValue<'a>,
+    {
+        let nick = String::deserialize(deserializer)?;
+        EnumClass::new(T::static_type())
+            .and_then(|class| class.to_value_by_nick(&nick))
+            .and_then(|value| value.get::<T>().ok())
+            .ok_or_else(|| D::Error::custom(format!("{nick} isn't a {}", T::static_type().name())))
+    }
+}
+
+/// Dates as ISO 8601 strings
+pub mod option_date_time {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<gst::DateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => serializer.serialize_some(
+                date.to_iso8601_string()
+                    .map_err(serde::ser::Error::custom)?
+                    .as_str(),
+            ),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<gst::DateTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|date| gst::DateTime::from_iso8601_string(&date).map_err(D::Error::custom))
+            .transpose()
+    }
+}
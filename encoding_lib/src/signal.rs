@@ -0,0 +1,32 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock,
+};
+
+/// The flag set by the handler, shared by every call to [`stop_signal`]
+static STOP_SIGNAL: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Installs a handler for Ctrl-C, and SIGTERM on Unix, returning a flag it sets
+///
+/// Pass the flag to [`EncodeHandle::run_until`](crate::EncodeHandle::run_until) or
+/// [`EncodeHandle::stop_when`](crate::EncodeHandle::stop_when) so a recording run from
+/// a terminal is finalized instead of left as a broken file when it's interrupted.<br>
+/// The handler replaces the default one, which killed the process,
+/// so the program has to exit on its own once the video is finished.
+///
+/// Only one handler can be installed, it fails if something else already installed one.
+/// Calling this again returns the same flag.
+pub fn stop_signal() -> anyhow::Result<Arc<AtomicBool>> {
+    if let Some(flag) = STOP_SIGNAL.get() {
+        return Ok(flag.clone());
+    }
+
+    let flag = Arc::new(AtomicBool::new(false));
+    let handler_flag = flag.clone();
+    ctrlc::set_handler(move || {
+        println!("Stopping, finishing the video");
+        handler_flag.store(true, Ordering::Relaxed);
+    })?;
+
+    Ok(STOP_SIGNAL.get_or_init(|| flag).clone())
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use crate::{with_encoder, EncodeStats, VideoSettings};
+
+/// Turns still images into a video, each shown for its own duration
+///
+/// Stills are stretched to the size of the video so they can be blended during crossfades.<br>
+/// Frames are generated as they're encoded, a still is only ever stored once.
+#[derive(Debug, Clone)]
+pub struct Slideshow {
+    slides: Vec<(DynamicImage, Duration)>,
+    crossfade: Option<Duration>,
+}
+
+impl Slideshow {
+    /// Shows each image for its duration, cutting straight to the next one
+    pub fn new(slides: Vec<(DynamicImage, Duration)>) -> Self {
+        Slideshow {
+            slides,
+            crossfade: None,
+        }
+    }
+
+    /// Fades between slides over `duration`, which is taken from the end of the outgoing slide
+    ///
+    /// The fade is cut short for slides shorter than it.
+    pub fn crossfade(mut self, duration: Duration) -> Self {
+        self.crossfade = Some(duration);
+        self
+    }
+
+    /// Encodes the slideshow into `output_path`
+    ///
+    /// Blocks the current thread till the encoding is done
+    pub fn encode(
+        self,
+        output_path: &str,
+        video_settings: VideoSettings,
+    ) -> anyhow::Result<EncodeStats> {
+        let (width, height) = (video_settings.width, video_settings.height);
+        let framerate = video_settings.framerate;
+        let frames_for =
+            |duration: Duration| (duration.as_secs_f64() * framerate as f64).round() as u64;
+
+        let slides = self
+            .slides
+            .into_iter()
+            .map(|(image, duration)| {
+                let image = if image.dimensions() == (width, height) {
+                    image.into_rgba8()
+                } else {
+                    image
+                        .resize_exact(width, height, FilterType::Triangle)
+                        .into_rgba8()
+                };
+                (image, frames_for(duration))
+            })
+            .collect::<Vec<_>>();
+        let fade_frames = self.crossfade.map_or(0, frames_for);
+
+        with_encoder(output_path, video_settings, |sink| {
+            let mut blended = RgbaImage::new(width, height);
+
+            for (i, (image, frames)) in slides.iter().enumerate() {
+                let next = slides.get(i + 1).map(|(next, _)| next);
+                let fade = match next {
+                    Some(_) => fade_frames.min(*frames),
+                    None => 0,
+                };
+
+                for frame in 0..*frames {
+                    let fade_frame = frame + fade;
+                    match next {
+                        Some(next) if fade_frame >= *frames => {
+                            let amount = (fade_frame - *frames + 1) as f32 / (fade + 1) as f32;
+                            blend(image, next, amount, &mut blended);
+                            sink.push_frame(&blended)?;
+                        }
+                        _ => sink.push_frame(image)?,
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Mixes `amount` of `to` into `from`, writing the result into `dest`
+fn blend(from: &RgbaImage, to: &RgbaImage, amount: f32, dest: &mut RgbaImage) {
+    for ((from, to), dest) in from.pixels().zip(to.pixels()).zip(dest.pixels_mut()) {
+        let mut pixel = [0; 4];
+        for channel in 0..4 {
+            let (from, to) = (from[channel] as f32, to[channel] as f32);
+            pixel[channel] = (from + (to - from) * amount).round() as u8;
+        }
+        *dest = Rgba(pixel);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use gst::{prelude::*, Pipeline};
+use gstreamer as gst;
+
+use crate::{
+    handle::DropCounter,
+    limits::{EncodeLimits, LimitState, StopReason},
+};
+
+/// Information about a finished encode
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodeStats {
+    /// How many frames were sent to the encoder
+    pub frames: u64,
+    /// How many frames were thrown away by the [`BackpressurePolicy`](crate::BackpressurePolicy)
+    pub dropped_frames: u64,
+    /// The length of the encoded video
+    pub duration: Duration,
+    /// How many bytes were written, the size of the file for [`OutputTarget::File`](crate::OutputTarget::File)
+    pub file_size: u64,
+    /// The average bitrate of the output in bits per second
+    pub avg_bitrate: u64,
+    /// How long the encode took
+    pub encode_wall_time: Duration,
+    /// How many seconds of video were encoded per second, above 1 is faster than realtime
+    pub realtime_factor: f64,
+    /// Which of the [`EncodeLimits`] ended the video early, `None` when it ran to the end
+    pub stop_reason: Option<StopReason>,
+}
+
+impl EncodeStats {
+    /// Fills in the bitrate and realtime factor from the other fields
+    pub(crate) fn new(
+        frames: u64,
+        dropped_frames: u64,
+        duration: Duration,
+        file_size: u64,
+        encode_wall_time: Duration,
+    ) -> Self {
+        let seconds = duration.as_secs_f64();
+        let avg_bitrate = if seconds > 0.0 {
+            (file_size as f64 * 8.0 / seconds) as u64
+        } else {
+            0
+        };
+        let realtime_factor = if encode_wall_time.is_zero() {
+            0.0
+        } else {
+            seconds / encode_wall_time.as_secs_f64()
+        };
+
+        EncodeStats {
+            frames,
+            dropped_frames,
+            duration,
+            file_size,
+            avg_bitrate,
+            encode_wall_time,
+            realtime_factor,
+            stop_reason: None,
+        }
+    }
+}
+
+/// Gathers [`EncodeStats`] from probes on a pipeline while it runs
+#[derive(Clone)]
+pub(crate) struct StatsCollector {
+    start: Instant,
+    frames: Arc<AtomicU64>,
+    /// The end of the last frame in nanoseconds
+    end: Arc<AtomicU64>,
+    bytes: Arc<AtomicU64>,
+    sink: Option<gst::Element>,
+    drop_counter: DropCounter,
+    limits: LimitState,
+}
+
+impl StatsCollector {
+    /// Starts counting the frames going into the encoder and the bytes going into the sink
+    ///
+    /// Pipelines without an element named `encoder`, like ones from a launch description,
+    /// count the frames coming out of `source_pad` instead.<br>
+    /// The stream is ended at `source_pad` once one of the `limits` is hit.
+    pub(crate) fn install(
+        pipeline: &Pipeline,
+        source_pad: &gst::Pad,
+        limits: &EncodeLimits,
+    ) -> Self {
+        let sink = pipeline
+            .by_name("sink")
+            .or_else(|| pipeline.iterate_sinks().into_iter().flatten().next());
+
+        let bytes = Arc::new(AtomicU64::new(0));
+        let collector = StatsCollector {
+            start: Instant::now(),
+            frames: Default::default(),
+            end: Default::default(),
+            bytes: bytes.clone(),
+            sink: sink.clone(),
+            drop_counter: DropCounter::install(pipeline),
+            limits: LimitState::install(limits, source_pad, bytes, file_location(sink.as_ref())),
+        };
+
+        let frame_pad = pipeline
+            .by_name("encoder")
+            .and_then(|encoder| encoder.static_pad("sink"))
+            .unwrap_or_else(|| source_pad.clone());
+        let (frames, end) = (collector.frames.clone(), collector.end.clone());
+        frame_pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &info.data {
+                frames.fetch_add(1, Ordering::Relaxed);
+                if let Some(pts) = buffer.pts() {
+                    let frame_end = pts + buffer.duration().unwrap_or_default();
+                    end.fetch_max(frame_end.nseconds(), Ordering::Relaxed);
+                }
+            }
+            gst::PadProbeReturn::Ok
+        });
+
+        if let Some(pad) = sink.and_then(|sink| sink.static_pad("sink")) {
+            let bytes = collector.bytes.clone();
+            pad.add_probe(
+                gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+                move |_, info| {
+                    let size = match &info.data {
+                        Some(gst::PadProbeData::Buffer(buffer)) => buffer.size(),
+                        Some(gst::PadProbeData::BufferList(list)) => {
+                            list.iter().map(|buffer| buffer.size()).sum()
+                        }
+                        _ => 0,
+                    };
+                    bytes.fetch_add(size as u64, Ordering::Relaxed);
+                    gst::PadProbeReturn::Ok
+                },
+            );
+        }
+
+        collector
+    }
+
+    pub(crate) fn drop_counter(&self) -> &DropCounter {
+        &self.drop_counter
+    }
+
+    pub(crate) fn limits(&self) -> &LimitState {
+        &self.limits
+    }
+
+    /// The stats so far, should be called once the pipeline has finished
+    ///
+    /// Muxers like `mp4mux` go back and rewrite the start of files,
+    /// so the size of the file is used over the bytes counted when there is one.
+    pub(crate) fn stats(&self) -> EncodeStats {
+        let file_size = file_location(self.sink.as_ref())
+            .and_then(|location| std::fs::metadata(location).ok())
+            .map(|metadata| metadata.len())
+            .unwrap_or_else(|| self.bytes.load(Ordering::Relaxed));
+
+        EncodeStats {
+            stop_reason: self.limits.reason(),
+            ..EncodeStats::new(
+                self.frames.load(Ordering::Relaxed),
+                self.drop_counter.dropped(),
+                Duration::from_nanos(self.end.load(Ordering::Relaxed)),
+                file_size,
+                self.start.elapsed(),
+            )
+        }
+    }
+}
+
+/// The file written by the sink, when it's a `filesink`
+fn file_location(sink: Option<&gst::Element>) -> Option<PathBuf> {
+    sink.filter(|sink| {
+        sink.factory()
+            .is_some_and(|factory| factory.name() == "filesink")
+    })
+    .and_then(|sink| sink.property::<Option<String>>("location"))
+    .map(PathBuf::from)
+}
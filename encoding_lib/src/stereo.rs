@@ -0,0 +1,104 @@
+use std::{
+    ops::Deref,
+    sync::mpsc::{channel, Sender},
+};
+
+use image::ImageBuffer;
+
+use crate::{
+    compositor::{Compositor, Placement},
+    ElementSpec, EncodeHandle, PipelineStage, PixelFormat, PropertyValue, VideoSettings,
+};
+
+/// How the two eyes of a stereoscopic video are packed into one frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// The left eye in the left half and the right eye in the right half
+    SideBySide,
+    /// The left eye in the top half and the right eye in the bottom half
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// The GStreamer name of the layout, as used by `multiview-mode` in caps
+    fn multiview_mode(self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "side-by-side",
+            StereoLayout::TopBottom => "top-bottom",
+        }
+    }
+}
+
+/// The frames of the left and right eye
+pub type StereoFrame<Format, Container> = (
+    ImageBuffer<Format, Container>,
+    ImageBuffer<Format, Container>,
+);
+
+/// Starts encoding a stereoscopic 3D video from pairs of frames for the left and right eye
+///
+/// The width and height of the settings are the size of the packed frame,
+/// so each eye is half the width for side by side and half the height for top bottom,
+/// and frames have to be sent at that size.<br>
+/// The layout is written into the caps, which `x264enc` turns into a frame packing SEI message
+/// and `matroskamux` into its stereo mode, so players know the video is 3D.
+///
+/// # Deadlock
+/// Joining the handle before dropping the sender will deadlock.
+pub fn start_stereo_encoding<
+    Format: PixelFormat + Send + Sync + 'static,
+    Container: Deref<Target = [Format::Subpixel]> + Send + Sync + 'static,
+    const BUFFER_SIZE: usize,
+>(
+    output_path: &str,
+    mut video_settings: VideoSettings,
+    layout: StereoLayout,
+) -> anyhow::Result<(EncodeHandle, Sender<StereoFrame<Format, Container>>)> {
+    let (width, height) = (video_settings.width, video_settings.height);
+    let (eye_width, eye_height, right_x, right_y) = match layout {
+        StereoLayout::SideBySide => (width / 2, height, width / 2, 0),
+        StereoLayout::TopBottom => (width, height / 2, 0, height / 2),
+    };
+
+    // Marks the packed frames as stereo on their way into the encoder
+    video_settings.custom_elements.insert(
+        0,
+        (
+            PipelineStage::PreEncode,
+            ElementSpec::new("capssetter").property(
+                "caps",
+                PropertyValue::Str(format!(
+                    "video/x-raw,multiview-mode={}",
+                    layout.multiview_mode()
+                )),
+            ),
+        ),
+    );
+
+    let (handle, mut senders) = Compositor::new(video_settings)
+        .source(
+            eye_width,
+            eye_height,
+            Placement::new(0, 0, eye_width, eye_height, 0),
+        )
+        .source(
+            eye_width,
+            eye_height,
+            Placement::new(right_x as i32, right_y as i32, eye_width, eye_height, 0),
+        )
+        .start::<Format, Container, BUFFER_SIZE>(output_path)?;
+
+    let right = senders.pop().unwrap();
+    let left = senders.pop().unwrap();
+
+    let (sender, recv) = channel::<StereoFrame<Format, Container>>();
+    std::thread::spawn(move || {
+        for (left_frame, right_frame) in recv {
+            if left.send(left_frame).is_err() || right.send(right_frame).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((handle, sender))
+}
@@ -0,0 +1,190 @@
+use std::{path::Path, time::Duration};
+
+use gst::{prelude::*, Caps, ClockTime, Pipeline};
+use gstreamer as gst;
+
+use crate::{
+    data_provider::wait_for_eos,
+    pipeline::{init_encoder, unique_pipeline},
+    probe,
+};
+
+/// How [`trim`] cuts a video
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimMode {
+    /// Copies the encoded frames without re-encoding them
+    ///
+    /// Fast and lossless, but the cut can only start on a keyframe,
+    /// so the output may start a little before `start`.
+    Copy,
+    /// Decodes and re-encodes the whole range so it starts and ends on exactly the right frames
+    ///
+    /// Slower and lossy, GStreamer can't splice re-encoded boundary frames into copied ones.<br>
+    /// The range is encoded with the codec of the input when the container can hold it,
+    /// otherwise with `x264enc`, or `vp9enc` for WebM files.
+    ReEncode,
+}
+
+/// Cuts the video between `start` and `end` out of `input` and writes it to `output`
+///
+/// The container is picked from the extension of `output`, `mp4`, `mov`, `mkv`, `webm` and `ts`
+/// are supported.<br>
+/// Only the video stream is kept.
+pub fn trim(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    start: Duration,
+    end: Duration,
+    mode: TrimMode,
+) -> anyhow::Result<()> {
+    if end <= start {
+        return Err(anyhow::Error::msg(
+            "The end of a trim has to be after its start",
+        ));
+    }
+
+    init_encoder();
+
+    let output = output.as_ref();
+    let extension = output
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let (muxer, fallback_encoder) = match extension.as_str() {
+        "mp4" => ("mp4mux", "x264enc"),
+        "mov" => ("qtmux", "x264enc"),
+        "mkv" => ("matroskamux", "x264enc"),
+        "webm" => ("webmmux", "vp9enc"),
+        "ts" => ("mpegtsmux", "x264enc"),
+        _ => {
+            return Err(anyhow::Error::msg(format!(
+                "Can't pick a container for {}",
+                output.display()
+            )))
+        }
+    };
+
+    let pipeline = unique_pipeline("trim pipeline");
+
+    let src = gst::ElementFactory::make("filesrc", Some("source"))?;
+    src.set_property("location", input.as_ref().to_string_lossy().as_ref());
+
+    let demux = match mode {
+        TrimMode::Copy => gst::ElementFactory::make("parsebin", Some("demux"))?,
+        TrimMode::ReEncode => gst::ElementFactory::make("decodebin", Some("demux"))?,
+    };
+
+    let muxer = gst::ElementFactory::make(muxer, Some("muxer"))?;
+    let sink = gst::ElementFactory::make("filesink", Some("sink"))?;
+    sink.set_property("location", output.to_string_lossy().as_ref());
+
+    pipeline.add_many(&[&src, &demux, &muxer, &sink])?;
+    src.link(&demux)?;
+    muxer.link(&sink)?;
+
+    // Everything between the demuxer and the muxer, only used when re-encoding
+    let encode_elements = match mode {
+        TrimMode::Copy => Vec::new(),
+        TrimMode::ReEncode => {
+            let encoder = matching_encoder(input.as_ref(), &muxer, fallback_encoder);
+            let elements = vec![
+                gst::ElementFactory::make("videoconvert", Some("convert"))?,
+                gst::ElementFactory::make(encoder, Some("encoder"))?,
+            ];
+            let element_refs = elements.iter().collect::<Vec<_>>();
+            pipeline.add_many(&element_refs)?;
+            gst::Element::link_many(&element_refs)?;
+            elements.last().unwrap().link(&muxer)?;
+            elements
+        }
+    };
+
+    let pipeline_weak = pipeline.downgrade();
+    demux.connect_pad_added(move |_, pad| {
+        let pipeline = match pipeline_weak.upgrade() {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+
+        link_stream(&pipeline, pad, encode_elements.first());
+    });
+
+    pipeline.set_state(gst::State::Paused)?;
+    pipeline.state(ClockTime::NONE).0?;
+
+    let flags = match mode {
+        TrimMode::Copy => {
+            gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT | gst::SeekFlags::SNAP_BEFORE
+        }
+        TrimMode::ReEncode => gst::SeekFlags::FLUSH | gst::SeekFlags::ACCURATE,
+    };
+    pipeline.seek(
+        1.0,
+        flags,
+        gst::SeekType::Set,
+        ClockTime::from_nseconds(start.as_nanos() as u64),
+        gst::SeekType::Set,
+        ClockTime::from_nseconds(end.as_nanos() as u64),
+    )?;
+
+    pipeline.set_state(gst::State::Playing)?;
+    wait_for_eos(&pipeline, None)?;
+
+    Ok(())
+}
+
+/// Picks the encoder for the codec of the first video stream of `input`,
+/// or `fallback` when it's unknown or `muxer` can't hold it
+fn matching_encoder<'a>(input: &Path, muxer: &gst::Element, fallback: &'a str) -> &'a str {
+    let codec = probe(input)
+        .ok()
+        .and_then(|info| info.video_streams.into_iter().next())
+        .and_then(|stream| stream.caps)
+        .and_then(|caps| caps.structure(0).map(|s| s.name().to_owned()));
+
+    let (codec, encoder) = match codec.as_deref() {
+        Some(codec @ "video/x-h264") => (codec, "x264enc"),
+        Some(codec @ "video/x-h265") => (codec, "x265enc"),
+        Some(codec @ "video/x-vp8") => (codec, "vp8enc"),
+        Some(codec @ "video/x-vp9") => (codec, "vp9enc"),
+        _ => return fallback,
+    };
+
+    let fits = muxer
+        .factory()
+        .is_some_and(|factory| factory.can_sink_any_caps(&Caps::builder(codec).build()));
+    if !fits {
+        println!(
+            "{encoder} can't be muxed by {}, using {fallback}",
+            muxer.name()
+        );
+        return fallback;
+    }
+
+    encoder
+}
+
+/// Links the first video stream of the demuxer to `convert` when re-encoding,
+/// or straight to the muxer when copying. Every other stream is thrown away.
+fn link_stream(pipeline: &Pipeline, pad: &gst::Pad, convert: Option<&gst::Element>) {
+    let is_video = pad
+        .current_caps()
+        .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("video/")))
+        .unwrap_or(false);
+
+    let sink_pad = match convert {
+        Some(convert) if is_video => convert.static_pad("sink").filter(|pad| !pad.is_linked()),
+        None if is_video => pipeline.by_name("muxer").unwrap().compatible_pad(pad, None),
+        _ => None,
+    };
+
+    let sink_pad = sink_pad.unwrap_or_else(|| {
+        let fakesink = gst::ElementFactory::make("fakesink", None).unwrap();
+        pipeline.add(&fakesink).unwrap();
+        fakesink.sync_state_with_parent().unwrap();
+        fakesink.static_pad("sink").unwrap()
+    });
+
+    pad.link(&sink_pad).unwrap();
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::{natural_cmp, with_encoder, EncodeStats, VideoSettings};
+
+/// The extensions of the files picked up from the directory
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "tga", "webp"];
+
+/// The order images that are ready at the same time are encoded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchOrder {
+    /// Sorted by file name, with numbers in names compared by value
+    /// so `frame_2.png` comes before `frame_10.png`
+    Name,
+    /// Sorted by when the files were last modified
+    Modified,
+}
+
+/// A directory that a renderer writes frames into, see [`encode_watch_folder`]
+#[derive(Debug, Clone)]
+pub struct WatchFolder {
+    /// The directory to watch, files in subdirectories are ignored
+    pub dir: PathBuf,
+    /// The order images that are ready at the same time are encoded in
+    pub order: WatchOrder,
+    /// How long a file has to go without changing before it's read,
+    /// so images aren't read while they're still being written
+    pub debounce: Duration,
+    /// The name of the file that ends the video once it appears in the directory
+    pub stop_file: String,
+}
+
+impl WatchFolder {
+    /// Watches `dir`, encoding images in name order half a second after they stop changing
+    /// until a file named `done` appears
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        WatchFolder {
+            dir: dir.into(),
+            order: WatchOrder::Name,
+            debounce: Duration::from_millis(500),
+            stop_file: "done".to_owned(),
+        }
+    }
+
+    pub fn order(mut self, order: WatchOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn stop_file(mut self, stop_file: impl Into<String>) -> Self {
+        self.stop_file = stop_file.into();
+        self
+    }
+}
+
+/// Encodes images as they're written into a directory, so a long render can be encoded
+/// while the renderer is still making frames
+///
+/// Images already in the directory are encoded first, a stop file already there is ignored.
+/// Once the stop file appears the images still waiting are encoded and the video is finished.<br>
+/// Files are only sorted against the ones that become ready at the same time,
+/// so a frame written after later frames were already encoded ends up out of order.
+pub fn encode_watch_folder(
+    output_path: &str,
+    watch: WatchFolder,
+    video_settings: VideoSettings,
+) -> anyhow::Result<EncodeStats> {
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&watch.dir, RecursiveMode::NonRecursive)?;
+
+    // Anything written before the watcher started still has to wait out the debounce,
+    // except a stop file left over from an earlier run which would end the video straight away
+    let mut pending = HashMap::new();
+    for entry in std::fs::read_dir(&watch.dir)?.flatten() {
+        if entry.file_name() == watch.stop_file.as_str() {
+            println!("Ignoring the stop file left in the directory from before");
+            continue;
+        }
+        pending.insert(entry.path(), Instant::now());
+    }
+
+    with_encoder(output_path, video_settings, |sink| {
+        let mut encoded = HashSet::new();
+        let mut stopping = false;
+
+        loop {
+            match events.recv_timeout(watch.debounce / 4) {
+                Ok(event) => {
+                    for path in event?.paths {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow::Error::msg("The directory watcher stopped"));
+                }
+            }
+
+            if pending
+                .keys()
+                .any(|path| path.file_name() == Some(watch.stop_file.as_ref()))
+            {
+                stopping = true;
+            }
+
+            // Once stopping there are no more frames coming, so everything left is ready
+            let mut ready = pending
+                .iter()
+                .filter(|(_, changed)| stopping || changed.elapsed() >= watch.debounce)
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            for path in &ready {
+                pending.remove(path);
+            }
+
+            ready.retain(|path| is_image(path) && path.is_file() && !encoded.contains(path));
+            sort_paths(&mut ready, watch.order);
+
+            for path in ready {
+                let frame = image::open(&path)?;
+                sink.push_dynamic_frame(&frame)?;
+                encoded.insert(path);
+            }
+
+            if stopping {
+                return Ok(());
+            }
+        }
+    })
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+fn sort_paths(paths: &mut [PathBuf], order: WatchOrder) {
+    match order {
+        WatchOrder::Name => {
+            paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()))
+        }
+        WatchOrder::Modified => paths.sort_by_cached_key(|path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use gst::{prelude::*, Pipeline};
+use gstreamer as gst;
+
+/// The name of the application message posted on the bus when a pipeline stalls
+pub const STALLED_MESSAGE: &str = "stream-encoder-stalled";
+
+/// Watches a pipeline for buffers that stop flowing, e.g. an element that never prerolls,
+/// which would otherwise leave the encode waiting forever
+///
+/// When nothing has reached the output for `timeout` a [`STALLED_MESSAGE`] is posted on the bus,
+/// with how long it has been stalled in seconds as its `seconds` field,
+/// which an [`EncodingController`](crate::EncodingController) reports as
+/// [`EncodeEvent::Stalled`](crate::EncodeEvent::Stalled).<br>
+/// A producer that stops sending frames stalls the pipeline too,
+/// so the timeout has to be longer than any pause in the frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Watchdog {
+    /// How long no buffers may reach the output before the pipeline counts as stalled
+    pub timeout: Duration,
+    /// Where the DOT graph of a stalled pipeline is written, named after the pipeline
+    pub graph_dir: PathBuf,
+    /// Whether to fail the encode once it stalls instead of waiting for it to recover
+    pub abort: bool,
+}
+
+impl Watchdog {
+    /// Reports stalls of `timeout`, writing graphs into the temp directory
+    pub fn new(timeout: Duration) -> Self {
+        Watchdog {
+            timeout,
+            graph_dir: std::env::temp_dir(),
+            abort: false,
+        }
+    }
+
+    pub fn graph_dir(mut self, graph_dir: impl Into<PathBuf>) -> Self {
+        self.graph_dir = graph_dir.into();
+        self
+    }
+
+    pub fn abort(mut self) -> Self {
+        self.abort = true;
+        self
+    }
+
+    /// Starts watching the buffers going through `pad` on a new thread,
+    /// which ends along with the pipeline
+    pub(crate) fn install(&self, pipeline: &Pipeline, pad: &gst::Pad) {
+        let last_buffer = Arc::new(Mutex::new(None::<Instant>));
+
+        let probe_last_buffer = last_buffer.clone();
+        pad.add_probe(
+            gst::PadProbeType::BUFFER | gst::PadProbeType::BUFFER_LIST,
+            move |_, _| {
+                *probe_last_buffer.lock().unwrap() = Some(Instant::now());
+                gst::PadProbeReturn::Ok
+            },
+        );
+
+        let watchdog = self.clone();
+        let pipeline = pipeline.downgrade();
+        std::thread::spawn(move || {
+            let mut started = false;
+            let mut reported = false;
+
+            loop {
+                std::thread::sleep(watchdog.timeout / 4);
+
+                let pipeline = match pipeline.upgrade() {
+                    Some(pipeline) => pipeline,
+                    None => return,
+                };
+                // Stalls are counted from when the pipeline starts, and it's shut down once it ends
+                let (_, current, pending) = pipeline.state(gst::ClockTime::ZERO);
+                match (current, started) {
+                    (gst::State::Null, true) => return,
+                    (gst::State::Null, false) => continue,
+                    _ => {}
+                }
+
+                // Being paused on purpose, like by `EncodeHandle::pause`, isn't a stall
+                let paused = current == gst::State::Paused && pending == gst::State::VoidPending;
+                let mut last_buffer = last_buffer.lock().unwrap();
+                if !started || paused {
+                    started = true;
+                    *last_buffer = Some(Instant::now());
+                    continue;
+                }
+
+                let stalled_for = last_buffer.map(|last| last.elapsed()).unwrap_or_default();
+                if stalled_for < watchdog.timeout {
+                    reported = false;
+                    continue;
+                }
+
+                if !reported {
+                    reported = true;
+                    watchdog.report(&pipeline, stalled_for);
+                }
+            }
+        });
+    }
+
+    fn report(&self, pipeline: &Pipeline, stalled_for: Duration) {
+        let seconds = stalled_for.as_secs_f64();
+        let graph_path = self
+            .graph_dir
+            .join(format!("{}-stalled.dot", pipeline.name()));
+        let graph = pipeline.debug_to_dot_data(gst::DebugGraphDetails::all());
+
+        match std::fs::write(&graph_path, graph.as_str()) {
+            Ok(()) => println!(
+                "No buffers reached the output for {seconds:.1}s, the pipeline graph was written to {}",
+                graph_path.display()
+            ),
+            Err(e) => println!(
+                "No buffers reached the output for {seconds:.1}s, couldn't write the pipeline graph: {e}"
+            ),
+        }
+
+        let _ = pipeline.post_message(
+            gst::message::Application::builder(
+                gst::Structure::builder(STALLED_MESSAGE)
+                    .field("seconds", seconds)
+                    .build(),
+            )
+            .src(pipeline)
+            .build(),
+        );
+
+        if self.abort {
+            let _ = pipeline.post_message(
+                gst::message::Error::builder(
+                    gst::CoreError::Failed,
+                    &format!(
+                        "The pipeline stalled, no buffers reached the output for {seconds:.1}s, see {}",
+                        graph_path.display()
+                    ),
+                )
+                .src(pipeline)
+                .build(),
+            );
+        }
+    }
+}
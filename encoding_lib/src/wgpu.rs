@@ -0,0 +1,185 @@
+//! Reading frames rendered with wgpu back from the GPU and sending them to an encoder
+//!
+//! wgpu has no way to export a texture's memory, so frames take the trip through the CPU.
+
+use std::num::NonZeroU32;
+
+use ::wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Extent3d, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, Maintain, MapMode, Origin3d, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+use gstreamer_video::VideoFormat;
+#[cfg(feature = "image")]
+use image::{ImageBuffer, Pixel};
+
+use crate::FrameSink;
+
+/// Copies frames out of wgpu textures so they can be encoded
+///
+/// Owns a texture to render frames into and the buffer frames are read back through.
+/// Rows of a texture copy have to be a multiple of 256 bytes apart,
+/// the padding this adds is stripped before frames are sent.
+pub struct FrameCapturer {
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+    padded_bytes_per_row: u32,
+    texture: Texture,
+    view: TextureView,
+    buffer: Buffer,
+}
+
+impl FrameCapturer {
+    /// Creates a capturer for `width` by `height` frames of a 4 byte per pixel `format`,
+    /// like `Bgra8UnormSrgb`
+    ///
+    /// Fails for formats with any other pixel size.
+    pub fn new(
+        device: &Device,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> anyhow::Result<Self> {
+        let bytes_per_pixel = format.describe().block_size as u32;
+        if bytes_per_pixel != 4 {
+            return Err(anyhow::Error::msg(format!(
+                "Only 4 byte per pixel formats can be captured, {format:?} has {bytes_per_pixel}"
+            )));
+        }
+
+        let unpadded_bytes_per_row = width * bytes_per_pixel;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("capture texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT,
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("capture readback buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Ok(FrameCapturer {
+            width,
+            height,
+            format,
+            padded_bytes_per_row,
+            texture,
+            view,
+            buffer,
+        })
+    }
+
+    /// The texture owned by the capturer, which can be passed to [`capture`](Self::capture)
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// A view of [`texture`](Self::texture) to render frames into
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// The `VideoFormat` to put in the `VideoSettings` of the encoder the frames are sent to
+    pub fn video_format(&self) -> Option<VideoFormat> {
+        match self.format {
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => Some(VideoFormat::Bgra),
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => Some(VideoFormat::Rgba),
+            _ => None,
+        }
+    }
+
+    /// Records copying the top left of `texture` into the readback buffer,
+    /// which happens once the commands of `encoder` are submitted
+    ///
+    /// The texture needs `COPY_SRC` usage and has to be at least as big as the capturer.
+    pub fn capture(&self, encoder: &mut CommandEncoder, texture: &Texture) {
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                aspect: TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+            },
+            ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(self.height),
+                },
+            },
+            Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Waits for the last captured frame to be copied, then sends it to `sink`
+    ///
+    /// The commands recorded by [`capture`](Self::capture) have to be submitted first.
+    pub async fn submit_to<Frame: CapturedFrame>(
+        &self,
+        device: &Device,
+        sink: &mut impl FrameSink<Frame>,
+    ) -> anyhow::Result<()> {
+        let slice = self.buffer.slice(..);
+        let mapping = slice.map_async(MapMode::Read);
+        device.poll(Maintain::Wait);
+        mapping.await?;
+
+        let bytes = {
+            let data = slice.get_mapped_range();
+            let row_size = (self.width * 4) as usize;
+
+            let mut bytes = Vec::with_capacity(row_size * self.height as usize);
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                bytes.extend_from_slice(&row[..row_size]);
+            }
+            bytes
+        };
+        self.buffer.unmap();
+
+        sink.push(Frame::from_capture(self.width, self.height, bytes)?)
+    }
+}
+
+/// A frame that can be made from the tightly packed pixels read back by a [`FrameCapturer`]
+pub trait CapturedFrame: Sized {
+    fn from_capture(width: u32, height: u32, bytes: Vec<u8>) -> anyhow::Result<Self>;
+}
+
+impl CapturedFrame for Vec<u8> {
+    fn from_capture(_width: u32, _height: u32, bytes: Vec<u8>) -> anyhow::Result<Self> {
+        Ok(bytes)
+    }
+}
+
+#[cfg(feature = "image")]
+impl<Format: Pixel<Subpixel = u8> + 'static> CapturedFrame for ImageBuffer<Format, Vec<u8>> {
+    fn from_capture(width: u32, height: u32, bytes: Vec<u8>) -> anyhow::Result<Self> {
+        ImageBuffer::from_raw(width, height, bytes).ok_or_else(|| {
+            anyhow::Error::msg(format!(
+                "The captured pixels don't fit a {width}x{height} image of this pixel type"
+            ))
+        })
+    }
+}
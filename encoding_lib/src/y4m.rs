@@ -0,0 +1,187 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+
+use gstreamer_video::VideoFormat;
+
+use crate::{with_encoder, EncodeStats, VideoSettings};
+
+/// Reads the frames of a YUV4MPEG2 stream, like the ones written by `ffmpeg -f yuv4mpegpipe`
+/// or by [`Container::Y4m`](crate::Container::Y4m)
+pub struct Y4mReader<R: Read> {
+    input: BufReader<R>,
+    width: u32,
+    height: u32,
+    framerate: (u32, u32),
+    format: VideoFormat,
+}
+
+impl<R: Read> Y4mReader<R> {
+    /// Reads the stream header
+    ///
+    /// Only progressive 8-bit streams in the 420, 422, 444 and mono colorspaces are supported.
+    pub fn new(input: R) -> anyhow::Result<Self> {
+        let mut input = BufReader::new(input);
+        let header =
+            read_line(&mut input)?.ok_or_else(|| anyhow::Error::msg("The y4m stream is empty"))?;
+
+        let mut params = header.split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(anyhow::Error::msg("The stream isn't a y4m stream"));
+        }
+
+        let mut width = None;
+        let mut height = None;
+        let mut framerate = (30, 1);
+        let mut format = VideoFormat::I420;
+
+        for param in params.filter(|param| !param.is_empty()) {
+            // Split on a char, as a byte index can land inside a multi-byte char
+            let mut chars = param.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = Some(parse_number(value)?),
+                Some('H') => height = Some(parse_number(value)?),
+                Some('F') => {
+                    let (numer, denom) = value.split_once(':').ok_or_else(|| {
+                        anyhow::Error::msg(format!("{value} isn't a y4m framerate"))
+                    })?;
+                    framerate = (parse_number(numer)?, parse_number(denom)?);
+                }
+                Some('C') => format = colorspace_format(value)?,
+                Some('I') if value != "p" && value != "?" => {
+                    println!("Interlaced y4m streams are read as if they were progressive")
+                }
+                // Aspect ratio and comments don't change how frames are laid out
+                _ => {}
+            }
+        }
+
+        match (width, height) {
+            (Some(width), Some(height)) => Ok(Y4mReader {
+                input,
+                width,
+                height,
+                framerate,
+                format,
+            }),
+            _ => Err(anyhow::Error::msg("The y4m header has no size")),
+        }
+    }
+
+    /// The size of the frames
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The framerate as a fraction
+    pub fn framerate(&self) -> (u32, u32) {
+        self.framerate
+    }
+
+    /// The format the planes of each frame are in
+    pub fn format(&self) -> VideoFormat {
+        self.format
+    }
+
+    /// Changes the size, framerate and format of the settings to those of the stream
+    ///
+    /// Framerates that aren't whole numbers are rounded.
+    pub fn apply(&self, video_settings: &mut VideoSettings) {
+        let (numer, denom) = self.framerate;
+        video_settings.width = self.width;
+        video_settings.height = self.height;
+        video_settings.framerate = (numer as f64 / denom.max(1) as f64).round().max(1.0) as u64;
+        video_settings.format = self.format;
+    }
+
+    /// Reads the tightly packed planes of the next frame, `None` once the stream ends
+    pub fn read_frame(&mut self) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        let header = match read_line(&mut self.input)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if !header.starts_with("FRAME") {
+            return Err(anyhow::Error::msg(format!(
+                "Expected a y4m frame but found {header}"
+            )));
+        }
+
+        let mut planes = Vec::new();
+        for size in self.plane_sizes() {
+            let mut plane = vec![0; size];
+            self.input
+                .read_exact(&mut plane)
+                .map_err(|e| match e.kind() {
+                    ErrorKind::UnexpectedEof => anyhow::Error::msg("The y4m stream ends mid frame"),
+                    _ => e.into(),
+                })?;
+            planes.push(plane);
+        }
+
+        Ok(Some(planes))
+    }
+
+    fn plane_sizes(&self) -> Vec<usize> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let luma = width * height;
+        let chroma = match self.format {
+            VideoFormat::I420 => width.div_ceil(2) * height.div_ceil(2),
+            VideoFormat::Y42b => width.div_ceil(2) * height,
+            VideoFormat::Y444 => luma,
+            _ => return vec![luma],
+        };
+
+        vec![luma, chroma, chroma]
+    }
+}
+
+/// Encodes every frame of a y4m stream
+///
+/// The size, framerate and format of `video_settings` are replaced by those of the stream.
+pub fn encode_y4m<R: Read>(
+    output_path: &str,
+    input: R,
+    mut video_settings: VideoSettings,
+) -> anyhow::Result<EncodeStats> {
+    let mut reader = Y4mReader::new(input)?;
+    reader.apply(&mut video_settings);
+
+    with_encoder(output_path, video_settings, |sink| {
+        while let Some(planes) = reader.read_frame()? {
+            let planes = planes.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            sink.push_planes(&planes)?;
+        }
+        Ok(())
+    })
+}
+
+/// Reads a header line without its newline, `None` at the end of the stream
+fn read_line(input: &mut impl BufRead) -> anyhow::Result<Option<String>> {
+    let mut line = Vec::new();
+    if input.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+
+    Ok(Some(String::from_utf8(line)?))
+}
+
+fn parse_number(value: &str) -> anyhow::Result<u32> {
+    value
+        .parse()
+        .map_err(|_| anyhow::Error::msg(format!("{value} isn't a number in the y4m header")))
+}
+
+fn colorspace_format(colorspace: &str) -> anyhow::Result<VideoFormat> {
+    match colorspace {
+        "420" | "420jpeg" | "420mpeg2" | "420paldv" => Ok(VideoFormat::I420),
+        "422" => Ok(VideoFormat::Y42b),
+        "444" => Ok(VideoFormat::Y444),
+        "mono" => Ok(VideoFormat::Gray8),
+        _ => Err(anyhow::Error::msg(format!(
+            "The y4m colorspace {colorspace} isn't supported"
+        ))),
+    }
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "image")]
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use image::{ImageBuffer, Rgba};
+use stream_encoder::{gstreamer::prelude::*, init_encoder, start_encoding, VideoSettings};
+
+#[test]
+fn two_encodes_at_once() {
+    init_encoder();
+
+    let dir = tempfile::tempdir().unwrap();
+    let paths = [dir.path().join("first.mp4"), dir.path().join("second.mp4")];
+
+    let threads = paths
+        .iter()
+        .map(|path| {
+            let path = path.to_str().unwrap().to_owned();
+            std::thread::spawn(move || {
+                // Every message of the encode carries the name of its pipeline in its source's path
+                let pipeline_names = Arc::new(Mutex::new(HashSet::new()));
+                let message_names = pipeline_names.clone();
+                let settings = VideoSettings::new(30, 64, 64).on_message(move |msg| {
+                    if let Some(src) = msg.src() {
+                        let path = src.path_string();
+                        if let Some(pipeline) = path.split('/').nth(1) {
+                            message_names.lock().unwrap().insert(pipeline.to_owned());
+                        }
+                    }
+                });
+                let (handle, sender) =
+                    start_encoding::<Rgba<u8>, Vec<u8>, 3>(&path, settings).unwrap();
+
+                for frame in 0..30u8 {
+                    let shade = frame * 8;
+                    sender
+                        .send(ImageBuffer::from_pixel(
+                            64,
+                            64,
+                            Rgba([shade, 0, 255 - shade, 255]),
+                        ))
+                        .unwrap();
+                }
+                drop(sender);
+
+                let stats = handle.join().unwrap();
+                let names = pipeline_names.lock().unwrap().clone();
+                (stats, names)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut names = Vec::new();
+    for (thread, path) in threads.into_iter().zip(&paths) {
+        let (stats, pipeline_names) = thread.join().unwrap();
+        assert_eq!(stats.frames, 30);
+        assert!(std::fs::metadata(path).unwrap().len() > 0);
+        assert_eq!(pipeline_names.len(), 1, "{pipeline_names:?}");
+        names.extend(pipeline_names);
+    }
+
+    assert_ne!(names[0], names[1]);
+}
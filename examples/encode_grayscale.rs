@@ -0,0 +1,18 @@
+use image::{ImageBuffer, Luma};
+use stream_encoder::{encode_iter, init_encoder, VideoSettings};
+
+fn main() {
+    init_encoder();
+
+    let video_settings = VideoSettings::new(30, 256, 256).grayscale();
+
+    // A moving gradient, like a heatmap rendered by a simulation
+    let frames = (0..90u32).map(|frame| {
+        ImageBuffer::from_fn(256, 256, move |x, y| {
+            Luma([((x + y + frame * 4) % 256) as u8])
+        })
+    });
+
+    println!("Starting encoding");
+    encode_iter::<Luma<u8>, Vec<u8>, _>("./grayscale.mp4", video_settings, frames).unwrap();
+}
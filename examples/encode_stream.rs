@@ -6,7 +6,7 @@ fn main() {
     let video_settings = VideoSettings::new(30, 300, 300);
 
     println!("Starting encoding");
-    let (handle, image_sender) = start_encoding::<_, _, 3>("./test.mp4", video_settings);
+    let (handle, image_sender) = start_encoding::<_, _, 3>("./test.mp4", video_settings).unwrap();
 
     println!("Starting image sends");
     let images = std::fs::read_dir("./test_images").unwrap();
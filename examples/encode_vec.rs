@@ -23,5 +23,6 @@ fn main() {
         stream_encoder::data_provider_impls::vec_data_provider,
         None,
         (Arc::new(Mutex::new(0)), Arc::new(RwLock::new(images))),
-    );
+    )
+    .unwrap();
 }
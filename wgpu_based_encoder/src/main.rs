@@ -1,5 +1,4 @@
 use winit::{
-    dpi::PhysicalSize,
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
@@ -20,11 +19,6 @@ fn main() {
 
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let curr_size = window.inner_size();
-    window.set_inner_size(PhysicalSize {
-        width: 256 * (curr_size.width / 256),
-        height: curr_size.height,
-    });
 
     let mut state = pollster::block_on(State::new(&window));
 
@@ -1,17 +1,20 @@
-use std::{num::NonZeroU32, sync::mpsc::Sender, thread::JoinHandle, time::Instant};
+use std::{sync::mpsc::Sender, time::Instant};
 
 use cgmath::{prelude::*, Matrix4, Quaternion, Vector3};
 use image::{Bgra, ImageBuffer};
-use stream_encoder::{start_encoding, VideoSettings};
+use stream_encoder::{
+    start_encoding, wgpu::FrameCapturer, EncodeHandle, FrameSink, RateControl, ScalingMode,
+    VideoSettings,
+};
 use wgpu::{
     include_wgsl,
     util::{BufferInitDescriptor, DeviceExt},
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, BufferBindingType,
-    BufferDescriptor, BufferUsages, CompareFunction, DepthBiasState, DepthStencilState, Extent3d,
-    ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, LoadOp, Maintain, MapMode, Operations,
-    Origin3d, RenderPassDepthStencilAttachment, SamplerBindingType, ShaderStages, StencilState,
-    TextureAspect, TextureSampleType, TextureUsages, TextureViewDimension,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType, BufferUsages,
+    CompareFunction, DepthBiasState, DepthStencilState, Extent3d, ImageCopyTexture, LoadOp,
+    Operations, Origin3d, RenderPassDepthStencilAttachment, SamplerBindingType, ShaderStages,
+    StencilState, TextureAspect, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDimension,
 };
 use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
 
@@ -42,9 +45,8 @@ pub struct State {
     camera_controller: CameraController,
     depth_texture: Texture,
     frame_sender: Sender<ImageBuffer<Bgra<u8>, Vec<u8>>>,
-    frame_texture: Texture,
-    frame_buffer: Buffer,
-    frame_thread: JoinHandle<()>,
+    frame_capturer: FrameCapturer,
+    frame_thread: Option<EncodeHandle>,
     frame_time: Instant,
     frame_num: u64,
 }
@@ -128,7 +130,7 @@ impl State {
         let config = wgpu::SurfaceConfiguration {
             usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
             format: surface.get_preferred_format(&adapter).unwrap(),
-            width: 256 * (size.width / 256),
+            width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
@@ -297,19 +299,13 @@ impl State {
 
         let (frame_thread, frame_sender) = Self::init_encoder(&size);
 
-        let frame_texture = Texture::create_encoding_frame(&device, &config, Some("encoder frame"));
-
-        let frame_buffer_size =
-            (std::mem::size_of::<u32>() as u32 * 256 * (size.width / 256) * config.height)
-                as BufferAddress;
-        let frame_buffer_desc = BufferDescriptor {
-            size: frame_buffer_size,
-            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-            label: None,
-            mapped_at_creation: false,
-        };
-
-        let frame_buffer = device.create_buffer(&frame_buffer_desc);
+        let frame_capturer = FrameCapturer::new(
+            &device,
+            size.width,
+            config.height,
+            TextureFormat::Bgra8UnormSrgb,
+        )
+        .unwrap();
 
         Self {
             config,
@@ -332,9 +328,8 @@ impl State {
             instance_buffer,
             depth_texture,
             frame_sender,
-            frame_texture,
-            frame_buffer,
-            frame_thread,
+            frame_capturer,
+            frame_thread: Some(frame_thread),
             frame_time: Instant::now(),
             frame_num: 0,
         }
@@ -342,21 +337,18 @@ impl State {
 
     fn init_encoder(
         size: &PhysicalSize<u32>,
-    ) -> (JoinHandle<()>, Sender<ImageBuffer<Bgra<u8>, Vec<u8>>>) {
-        let mut video_settings = VideoSettings::new(
-            crate::FRAME_RATE as u64,
-            256 * (size.width / 256),
-            size.height,
-        );
+    ) -> (EncodeHandle, Sender<ImageBuffer<Bgra<u8>, Vec<u8>>>) {
+        // x264 needs an even size, so windows with an odd size are scaled down by a pixel
+        let mut video_settings =
+            VideoSettings::new(crate::FRAME_RATE as u64, size.width & !1, size.height & !1);
+        video_settings.scaling = ScalingMode::Letterbox;
+        video_settings.rate_control = RateControl::Crf(21);
         video_settings
             .encoder_settings
-            .insert("pass".to_owned(), "qual".to_owned());
-        video_settings
-            .encoder_settings
-            .insert("speed-preset".to_owned(), "slow".to_owned());
+            .insert("speed-preset".to_owned(), "slow".into());
 
         // We're using Bgra images, with data stored in Vecs and want a 120 frame buffer
-        start_encoding::<Bgra<u8>, Vec<u8>, 120>("./recording.mp4", video_settings)
+        start_encoding::<Bgra<u8>, Vec<u8>, 120>("./recording.mp4", video_settings).unwrap()
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -401,7 +393,7 @@ impl State {
         let mut encode_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &self.frame_texture.view,
+                view: self.frame_capturer.view(),
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -473,29 +465,8 @@ impl State {
             drop(render_pass);
         }
 
-        encoder.copy_texture_to_buffer(
-            ImageCopyTexture {
-                aspect: TextureAspect::All,
-                texture: &self.frame_texture.texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-            },
-            ImageCopyBuffer {
-                buffer: &self.frame_buffer,
-                layout: ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: NonZeroU32::new(
-                        std::mem::size_of::<u32>() as u32 * 256 * (self.config.width / 256),
-                    ),
-                    rows_per_image: NonZeroU32::new(self.config.height),
-                },
-            },
-            Extent3d {
-                width: 256 * (self.config.width / 256),
-                height: self.config.height,
-                depth_or_array_layers: 1,
-            },
-        );
+        self.frame_capturer
+            .capture(&mut encoder, self.frame_capturer.texture());
 
         // On vulkan (and possibly other backends)
         // we can copy directly to the surface texture+-
@@ -503,7 +474,7 @@ impl State {
         encoder.copy_texture_to_texture(
             ImageCopyTexture {
                 aspect: TextureAspect::All,
-                texture: &self.frame_texture.texture,
+                texture: self.frame_capturer.texture(),
                 mip_level: 0,
                 origin: Origin3d::ZERO,
             },
@@ -514,7 +485,7 @@ impl State {
                 origin: Origin3d::ZERO,
             },
             Extent3d {
-                width: 256 * (self.config.width / 256),
+                width: self.config.width,
                 height: self.config.height,
                 depth_or_array_layers: 1,
             },
@@ -524,28 +495,6 @@ impl State {
         self.queue.submit(Some(encoder.finish()));
         output.present();
 
-        let buffer_slice = self.frame_buffer.slice(..);
-        let mapping = buffer_slice.map_async(MapMode::Read);
-        self.device.poll(Maintain::Wait);
-        mapping.await.unwrap();
-
-        let data = buffer_slice.get_mapped_range();
-
-        let buffer_size = std::mem::size_of::<u32>()
-            * 256
-            * (self.config.width as usize / 256)
-            * self.config.height as usize;
-
-        let mut bytes = Vec::with_capacity(buffer_size);
-        unsafe {
-            data.as_ptr()
-                .copy_to_nonoverlapping(bytes.as_mut_ptr(), buffer_size);
-            bytes.set_len(buffer_size)
-        }
-
-        let frame_sender = self.frame_sender.clone();
-        let width = 256 * (self.config.width / 256);
-        let height = self.config.height;
         let curr_time = Instant::now();
 
         // We could be drawing faster than we want to encode, so we only encode on multiples of our framerate
@@ -554,32 +503,26 @@ impl State {
         {
             self.frame_time = curr_time;
 
-            // Technically I think this could lead to a race condition
-            // if somehow from_raw took an insane amount of time
-            // and the next frame didn't take as long
-            std::thread::spawn(move || {
-                let buffer = ImageBuffer::<Bgra<u8>, _>::from_raw(width, height, bytes).unwrap();
-
-                match frame_sender.send(buffer) {
-                    Ok(_) => {}
-                    Err(_) => eprintln!("tried to encode thread after closing the window"),
-                };
-            });
+            if let Err(e) = self
+                .frame_capturer
+                .submit_to::<ImageBuffer<Bgra<u8>, Vec<u8>>>(&self.device, &mut self.frame_sender)
+                .await
+            {
+                eprintln!("couldn't encode the frame: {e}");
+            }
         }
-        drop(data);
-        drop(buffer_slice);
 
         self.frame_num += 1;
-        self.frame_buffer.unmap();
 
         Ok(())
     }
 
     pub fn close(&mut self) {
         let prev = std::mem::replace(&mut self.frame_sender, std::sync::mpsc::channel().0);
-        drop(prev);
-        let encoder_thread = std::mem::replace(&mut self.frame_thread, std::thread::spawn(|| {}));
-        encoder_thread.join().unwrap();
+        prev.finish().unwrap();
+        if let Some(encoder_thread) = self.frame_thread.take() {
+            encoder_thread.join().unwrap();
+        }
     }
 }
 
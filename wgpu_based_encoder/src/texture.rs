@@ -95,7 +95,7 @@ impl Texture {
         label: Option<&str>,
     ) -> Self {
         let size = Extent3d {
-            width: 256 * (config.width / 256),
+            width: config.width,
             height: config.height,
             depth_or_array_layers: 1,
         };
@@ -132,48 +132,4 @@ impl Texture {
             sampler,
         }
     }
-
-    pub fn create_encoding_frame(
-        device: &Device,
-        config: &SurfaceConfiguration,
-        label: Option<&str>,
-    ) -> Self {
-        let size = Extent3d {
-            width: 256 * (config.width / 256),
-            height: config.height,
-            depth_or_array_layers: 1,
-        };
-
-        let desc = TextureDescriptor {
-            label,
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Bgra8UnormSrgb,
-            usage: TextureUsages::COPY_SRC | TextureUsages::RENDER_ATTACHMENT,
-        };
-
-        let texture = device.create_texture(&desc);
-        let view = texture.create_view(&TextureViewDescriptor::default());
-
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Nearest,
-            compare: Some(CompareFunction::LessEqual),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            ..Default::default()
-        });
-
-        Self {
-            texture,
-            view,
-            sampler,
-        }
-    }
 }